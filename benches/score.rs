@@ -0,0 +1,36 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use extended_isolation_forest::{ExtensionLevel, Forest, ForestOptions};
+use rand::distributions::Uniform;
+use rand::Rng;
+
+fn make_values(n_rows: usize) -> Vec<[f64; 8]> {
+    let rng = &mut rand::thread_rng();
+    let distribution = Uniform::new(0., 1.);
+    (0..n_rows)
+        .map(|_| std::array::from_fn(|_| rng.sample(distribution)))
+        .collect()
+}
+
+fn score(c: &mut Criterion) {
+    let values = make_values(20_000);
+    let options = ForestOptions {
+        n_trees: 100,
+        sample_size: 256,
+        extension_level: ExtensionLevel::Full,
+        ..ForestOptions::default()
+    };
+    let forest = Forest::from_slice(&values, &options).unwrap();
+
+    let mut group = c.benchmark_group("score 20000 rows");
+    group.sample_size(10);
+    group.bench_function("rows", |b| {
+        b.iter(|| values.iter().map(|v| forest.score(v)).collect::<Vec<_>>())
+    });
+    group.bench_function("columnar", |b| {
+        b.iter(|| forest.score_batch_columnar(&values))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, score);
+criterion_main!(benches);
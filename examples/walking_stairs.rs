@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use eyre::Result;
 use plotters::prelude::*;
 
-use extended_isolation_forest::{Forest, ForestOptions};
+use extended_isolation_forest::{ExtensionLevel, Forest, ForestOptions};
 
 fn read_acceleration_data(
     filename: &str,
@@ -51,7 +51,9 @@ fn main() -> Result<()> {
             n_trees: 100,
             sample_size: 600,
             max_tree_depth: None,
-            extension_level: 1,
+            extension_level: ExtensionLevel::Level(1),
+            max_threads: 4,
+            ..ForestOptions::default()
         },
     )?;
 
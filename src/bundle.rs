@@ -0,0 +1,481 @@
+//! Several named forests stored together in a single artifact, e.g. one forest per
+//! product segment, and forests trained per key, e.g. per tenant.
+
+use std::collections::BTreeMap;
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::visit::NodeKind;
+use crate::{
+    Error, FeatureMap, Forest, ForestFloat, ForestOptions, NonFiniteHandling, ScoreScale,
+    TreeTransform,
+};
+
+/// Named forests together with metadata shared by all of them.
+///
+/// Each forest is stored with its fingerprint, see [`Forest::fingerprint`], which allows
+/// to identify the exact model used to compute a score and to detect corrupted artifacts
+/// using [`ModelBundle::verify`].
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "Forest<T, N>: Serialize",
+        deserialize = "Forest<T, N>: Deserialize<'de>"
+    ))
+)]
+pub struct ModelBundle<T, const N: usize> {
+    metadata: BTreeMap<String, String>,
+    models: BTreeMap<String, BundledModel<T, N>>,
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "Forest<T, N>: Serialize",
+        deserialize = "Forest<T, N>: Deserialize<'de>"
+    ))
+)]
+struct BundledModel<T, const N: usize> {
+    fingerprint: u64,
+    forest: Forest<T, N>,
+}
+
+impl<T, const N: usize> Default for ModelBundle<T, N> {
+    fn default() -> Self {
+        Self {
+            metadata: BTreeMap::new(),
+            models: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Float, const N: usize> ModelBundle<T, N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a forest under `name`, returning the forest previously stored under this name.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        forest: Forest<T, N>,
+    ) -> Option<Forest<T, N>> {
+        let model = BundledModel {
+            fingerprint: forest.fingerprint(),
+            forest,
+        };
+        self.models
+            .insert(name.into(), model)
+            .map(|model| model.forest)
+    }
+
+    /// Remove the forest stored under `name`.
+    pub fn remove(&mut self, name: &str) -> Option<Forest<T, N>> {
+        self.models.remove(name).map(|model| model.forest)
+    }
+
+    /// The forest stored under `name`.
+    pub fn get(&self, name: &str) -> Option<&Forest<T, N>> {
+        self.models.get(name).map(|model| &model.forest)
+    }
+
+    /// The fingerprint of the forest stored under `name`, as computed when it was added.
+    pub fn fingerprint(&self, name: &str) -> Option<u64> {
+        self.models.get(name).map(|model| model.fingerprint)
+    }
+
+    /// Names of the forests in ascending order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.models.keys().map(String::as_str)
+    }
+
+    /// Number of forests of the bundle.
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    /// Whether the map contains no forests.
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// Metadata shared by all forests, e.g. the version of the training pipeline.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Set a metadata entry, returning the previous value.
+    pub fn set_metadata(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        self.metadata.insert(key.into(), value.into())
+    }
+
+    /// Check that the stored fingerprints match the forests, e.g. after deserializing the
+    /// bundle. Fails with [`Error::InvalidModel`] otherwise.
+    pub fn verify(&self) -> Result<(), Error> {
+        if self
+            .models
+            .values()
+            .all(|model| model.forest.fingerprint() == model.fingerprint)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidModel)
+        }
+    }
+}
+
+/// One forest per key, e.g. per tenant, trained with shared options.
+///
+/// The options are serialized together with the forests, so a deserialized map trains
+/// further forests like the original one.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "K: Serialize, Forest<T, N>: Serialize",
+        deserialize = "K: Deserialize<'de> + Ord, Forest<T, N>: Deserialize<'de>"
+    ))
+)]
+pub struct ForestMap<K, T, const N: usize> {
+    options: ForestOptions,
+    forests: BTreeMap<K, Forest<T, N>>,
+}
+
+impl<'de, K, T, const N: usize> ForestMap<K, T, N>
+where
+    K: Clone + Ord,
+    T: ForestFloat<'de> + SampleUniform + Default + Send + Sync,
+    StandardNormal: Distribution<T>,
+{
+    /// An empty map training forests with `options`.
+    pub fn new(options: ForestOptions) -> Self {
+        Self {
+            options,
+            forests: BTreeMap::new(),
+        }
+    }
+
+    /// Train one forest per key, where `keys` assigns each row of `data` to a key.
+    ///
+    /// Fails with [`Error::DimensionMismatch`] when there is not exactly one key per row,
+    /// and like [`Forest::from_slice`] when a forest can not be built from the rows of a
+    /// key.
+    pub fn fit(data: &[[T; N]], keys: &[K], options: ForestOptions) -> Result<Self, Error> {
+        if keys.len() != data.len() {
+            return Err(Error::DimensionMismatch {
+                expected: data.len(),
+                actual: keys.len(),
+            });
+        }
+        let mut rows: BTreeMap<&K, Vec<[T; N]>> = BTreeMap::new();
+        for (values, key) in data.iter().zip(keys) {
+            rows.entry(key).or_default().push(*values);
+        }
+        let mut map = Self::new(options);
+        for (key, data) in rows {
+            map.train(key.clone(), &data)?;
+        }
+        Ok(map)
+    }
+
+    /// Train a forest for `key` with the shared options, returning the forest previously
+    /// stored for this key.
+    pub fn train(&mut self, key: K, data: &[[T; N]]) -> Result<Option<Forest<T, N>>, Error> {
+        let forest = Forest::from_slice(data, &self.options)?;
+        Ok(self.forests.insert(key, forest))
+    }
+
+    /// Compute the anomaly score of an item with the forest of `key`, `None` if there is no
+    /// forest for the key.
+    pub fn score(&self, key: &K, values: &[T; N]) -> Option<f64> {
+        self.forests.get(key).map(|forest| forest.score(values))
+    }
+
+    /// Add a forest trained elsewhere, returning the forest previously stored for `key`.
+    pub fn insert(&mut self, key: K, forest: Forest<T, N>) -> Option<Forest<T, N>> {
+        self.forests.insert(key, forest)
+    }
+
+    /// Remove the forest of `key` and return it, `None` if there is no forest for the key.
+    pub fn remove(&mut self, key: &K) -> Option<Forest<T, N>> {
+        self.forests.remove(key)
+    }
+
+    /// The forest of `key`, `None` if there is no forest for the key.
+    pub fn get(&self, key: &K) -> Option<&Forest<T, N>> {
+        self.forests.get(key)
+    }
+
+    /// The keys in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.forests.keys()
+    }
+
+    /// Number of forests of the map.
+    pub fn len(&self) -> usize {
+        self.forests.len()
+    }
+
+    /// Whether the map contains no forests.
+    pub fn is_empty(&self) -> bool {
+        self.forests.is_empty()
+    }
+
+    /// The options used to train forests.
+    pub fn options(&self) -> &ForestOptions {
+        &self.options
+    }
+
+    /// Replace the options used to train forests. The forests already in the map are kept.
+    pub fn set_options(&mut self, options: ForestOptions) {
+        self.options = options;
+    }
+}
+
+impl<T: Float, const N: usize> Forest<T, N> {
+    /// A 64-bit FNV-1a hash of all state the scores depend on: the structure of the trees
+    /// including all hyperplanes, leaf sizes and tree transforms, the preprocessing, the
+    /// range and score statistics of the training data, the imputation values, the score
+    /// scale and the handling of non-finite values.
+    ///
+    /// Equal forests have equal fingerprints on all platforms, so the fingerprint can be
+    /// used to identify a model version.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write_u64(N as u64);
+        hash.write_u64(self.sample_size as u64);
+        hash.write_u64(self.trees.len() as u64);
+        for node in self.nodes() {
+            hash.write_u64(node.tree as u64);
+            hash.write_u64(node.depth as u64);
+            hash.write_u64(node.num_samples as u64);
+            match node.kind {
+                NodeKind::Internal { normal, intercept } => {
+                    for v in normal.iter().chain(intercept.iter()) {
+                        hash.write_value(*v);
+                    }
+                }
+                NodeKind::Leaf { id, .. } => hash.write_u64(u64::from(id)),
+            }
+        }
+        for tree in &self.trees {
+            match &tree.transform {
+                None => hash.write_u64(0),
+                Some(TreeTransform::Rotation(matrix)) => {
+                    hash.write_u64(1);
+                    hash.write_f64s(matrix);
+                }
+                Some(TreeTransform::FeatureMap {
+                    map,
+                    weights,
+                    center,
+                    bias,
+                }) => {
+                    match map {
+                        FeatureMap::Fourier { bandwidth } => {
+                            hash.write_u64(2);
+                            hash.write_f64(*bandwidth);
+                        }
+                        FeatureMap::Relu => hash.write_u64(3),
+                    }
+                    hash.write_f64s(weights);
+                    hash.write_f64s(center);
+                    hash.write_f64s(bias);
+                }
+            }
+        }
+
+        self.pipeline.write_fingerprint(&mut hash);
+        match &self.training_range {
+            None => hash.write_u64(0),
+            Some(range) => {
+                hash.write_u64(1);
+                for v in range.min.iter().chain(range.max.iter()) {
+                    hash.write_value(*v);
+                }
+            }
+        }
+        match &self.score_statistics {
+            None => hash.write_u64(0),
+            Some(statistics) => {
+                hash.write_u64(1);
+                statistics.write_fingerprint(&mut hash);
+            }
+        }
+        match &self.imputation_values {
+            None => hash.write_u64(0),
+            Some(values) => {
+                hash.write_u64(1);
+                hash.write_f64s(values);
+            }
+        }
+        hash.write_u64(match self.score_scale {
+            ScoreScale::Raw => 0,
+            ScoreScale::Percentile => 1,
+            ScoreScale::ZScore => 2,
+            ScoreScale::Linear0to100 => 3,
+        });
+        hash.write_u64(match self.non_finite_handling {
+            NonFiniteHandling::Saturate => 0,
+            NonFiniteHandling::MaxScore => 1,
+            NonFiniteHandling::Reject => 2,
+        });
+        hash.finish()
+    }
+}
+
+/// The 64-bit FNV-1a hash, which unlike the hasher of the standard library is stable across
+/// platforms and releases.
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    fn write_value<T: Float>(&mut self, value: T) {
+        self.write_f64(value.to_f64().unwrap_or(f64::NAN));
+    }
+
+    /// Write the length followed by the values, so that adjacent slices can not be confused.
+    pub fn write_f64s(&mut self, values: &[f64]) {
+        self.write_u64(values.len() as u64);
+        values.iter().for_each(|v| self.write_f64(*v));
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::{ForestMap, ModelBundle};
+    use crate::{Error, Forest, ForestOptions, NonFiniteHandling, ScoreScale};
+
+    fn make_forest(offset: f64) -> Forest<f64, 2> {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(offset, offset + 1.0);
+        let values: Vec<_> = (0..500)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        Forest::from_slice(values.as_slice(), &options).unwrap()
+    }
+
+    #[test]
+    fn bundle_forests() {
+        let mut bundle = ModelBundle::new();
+        assert!(bundle.insert("segment-a", make_forest(0.0)).is_none());
+        assert!(bundle.insert("segment-b", make_forest(10.0)).is_none());
+        bundle.set_metadata("version", "1");
+        assert_eq!(bundle.len(), 2);
+        assert_eq!(
+            bundle.names().collect::<Vec<_>>(),
+            vec!["segment-a", "segment-b"]
+        );
+
+        let a = bundle.get("segment-a").unwrap();
+        let b = bundle.get("segment-b").unwrap();
+        assert!(a.score(&[10.5, 10.5]) > b.score(&[10.5, 10.5]));
+        assert_eq!(bundle.fingerprint("segment-a"), Some(a.fingerprint()));
+        assert_ne!(a.fingerprint(), b.fingerprint());
+        let mut scaled = a.clone();
+        scaled.set_score_scale(ScoreScale::Percentile);
+        assert_ne!(scaled.fingerprint(), a.fingerprint());
+        let mut rejecting = a.clone();
+        rejecting.non_finite_handling = NonFiniteHandling::Reject;
+        assert_ne!(rejecting.fingerprint(), a.fingerprint());
+        assert!(bundle.get("segment-c").is_none());
+        assert!(bundle.verify().is_ok());
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&bundle).unwrap();
+            let bundle2: ModelBundle<f64, 2> = serde_json::from_str(&json).unwrap();
+            assert!(bundle2 == bundle);
+            assert!(bundle2.verify().is_ok());
+            assert_eq!(bundle2.metadata().get("version").unwrap(), "1");
+        }
+
+        bundle.models.get_mut("segment-b").unwrap().fingerprint ^= 1;
+        assert!(bundle.verify().is_err());
+    }
+
+    #[test]
+    fn forest_per_key() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0.0, 1.0);
+        let mut values = vec![];
+        let mut keys = vec![];
+        for tenant in [3_u32, 1, 2] {
+            let offset = tenant as f64 * 10.0;
+            values.extend((0..200).map(|_| {
+                [
+                    rng.sample(distribution) + offset,
+                    rng.sample(distribution) + offset,
+                ]
+            }));
+            keys.extend(std::iter::repeat(tenant).take(200));
+        }
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let mut map = ForestMap::fit(&values, &keys, options).unwrap();
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(map.score(&1, &[10.5, 10.5]).unwrap() < map.score(&2, &[10.5, 10.5]).unwrap());
+        assert!(map.score(&4, &[10.5, 10.5]).is_none());
+
+        assert!(map.train(4, &values[..200]).unwrap().is_none());
+        assert_eq!(map.len(), 4);
+        assert!(matches!(
+            map.train(5, &values[..10]),
+            Err(Error::InsufficientTrainingData)
+        ));
+        assert!(matches!(
+            ForestMap::fit(&values, &keys[1..], map.options().clone()),
+            Err(Error::DimensionMismatch { .. })
+        ));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&map).unwrap();
+            let map2: ForestMap<u32, f64, 2> = serde_json::from_str(&json).unwrap();
+            assert_eq!(map2.options(), map.options());
+            assert!(map2 == map);
+        }
+    }
+}
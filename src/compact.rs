@@ -0,0 +1,355 @@
+//! Compact representation of a forest as flat arrays, which is several times smaller than
+//! the nested representation when serialized and faster to parse.
+//!
+//! The nodes of each tree are stored in preorder, so the left child of an internal node
+//! directly follows it.
+//!
+//! All counts are stored as fixed-width `u32` values instead of `usize`, so a model trained
+//! on a 64-bit host can be loaded on a 32-bit target. The representation only uses plain
+//! sequences and structs, which makes it suitable for non-self-describing formats like
+//! `postcard` as well as for CBOR.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::Pipeline;
+use crate::stats::ScoreStatistics;
+use crate::{
+    assign_leaf_ids, Error, ExNode, Forest, InNode, LeafBounds, Node, NonFiniteHandling,
+    SampleSizePolicy, ScoreScale, Tree, TreeStats, TreeTransform,
+};
+
+/// A forest with the nodes of its trees flattened into arrays, see [`Forest::to_compact`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompactForest<T, const N: usize> {
+    avg_path_length_c: f64,
+    sample_size: u32,
+    sample_size_policy: SampleSizePolicy,
+    pipeline: Pipeline,
+    training_range: Option<LeafBounds<T, N>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    score_scale: ScoreScale,
+    #[cfg_attr(feature = "serde", serde(default))]
+    score_statistics: Option<ScoreStatistics>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    imputation_values: Option<Vec<f64>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    non_finite_handling: NonFiniteHandling,
+    trees: Vec<CompactTree<T>>,
+}
+
+/// The nodes of a tree in preorder.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct CompactTree<T> {
+    /// Index of the right child of each node, `0` for leaves.
+    right: Vec<u32>,
+
+    /// Number of training samples present at each node.
+    num_samples: Vec<u32>,
+
+    /// `N` coordinates of the normal vector per internal node.
+    normals: Vec<T>,
+
+    /// `N` coordinates of the intercept point per internal node.
+    intercepts: Vec<T>,
+
+    /// `2 * N` values per leaf with recorded bounds, first the smallest then the largest
+    /// values.
+    bounds: Vec<T>,
+
+    /// Rotation or random feature map of the tree, see
+    /// [`crate::ForestOptions::rotation_subset_size`] and
+    /// [`crate::ForestOptions::feature_map`].
+    #[cfg_attr(feature = "serde", serde(default, alias = "feature_map"))]
+    transform: Option<TreeTransform>,
+
+    /// Sorted indices of the training rows the tree has been built from.
+    #[cfg_attr(feature = "serde", serde(default))]
+    samples: Option<Vec<u32>>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    stats: TreeStats,
+}
+
+impl<T: Copy, const N: usize> Forest<T, N> {
+    /// Convert the forest into its compact representation.
+    ///
+    /// Fails with [`Error::CountOverflow`] when the sample size, a recorded sample index or
+    /// the number of nodes of a tree exceeds `u32::MAX`.
+    pub fn to_compact(&self) -> Result<CompactForest<T, N>, Error> {
+        Ok(CompactForest {
+            avg_path_length_c: self.avg_path_length_c,
+            sample_size: to_u32(self.sample_size)?,
+            sample_size_policy: self.sample_size_policy,
+            pipeline: self.pipeline.clone(),
+            training_range: self.training_range.clone(),
+            score_scale: self.score_scale,
+            score_statistics: self.score_statistics.clone(),
+            imputation_values: self.imputation_values.clone(),
+            non_finite_handling: self.non_finite_handling,
+            trees: self
+                .trees
+                .iter()
+                .map(compact_tree)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Restore a forest from its compact representation.
+    ///
+    /// Fails with [`Error::InvalidModel`] when the arrays of a tree are inconsistent or the
+    /// preprocessing, the imputation values or the transform of a tree do not fit `N`
+    /// dimensions.
+    pub fn from_compact(compact: CompactForest<T, N>) -> Result<Self, Error> {
+        if !compact.pipeline.is_valid::<N>()
+            || compact
+                .imputation_values
+                .as_ref()
+                .map_or(false, |values| values.len() != N)
+        {
+            return Err(Error::InvalidModel);
+        }
+        let trees = compact
+            .trees
+            .iter()
+            .map(expand_tree)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            avg_path_length_c: compact.avg_path_length_c,
+            sample_size: compact.sample_size as usize,
+            sample_size_policy: compact.sample_size_policy,
+            pipeline: compact.pipeline,
+            training_range: compact.training_range,
+            score_scale: compact.score_scale,
+            score_statistics: compact.score_statistics,
+            imputation_values: compact.imputation_values,
+            non_finite_handling: compact.non_finite_handling,
+            profiler: None,
+            trees: trees.into_boxed_slice(),
+        })
+    }
+}
+
+fn to_u32(value: usize) -> Result<u32, Error> {
+    u32::try_from(value).map_err(|_| Error::CountOverflow { value })
+}
+
+fn compact_tree<T: Copy, const N: usize>(tree: &Tree<T, N>) -> Result<CompactTree<T>, Error> {
+    let mut compact = CompactTree {
+        right: vec![],
+        num_samples: vec![],
+        normals: vec![],
+        intercepts: vec![],
+        bounds: vec![],
+        transform: tree.transform.clone(),
+        samples: tree
+            .samples
+            .as_ref()
+            .map(|samples| samples.iter().map(|idx| to_u32(*idx)).collect())
+            .transpose()?,
+        stats: tree.stats.clone(),
+    };
+    compact_node(&tree.root, &mut compact)?;
+    Ok(compact)
+}
+
+fn compact_node<T: Copy, const N: usize>(
+    node: &Node<T, N>,
+    compact: &mut CompactTree<T>,
+) -> Result<(), Error> {
+    let idx = compact.right.len();
+    compact.right.push(0);
+    match node {
+        Node::Ex(ex_node) => {
+            compact.num_samples.push(to_u32(ex_node.num_samples)?);
+            if let Some(bounds) = &ex_node.bounds {
+                compact.bounds.extend_from_slice(&bounds.min);
+                compact.bounds.extend_from_slice(&bounds.max);
+            }
+        }
+        Node::In(in_node) => {
+            compact.num_samples.push(to_u32(in_node.num_samples)?);
+            compact.normals.extend_from_slice(&in_node.n);
+            compact.intercepts.extend_from_slice(&in_node.p);
+            compact_node(&in_node.left, compact)?;
+            compact.right[idx] = to_u32(compact.right.len())?;
+            compact_node(&in_node.right, compact)?;
+        }
+    }
+    Ok(())
+}
+
+/// Position within the arrays of a compact tree while expanding it.
+#[derive(Default)]
+struct Cursor {
+    node: usize,
+    in_node: usize,
+    bounds: usize,
+}
+
+fn expand_tree<T: Copy, const N: usize>(compact: &CompactTree<T>) -> Result<Tree<T, N>, Error> {
+    if compact.num_samples.len() != compact.right.len() {
+        return Err(Error::InvalidModel);
+    }
+    let mut cursor = Cursor::default();
+    let mut root = expand_node(compact, &mut cursor)?;
+    if cursor.node != compact.right.len()
+        || cursor.in_node * N != compact.normals.len()
+        || compact.intercepts.len() != compact.normals.len()
+        || cursor.bounds != compact.bounds.len()
+    {
+        return Err(Error::InvalidModel);
+    }
+    if !compact
+        .transform
+        .as_ref()
+        .map_or(true, |transform| transform.is_valid::<N>())
+    {
+        return Err(Error::InvalidModel);
+    }
+    let n_leaves = assign_leaf_ids(&mut root, 0);
+    Ok(Tree::from_parts(
+        root,
+        n_leaves,
+        compact.transform.clone(),
+        compact
+            .samples
+            .as_ref()
+            .map(|samples| samples.iter().map(|idx| *idx as usize).collect()),
+        compact.stats.clone(),
+    ))
+}
+
+fn expand_node<T: Copy, const N: usize>(
+    compact: &CompactTree<T>,
+    cursor: &mut Cursor,
+) -> Result<Node<T, N>, Error> {
+    let idx = cursor.node;
+    let (right, num_samples) = match (compact.right.get(idx), compact.num_samples.get(idx)) {
+        (Some(right), Some(num_samples)) => (*right as usize, *num_samples as usize),
+        _ => return Err(Error::InvalidModel),
+    };
+    cursor.node += 1;
+
+    if right == 0 {
+        // leaves without samples never have bounds
+        let bounds = if num_samples > 0 && cursor.bounds < compact.bounds.len() {
+            let values = compact
+                .bounds
+                .get(cursor.bounds..cursor.bounds + 2 * N)
+                .ok_or(Error::InvalidModel)?;
+            cursor.bounds += 2 * N;
+            Some(Box::new(LeafBounds {
+                min: to_array(&values[..N])?,
+                max: to_array(&values[N..])?,
+            }))
+        } else {
+            None
+        };
+        return Ok(Node::Ex(ExNode::new(num_samples, bounds)));
+    }
+
+    let coordinates = cursor.in_node * N..(cursor.in_node + 1) * N;
+    let n = to_array(
+        compact
+            .normals
+            .get(coordinates.clone())
+            .ok_or(Error::InvalidModel)?,
+    )?;
+    let p = to_array(
+        compact
+            .intercepts
+            .get(coordinates)
+            .ok_or(Error::InvalidModel)?,
+    )?;
+    cursor.in_node += 1;
+
+    let left = expand_node(compact, cursor)?;
+    if cursor.node != right {
+        return Err(Error::InvalidModel);
+    }
+    let right = expand_node(compact, cursor)?;
+    Ok(Node::In(InNode {
+        left: Box::new(left),
+        right: Box::new(right),
+        n,
+        p,
+        num_samples,
+    }))
+}
+
+fn to_array<T: Copy, const N: usize>(values: &[T]) -> Result<[T; N], Error> {
+    values.try_into().map_err(|_| Error::InvalidModel)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datasets::uniform;
+    use crate::{Error, Forest, ForestOptions, TreeTransform};
+
+    fn make_forest(record_leaf_bounds: bool) -> Forest<f64, 2> {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 128,
+            record_leaf_bounds,
+            record_samples: record_leaf_bounds,
+            rotation_subset_size: Some(2),
+            ..ForestOptions::default()
+        };
+        Forest::from_slice(values.as_slice(), &options).unwrap()
+    }
+
+    #[test]
+    fn compact_roundtrip() {
+        for record_leaf_bounds in [false, true] {
+            let forest = make_forest(record_leaf_bounds);
+            let restored = Forest::from_compact(forest.to_compact().unwrap()).unwrap();
+            assert_eq!(restored, forest);
+        }
+
+        // a rotation not fitting the number of dimensions
+        let mut compact = make_forest(false).to_compact().unwrap();
+        compact.trees[3].transform = Some(TreeTransform::Rotation(vec![1.0, 0.0]));
+        assert!(matches!(
+            Forest::from_compact(compact),
+            Err(Error::InvalidModel)
+        ));
+        // imputation values not fitting the number of dimensions
+        let mut compact = make_forest(false).to_compact().unwrap();
+        compact.imputation_values = Some(vec![1.0]);
+        assert!(matches!(
+            Forest::from_compact(compact),
+            Err(Error::InvalidModel)
+        ));
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn compact_count_overflow() {
+        let mut forest = make_forest(false);
+        forest.sample_size = u32::MAX as usize + 1;
+        assert!(matches!(
+            forest.to_compact(),
+            Err(Error::CountOverflow { value }) if value == u32::MAX as usize + 1
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn compact_json_size() {
+        let forest = make_forest(false);
+        let nested = serde_json::to_string(&forest).unwrap();
+        let compact = serde_json::to_string(&forest.to_compact().unwrap()).unwrap();
+        assert!(compact.len() * 2 < nested.len());
+
+        let restored: super::CompactForest<f64, 2> = serde_json::from_str(&compact).unwrap();
+        assert_eq!(Forest::from_compact(restored).unwrap(), forest);
+        // transforms stored under their former field name
+        let renamed = compact.replace("\"transform\"", "\"feature_map\"");
+        let restored: super::CompactForest<f64, 2> = serde_json::from_str(&renamed).unwrap();
+        assert_eq!(Forest::from_compact(restored).unwrap(), forest);
+    }
+}
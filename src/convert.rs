@@ -0,0 +1,152 @@
+//! Training and scoring with integer or other non-float features, which are converted to
+//! the float type of the forest.
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{Error, Forest, ForestFloat, ForestOptions};
+
+/// A feature value which can be converted to the float type `T` of a forest, see
+/// [`Forest::from_features`].
+pub trait IntoFeature<T> {
+    /// The value as `T`, `None` if `T` can not represent the value exactly, e.g. integers
+    /// beyond `2^24` as `f32` or `f64` values beyond the range of `f32`.
+    fn into_feature(self) -> Option<T>;
+}
+
+macro_rules! impl_into_feature_int {
+    ($wide:ty, $to_wide:ident, $($int:ty),+) => {
+        $(
+            impl<T: Float> IntoFeature<T> for $int {
+                fn into_feature(self) -> Option<T> {
+                    let value = T::from(self)?;
+                    (value.$to_wide()? == self as $wide).then_some(value)
+                }
+            }
+        )+
+    };
+}
+
+impl_into_feature_int!(i64, to_i64, i8, i16, i32, i64, isize);
+impl_into_feature_int!(u64, to_u64, u8, u16, u32, u64, usize);
+
+macro_rules! impl_into_feature_float {
+    ($($float:ty),+) => {
+        $(
+            impl<T: Float> IntoFeature<T> for $float {
+                fn into_feature(self) -> Option<T> {
+                    let value = T::from(self)?;
+                    (self.is_nan() || value.to_f64()? == self as f64).then_some(value)
+                }
+            }
+        )+
+    };
+}
+
+impl_into_feature_float!(f32, f64);
+
+/// Convert the rows of `data`, failing with [`Error::InexactFeature`] at the first value
+/// which can not be represented.
+fn convert_rows<T, V, const N: usize>(data: &[[V; N]]) -> Result<Vec<[T; N]>, Error>
+where
+    T: Float,
+    V: IntoFeature<T> + Copy,
+{
+    data.iter()
+        .enumerate()
+        .map(|(row, values)| convert_row(values, row))
+        .collect()
+}
+
+fn convert_row<T, V, const N: usize>(values: &[V; N], row: usize) -> Result<[T; N], Error>
+where
+    T: Float,
+    V: IntoFeature<T> + Copy,
+{
+    let mut converted = [T::zero(); N];
+    for (dimension, (target, value)) in converted.iter_mut().zip(values).enumerate() {
+        *target = value
+            .into_feature()
+            .ok_or(Error::InexactFeature { row, dimension })?;
+    }
+    Ok(converted)
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Build a new forest from training data of another type, e.g. integers, which is
+    /// converted to `T`.
+    ///
+    /// Fails with [`Error::InexactFeature`] when a value can not be represented exactly by
+    /// `T`, instead of silently rounding it.
+    pub fn from_features<V>(data: &[[V; N]], options: &ForestOptions) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+        V: IntoFeature<T> + Copy,
+    {
+        Self::from_slice(&convert_rows(data)?, options)
+    }
+
+    /// Compute the anomaly score for an item of another type, e.g. integers, see
+    /// [`Forest::from_features`].
+    pub fn score_features<V>(&self, values: &[V; N]) -> Result<f64, Error>
+    where
+        V: IntoFeature<T> + Copy,
+    {
+        Ok(self.score(&convert_row(values, 0)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::IntoFeature;
+    use crate::{Error, Forest, ForestOptions};
+
+    #[test]
+    fn integer_features() {
+        assert_eq!(IntoFeature::<f32>::into_feature(-7_i32), Some(-7.0));
+        assert_eq!(
+            IntoFeature::<f32>::into_feature(1_u32 << 24),
+            Some(16777216.0)
+        );
+        assert_eq!(IntoFeature::<f32>::into_feature((1_u32 << 24) + 1), None);
+        assert_eq!(IntoFeature::<f64>::into_feature(i64::MAX), None);
+        assert_eq!(IntoFeature::<f64>::into_feature(u64::MAX), None);
+        assert_eq!(IntoFeature::<f32>::into_feature(1e300_f64), None);
+        assert_eq!(IntoFeature::<f32>::into_feature(0.5_f64), Some(0.5));
+        assert!(IntoFeature::<f32>::into_feature(f64::NAN).unwrap().is_nan());
+
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(-100, 100);
+        let values: Vec<[i32; 2]> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest: Forest<f64, 2> = Forest::from_features(&values, &options).unwrap();
+        let normal = forest.score_features(&[0_i32, 0]).unwrap();
+        let anomaly = forest.score_features(&[500_u32, 500]).unwrap();
+        assert_eq!(normal, forest.score(&[0.0, 0.0]));
+        assert!(anomaly > normal);
+
+        let mut large: Vec<[i64; 2]> = values.iter().map(|v| v.map(i64::from)).collect();
+        large[3][1] = i64::MAX - 1;
+        assert!(matches!(
+            Forest::<f64, 2>::from_features(&large, &options),
+            Err(Error::InexactFeature {
+                row: 3,
+                dimension: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+//! Synthetic datasets with planted anomalies, in the shapes used for the evaluation in the
+//! paper on extended isolation forests.
+//!
+//! Each generator returns the normal items followed by the anomalies, together with one
+//! label per item marking the anomalies. Passing a seeded random number generator makes
+//! the datasets reproducible, e.g. to compare options on the same data.
+
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+/// Items with labels marking the planted anomalies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dataset<const N: usize> {
+    pub data: Vec<[f64; N]>,
+
+    /// `true` for the anomalies, one label per item.
+    pub labels: Vec<bool>,
+}
+
+impl<const N: usize> Dataset<N> {
+    fn new(normal: Vec<[f64; N]>, anomalies: Vec<[f64; N]>) -> Self {
+        let labels = (0..normal.len() + anomalies.len())
+            .map(|i| i >= normal.len())
+            .collect();
+        let mut data = normal;
+        data.extend(anomalies);
+        Self { data, labels }
+    }
+}
+
+/// A standard normal blob at the origin, with anomalies at a distance between 4 and 6
+/// standard deviations in random directions.
+pub fn single_blob<R: Rng + ?Sized, const N: usize>(
+    n: usize,
+    n_anomalies: usize,
+    rng: &mut R,
+) -> Dataset<N> {
+    let normal = (0..n).map(|_| gaussian([0.0; N], rng)).collect();
+    let anomalies = (0..n_anomalies)
+        .map(|_| shell([0.0; N], 4.0, 6.0, rng))
+        .collect();
+    Dataset::new(normal, anomalies)
+}
+
+/// Two standard normal blobs of equal size, centered at `10` in the first and in the second
+/// dimension respectively, with anomalies at a distance between 4 and 6 standard deviations
+/// from either center.
+///
+/// Standard isolation forests assign low scores to the empty regions at `(0, 0)` and
+/// `(10, 10)` in the first two dimensions, see [`crate::ExtensionLevel`].
+pub fn double_blob<R: Rng + ?Sized, const N: usize>(
+    n: usize,
+    n_anomalies: usize,
+    rng: &mut R,
+) -> Dataset<N> {
+    let center = |i: usize| {
+        let mut center = [0.0; N];
+        if N > 0 {
+            center[(i % 2).min(N - 1)] = 10.0;
+        }
+        center
+    };
+    let normal = (0..n).map(|i| gaussian(center(i), rng)).collect();
+    let anomalies = (0..n_anomalies)
+        .map(|i| shell(center(i), 4.0, 6.0, rng))
+        .collect();
+    Dataset::new(normal, anomalies)
+}
+
+/// Items along a sine wave `y = sin(x)` for `x` in `[0, 8 pi]` with Gaussian noise of
+/// standard deviation `noise`, with anomalies displaced from the wave by 1.5 to 3 in `y`.
+pub fn sinusoid<R: Rng + ?Sized>(
+    n: usize,
+    n_anomalies: usize,
+    noise: f64,
+    rng: &mut R,
+) -> Dataset<2> {
+    let x_max = 8.0 * std::f64::consts::PI;
+    let normal = (0..n)
+        .map(|_| {
+            let x = rng.gen_range(0.0..x_max);
+            let e: f64 = rng.sample(StandardNormal);
+            [x, x.sin() + noise * e]
+        })
+        .collect();
+    let anomalies = (0..n_anomalies)
+        .map(|_| {
+            let x = rng.gen_range(0.0..x_max);
+            let offset = rng.gen_range(1.5..3.0);
+            [x, x.sin() + if rng.gen() { offset } else { -offset }]
+        })
+        .collect();
+    Dataset::new(normal, anomalies)
+}
+
+/// A standard normal item around `center`.
+fn gaussian<R: Rng + ?Sized, const N: usize>(center: [f64; N], rng: &mut R) -> [f64; N] {
+    center.map(|c| c + rng.sample::<f64, _>(StandardNormal))
+}
+
+/// A uniformly random item at a distance between `min` and `max` from `center`.
+fn shell<R: Rng + ?Sized, const N: usize>(
+    center: [f64; N],
+    min: f64,
+    max: f64,
+    rng: &mut R,
+) -> [f64; N] {
+    let direction: [f64; N] = gaussian([0.0; N], rng);
+    let norm = direction.iter().map(|d| d * d).sum::<f64>().sqrt();
+    let radius = rng.gen_range(min..max);
+    let mut item = center;
+    if norm > 0.0 {
+        for (v, d) in item.iter_mut().zip(direction) {
+            *v += d / norm * radius;
+        }
+    }
+    item
+}
+
+/// Items with all values drawn uniformly from `[1, 9)`, the training data of most tests.
+#[cfg(test)]
+pub(crate) fn uniform<const N: usize>(n: usize) -> Vec<[f64; N]> {
+    let rng = &mut rand::thread_rng();
+    let distribution = rand::distributions::Uniform::new(1., 9.);
+    (0..n)
+        .map(|_| std::array::from_fn(|_| rng.sample(distribution)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{double_blob, single_blob, sinusoid};
+    use crate::eval::roc_auc;
+    use crate::{ExtensionLevel, Forest, ForestOptions};
+
+    #[test]
+    fn planted_anomalies() {
+        let rng = &mut StdRng::seed_from_u64(7);
+        let blob = single_blob::<_, 3>(1000, 20, rng);
+        assert_eq!(blob.data.len(), 1020);
+        assert_eq!(blob.labels.iter().filter(|label| **label).count(), 20);
+        assert!(blob.data[1000..].iter().all(|values| {
+            let norm = values.iter().map(|v| v * v).sum::<f64>().sqrt();
+            (4.0..6.0).contains(&norm)
+        }));
+        assert_eq!(
+            single_blob::<_, 3>(10, 2, &mut StdRng::seed_from_u64(7)).data,
+            single_blob::<_, 3>(10, 2, &mut StdRng::seed_from_u64(7)).data
+        );
+
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            extension_level: ExtensionLevel::Full,
+            ..ForestOptions::default()
+        };
+        for dataset in [
+            double_blob::<_, 2>(1000, 20, rng),
+            sinusoid(1000, 20, 0.1, rng),
+        ] {
+            let forest = Forest::from_slice(&dataset.data, &options).unwrap();
+            let scores: Vec<_> = dataset
+                .data
+                .iter()
+                .map(|values| forest.score(values))
+                .collect();
+            assert!(roc_auc(&scores, &dataset.labels).unwrap() > 0.8);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Detection of drift between the score distribution of the training data and the
+//! scores of live data, and comparison of retrained models.
+
+use std::collections::VecDeque;
+
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::stats::{pearson_correlation, quantile_sorted, ranks, sort_scores};
+use crate::{Forest, ForestFloat};
+
+/// Proportion used in place of empty bins to keep the population stability index finite.
+const PSI_MIN_PROPORTION: f64 = 1e-4;
+
+/// The statistical test used to compare the live scores against the reference scores.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DriftTest {
+    /// Two-sample Kolmogorov-Smirnov test. Drift is signaled when the null hypothesis of
+    /// both samples being drawn from the same distribution is rejected at significance
+    /// level `alpha`.
+    KolmogorovSmirnov { alpha: f64 },
+
+    /// Population stability index over `bins` bins of equal reference frequency. Drift is
+    /// signaled when the index exceeds `threshold`. Common choices are 0.1 for moderate and
+    /// 0.25 for significant shifts.
+    PopulationStabilityIndex { bins: usize, threshold: f64 },
+}
+
+/// Emitted by [`DriftDetector::observe`] when the live scores depart from the reference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriftSignal {
+    /// The test statistic computed over the current window.
+    pub statistic: f64,
+
+    /// The value the statistic had to exceed.
+    pub threshold: f64,
+}
+
+/// Compares a rolling window of live scores against a reference score distribution.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DriftDetector {
+    /// Sorted reference scores.
+    reference: Vec<f64>,
+    test: DriftTest,
+    window_size: usize,
+    window: VecDeque<f64>,
+}
+
+impl DriftDetector {
+    /// Create a detector comparing windows of `window_size` scores against the given
+    /// reference scores.
+    pub fn new(reference_scores: &[f64], window_size: usize, test: DriftTest) -> Self {
+        let mut reference = reference_scores.to_vec();
+        sort_scores(&mut reference);
+        Self {
+            reference,
+            test,
+            window_size,
+            window: VecDeque::with_capacity(window_size),
+        }
+    }
+
+    /// Create a detector using the scores of the training data of `forest` as reference.
+    pub fn fit<'de, T, const N: usize>(
+        forest: &Forest<T, N>,
+        training_data: &[[T; N]],
+        window_size: usize,
+        test: DriftTest,
+    ) -> Self
+    where
+        T: ForestFloat<'de> + SampleUniform + Default,
+        StandardNormal: Distribution<T>,
+    {
+        let scores: Vec<_> = training_data
+            .iter()
+            .map(|values| forest.score(values))
+            .collect();
+        Self::new(&scores, window_size, test)
+    }
+
+    /// Add a live score to the rolling window.
+    ///
+    /// Returns a [`DriftSignal`] when the window is filled and its distribution departs from
+    /// the reference distribution.
+    pub fn observe(&mut self, score: f64) -> Option<DriftSignal> {
+        if self.window_size == 0 {
+            return None;
+        }
+        if self.window.len() == self.window_size {
+            self.window.pop_front();
+        }
+        self.window.push_back(score);
+
+        if self.window.len() < self.window_size {
+            return None;
+        }
+        let statistic = self.statistic()?;
+        let threshold = self.threshold();
+        if statistic > threshold {
+            Some(DriftSignal {
+                statistic,
+                threshold,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The test statistic of the scores currently in the window. `None` when either the
+    /// reference or the window is empty.
+    pub fn statistic(&self) -> Option<f64> {
+        if self.reference.is_empty() || self.window.is_empty() {
+            return None;
+        }
+        let mut window: Vec<_> = self.window.iter().copied().collect();
+        sort_scores(&mut window);
+
+        Some(match self.test {
+            DriftTest::KolmogorovSmirnov { .. } => ks_statistic(&self.reference, &window),
+            DriftTest::PopulationStabilityIndex { bins, .. } => {
+                psi_statistic(&self.reference, &window, bins)
+            }
+        })
+    }
+
+    /// Remove all scores from the window, for example after retraining.
+    pub fn reset(&mut self) {
+        self.window.clear();
+    }
+
+    fn threshold(&self) -> f64 {
+        match self.test {
+            DriftTest::KolmogorovSmirnov { alpha } => {
+                let n = self.reference.len() as f64;
+                let m = self.window.len() as f64;
+                (-(alpha / 2.0).ln() / 2.0).sqrt() * ((n + m) / (n * m)).sqrt()
+            }
+            DriftTest::PopulationStabilityIndex { threshold, .. } => threshold,
+        }
+    }
+}
+
+/// Structure of one of the forests compared by [`Forest::diff`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForestSummary {
+    pub n_trees: usize,
+
+    /// Number of trees of each depth, indexed by the depth.
+    pub depth_counts: Vec<usize>,
+
+    pub mean_depth: f64,
+}
+
+impl ForestSummary {
+    fn new<T, const N: usize>(forest: &Forest<T, N>) -> Self {
+        let depths: Vec<_> = forest.trees.iter().map(|tree| tree.root.depth()).collect();
+        let mut depth_counts = vec![0; depths.iter().max().map_or(0, |max| max + 1)];
+        depths.iter().for_each(|depth| depth_counts[*depth] += 1);
+        Self {
+            n_trees: depths.len(),
+            depth_counts,
+            mean_depth: depths.iter().sum::<usize>() as f64 / depths.len().max(1) as f64,
+        }
+    }
+}
+
+/// Differences between two forests, for example before promoting a retrained model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelDiff {
+    /// The forest [`Forest::diff`] has been called on.
+    pub current: ForestSummary,
+
+    /// The forest it has been compared to.
+    pub other: ForestSummary,
+
+    /// Mean absolute difference of the scores of the sample.
+    pub mean_score_difference: f64,
+
+    /// Largest absolute difference of the scores of the sample.
+    pub max_score_difference: f64,
+
+    /// Spearman rank correlation of the scores of the sample. `1.0` when both forests rank
+    /// the items the same way, `NaN` when the scores of either forest are constant.
+    pub rank_correlation: f64,
+
+    /// Kolmogorov-Smirnov statistic of the score distributions of the sample.
+    pub ks_statistic: f64,
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Compare the structure of the forest to `other` and quantify how differently both
+    /// score the items of `sample`.
+    pub fn diff(&self, other: &Self, sample: &[[T; N]]) -> ModelDiff {
+        let scores: Vec<_> = sample.iter().map(|values| self.score(values)).collect();
+        let other_scores: Vec<_> = sample.iter().map(|values| other.score(values)).collect();
+        let differences: Vec<_> = scores
+            .iter()
+            .zip(other_scores.iter())
+            .map(|(a, b)| (a - b).abs())
+            .collect();
+
+        let rank_correlation =
+            pearson_correlation(&ranks(&scores), &ranks(&other_scores)).unwrap_or(f64::NAN);
+        let (mut sorted, mut other_sorted) = (scores, other_scores);
+        sort_scores(&mut sorted);
+        sort_scores(&mut other_sorted);
+
+        ModelDiff {
+            current: ForestSummary::new(self),
+            other: ForestSummary::new(other),
+            mean_score_difference: differences.iter().sum::<f64>()
+                / differences.len().max(1) as f64,
+            max_score_difference: differences.iter().copied().fold(0.0, f64::max),
+            rank_correlation,
+            ks_statistic: ks_statistic(&sorted, &other_sorted),
+        }
+    }
+}
+
+/// Max. distance between the empirical distribution functions of two sorted samples.
+fn ks_statistic(a: &[f64], b: &[f64]) -> f64 {
+    let (mut i, mut j) = (0, 0);
+    let mut max_distance = 0.0f64;
+    while i < a.len() && j < b.len() {
+        let value = a[i].min(b[j]);
+        while i < a.len() && a[i] <= value {
+            i += 1;
+        }
+        while j < b.len() && b[j] <= value {
+            j += 1;
+        }
+        let distance = (i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs();
+        max_distance = max_distance.max(distance);
+    }
+    max_distance
+}
+
+/// Population stability index of the sorted `actual` sample against the sorted `expected`
+/// sample using bins of equal frequency in `expected`.
+fn psi_statistic(expected: &[f64], actual: &[f64], bins: usize) -> f64 {
+    let bins = bins.max(1);
+    let edges: Vec<_> = (1..bins)
+        .filter_map(|i| quantile_sorted(expected, i as f64 / bins as f64))
+        .collect();
+
+    let proportions = |sorted: &[f64]| {
+        let mut counts = vec![0usize; bins];
+        for value in sorted {
+            counts[edges.partition_point(|edge| edge < value)] += 1;
+        }
+        counts
+            .into_iter()
+            .map(|count| (count as f64 / sorted.len() as f64).max(PSI_MIN_PROPORTION))
+            .collect::<Vec<_>>()
+    };
+
+    proportions(expected)
+        .into_iter()
+        .zip(proportions(actual))
+        .map(|(e, a)| (a - e) * (a / e).ln())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DriftDetector, DriftTest};
+    use crate::datasets::uniform;
+    use crate::stats::ranks;
+    use crate::{Forest, ForestOptions};
+
+    fn reference() -> Vec<f64> {
+        (0..1000).map(|i| 0.3 + f64::from(i) / 5000.0).collect()
+    }
+
+    #[test]
+    fn drift_ks() {
+        let mut detector = DriftDetector::new(
+            &reference(),
+            100,
+            DriftTest::KolmogorovSmirnov { alpha: 0.01 },
+        );
+        for i in 0..100 {
+            assert!(detector.observe(0.3 + f64::from(i) / 500.0).is_none());
+        }
+        let signals = (0..100)
+            .filter_map(|i| detector.observe(0.6 + f64::from(i) / 1000.0))
+            .count();
+        assert!(signals > 0);
+    }
+
+    #[test]
+    fn drift_psi() {
+        let test = DriftTest::PopulationStabilityIndex {
+            bins: 10,
+            threshold: 0.25,
+        };
+        let mut detector = DriftDetector::new(&reference(), 200, test);
+        for i in 0..200 {
+            assert!(detector.observe(0.3 + f64::from(i) / 1000.0).is_none());
+        }
+        assert!(detector.statistic().unwrap() < 0.1);
+
+        detector.reset();
+        let signal = (0..200)
+            .filter_map(|i| detector.observe(0.45 + f64::from(i) / 4000.0))
+            .last()
+            .unwrap();
+        assert!(signal.statistic > signal.threshold);
+    }
+
+    #[test]
+    fn model_diff() {
+        assert_eq!(ranks(&[0.5, 0.1, 0.5, 0.9]), vec![2.5, 1.0, 2.5, 4.0]);
+
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let sample: Vec<_> = (0..100).map(|i| [f64::from(i) / 5.0, 5.0]).collect();
+
+        let diff = forest.diff(&forest, &sample);
+        assert_eq!(diff.current, diff.other);
+        assert_eq!(diff.current.n_trees, 50);
+        assert_eq!(diff.current.depth_counts.iter().sum::<usize>(), 50);
+        assert_eq!(diff.max_score_difference, 0.0);
+        assert!((diff.rank_correlation - 1.0).abs() < 1e-12);
+
+        // a forest trained on shifted data ranks the items differently
+        let shifted: Vec<_> = values.iter().map(|v| [v[0] + 10.0, v[1]]).collect();
+        let options = ForestOptions {
+            n_trees: 20,
+            ..options
+        };
+        let other = Forest::from_slice(shifted.as_slice(), &options).unwrap();
+        let diff = forest.diff(&other, &sample);
+        assert_eq!(diff.other.n_trees, 20);
+        assert!(diff.mean_score_difference > 0.02);
+        assert!(diff.rank_correlation < 0.5);
+    }
+}
@@ -0,0 +1,146 @@
+//! Scores of entities like users or devices, aggregated from the scores of their rows.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::stats::{quantile_sorted, sort_scores};
+use crate::{Error, Forest, ForestFloat};
+
+/// How the scores of the rows of an entity are combined into the score of the entity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    /// The highest score, flagging entities with a single anomalous row.
+    Max,
+
+    /// The mean score, flagging entities whose rows are anomalous overall.
+    Mean,
+
+    /// The `q`-quantile of the scores using linear interpolation, with `q` in `[0, 1]`.
+    /// Less sensitive to single rows than [`Aggregation::Max`].
+    Quantile(f64),
+}
+
+impl Aggregation {
+    /// Aggregate `scores`, reordering them. `NaN` if there are no scores.
+    pub fn apply(&self, scores: &mut [f64]) -> f64 {
+        if scores.is_empty() {
+            return f64::NAN;
+        }
+        match self {
+            Self::Max => scores.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Self::Mean => scores.iter().sum::<f64>() / scores.len() as f64,
+            Self::Quantile(q) => {
+                sort_scores(scores);
+                quantile_sorted(scores, *q).unwrap_or(f64::NAN)
+            }
+        }
+    }
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Compute one anomaly score per entity, where `entities` assigns each row of `data` to
+    /// an entity, e.g. a user or a device.
+    ///
+    /// The scores of the rows of each entity are combined using `aggregation`. The entities
+    /// are returned in the order of their first row. Fails with
+    /// [`Error::DimensionMismatch`] when there is not exactly one entity per row.
+    pub fn score_entities<K: Clone + Eq + Hash>(
+        &self,
+        data: &[[T; N]],
+        entities: &[K],
+        aggregation: Aggregation,
+    ) -> Result<Vec<(K, f64)>, Error> {
+        if entities.len() != data.len() {
+            return Err(Error::DimensionMismatch {
+                expected: data.len(),
+                actual: entities.len(),
+            });
+        }
+        let mut positions = HashMap::new();
+        let mut groups: Vec<(K, Vec<f64>)> = vec![];
+        for (values, entity) in data.iter().zip(entities) {
+            let position = *positions.entry(entity).or_insert_with(|| {
+                groups.push((entity.clone(), vec![]));
+                groups.len() - 1
+            });
+            groups[position].1.push(self.score(values));
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(entity, mut scores)| (entity, aggregation.apply(&mut scores)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aggregation;
+    use crate::datasets::uniform;
+    use crate::{Error, Forest, ForestOptions};
+
+    #[test]
+    fn aggregate_entities() {
+        assert_eq!(Aggregation::Max.apply(&mut [0.3, 0.7, 0.5]), 0.7);
+        assert_eq!(Aggregation::Mean.apply(&mut [0.3, 0.6, 0.6]), 0.5);
+        assert_eq!(Aggregation::Quantile(0.5).apply(&mut [0.7, 0.3, 0.5]), 0.5);
+        assert!(Aggregation::Quantile(0.5).apply(&mut []).is_nan());
+        assert!((Aggregation::Quantile(0.75).apply(&mut [0.2, 0.4, 0.8]) - 0.6).abs() < 1e-12);
+
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let entities: Vec<_> = (0..1000)
+            .map(|row| format!("user-{}", 9 - row % 10))
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+
+        for aggregation in [
+            Aggregation::Max,
+            Aggregation::Mean,
+            Aggregation::Quantile(0.9),
+        ] {
+            let scores = forest
+                .score_entities(&values, &entities, aggregation)
+                .unwrap();
+            // in the order of the first rows
+            assert_eq!(scores.len(), 10);
+            assert_eq!(scores[0].0, "user-9");
+            for (i, (entity, score)) in scores.into_iter().enumerate() {
+                let mut member_scores: Vec<_> = (0..100)
+                    .map(|j| forest.score(&values[j * 10 + i]))
+                    .collect();
+                member_scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let expected = match aggregation {
+                    Aggregation::Max => member_scores[99],
+                    Aggregation::Mean => member_scores.iter().sum::<f64>() / 100.0,
+                    // rank 0.9 * 99 = 89.1 between the 90th and the 91st score
+                    Aggregation::Quantile(_) => {
+                        member_scores[89] + 0.1 * (member_scores[90] - member_scores[89])
+                    }
+                };
+                assert_eq!(entity, format!("user-{}", 9 - i));
+                assert!(
+                    (score - expected).abs() < 1e-12,
+                    "{} != {}",
+                    score,
+                    expected
+                );
+            }
+        }
+
+        assert!(matches!(
+            forest.score_entities(&values, &entities[1..], Aggregation::Mean),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+}
@@ -5,6 +5,35 @@ use std::fmt::Formatter;
 pub enum Error {
     ExtensionLevelExceedsDimensions,
     InsufficientTrainingData,
+    DimensionMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        actual: usize,
+    },
+    NonFiniteValue {
+        dimension: usize,
+        value: f64,
+    },
+    InexactFeature {
+        row: usize,
+        dimension: usize,
+    },
+    InvalidModel,
+    CountOverflow {
+        value: usize,
+    },
+    TreeIndexOutOfRange {
+        index: usize,
+        n_trees: usize,
+    },
+    InvalidOption {
+        name: &'static str,
+    },
+    Io(std::io::Error),
 }
 
 impl fmt::Display for Error {
@@ -15,8 +44,50 @@ impl fmt::Display for Error {
                 "Extension level has to be less than the number of dimensions"
             ),
             Self::InsufficientTrainingData => write!(f, "insufficient training data"),
+            Self::DimensionMismatch { expected, actual } => {
+                write!(f, "expected {} values, but got {}", expected, actual)
+            }
+            Self::RaggedRow {
+                row,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected {} values in row {}, but got {}",
+                expected, row, actual
+            ),
+            Self::NonFiniteValue { dimension, value } => {
+                write!(
+                    f,
+                    "value {} in dimension {} is not finite",
+                    value, dimension
+                )
+            }
+            Self::InexactFeature { row, dimension } => write!(
+                f,
+                "value in row {} and dimension {} can not be represented exactly",
+                row, dimension
+            ),
+            Self::InvalidModel => write!(f, "invalid model data"),
+            Self::CountOverflow { value } => {
+                write!(f, "count {} does not fit into 32 bits", value)
+            }
+            Self::TreeIndexOutOfRange { index, n_trees } => write!(
+                f,
+                "tree index {} is out of range for {} trees",
+                index, n_trees
+            ),
+            Self::InvalidOption { name } => write!(f, "invalid value of option {}", name),
+            Self::Io(err) => write!(f, "io error: {}", err),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1164 @@
+//! Evaluation of forests and their scores.
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::math;
+use crate::stats::{pearson_correlation, ranks};
+use crate::{Error, ExtensionLevel, Forest, ForestFloat, ForestOptions};
+
+/// How synthetic outliers are generated from the training data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutlierStrategy {
+    /// Sample uniformly from the bounding box of the training data, enlarged by
+    /// `inflation` times its extent on every side.
+    InflatedBoundingBox { inflation: f64 },
+
+    /// Sample along random directions from the centroid of the training data, at
+    /// `distance_factor` times the largest distance of a training item from the centroid.
+    RandomRays { distance_factor: f64 },
+}
+
+/// Separation between the scores of the training data and synthetic outliers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Separation {
+    /// Mean score of the training data.
+    pub training_mean: f64,
+
+    /// Mean score of the synthetic outliers.
+    pub outlier_mean: f64,
+
+    /// Probability of a synthetic outlier being scored higher than a training item.
+    pub auc: f64,
+
+    /// The threshold best separating training data and outliers (maximizing the
+    /// difference between true- and false-positive rate).
+    pub threshold: f64,
+}
+
+/// Generate `n` synthetic outliers for the training data.
+///
+/// Useful for picking thresholds when no labeled anomalies are available. Returns an empty
+/// vec for empty training data.
+pub fn synthetic_outliers<T: Float, const N: usize>(
+    training_data: &[[T; N]],
+    n: usize,
+    strategy: OutlierStrategy,
+) -> Vec<[T; N]> {
+    if training_data.is_empty() {
+        return vec![];
+    }
+    let rng = &mut rand::thread_rng();
+    let to_f64 = |v: T| v.to_f64().unwrap_or(0.0);
+    let from_f64 = |v: f64| T::from(v).unwrap_or_else(T::zero);
+
+    let mut mins = [f64::INFINITY; N];
+    let mut maxs = [f64::NEG_INFINITY; N];
+    let mut centroid = [0.0; N];
+    for values in training_data {
+        for (i, v) in values.iter().copied().map(to_f64).enumerate() {
+            mins[i] = mins[i].min(v);
+            maxs[i] = maxs[i].max(v);
+            centroid[i] += v / training_data.len() as f64;
+        }
+    }
+
+    match strategy {
+        OutlierStrategy::InflatedBoundingBox { inflation } => (0..n)
+            .map(|_| {
+                let mut outlier = [T::zero(); N];
+                for (i, o) in outlier.iter_mut().enumerate() {
+                    let extent = (maxs[i] - mins[i]) * inflation.max(0.0);
+                    let (low, high) = (mins[i] - extent, maxs[i] + extent);
+                    *o = from_f64(if high > low {
+                        rng.gen_range(low..high)
+                    } else {
+                        low
+                    });
+                }
+                outlier
+            })
+            .collect(),
+        OutlierStrategy::RandomRays { distance_factor } => {
+            let radius = training_data
+                .iter()
+                .map(|values| {
+                    values
+                        .iter()
+                        .zip(centroid.iter())
+                        .map(|(v, c)| (to_f64(*v) - c).powi(2))
+                        .sum::<f64>()
+                        .sqrt()
+                })
+                .fold(0.0, f64::max);
+
+            (0..n)
+                .map(|_| {
+                    let mut direction = [0.0; N];
+                    direction
+                        .iter_mut()
+                        .for_each(|d| *d = rng.sample::<f64, _>(StandardNormal));
+                    let norm = direction.iter().map(|d| d * d).sum::<f64>().sqrt();
+
+                    let mut outlier = [T::zero(); N];
+                    for (i, o) in outlier.iter_mut().enumerate() {
+                        let offset = if norm > 0.0 {
+                            direction[i] / norm * radius * distance_factor
+                        } else {
+                            0.0
+                        };
+                        *o = from_f64(centroid[i] + offset);
+                    }
+                    outlier
+                })
+                .collect()
+        }
+    }
+}
+
+/// Generate `n_outliers` synthetic outliers and report how well `forest` separates them
+/// from the training data.
+pub fn score_separation<'de, T, const N: usize>(
+    forest: &Forest<T, N>,
+    training_data: &[[T; N]],
+    n_outliers: usize,
+    strategy: OutlierStrategy,
+) -> Separation
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    let outliers = synthetic_outliers(training_data, n_outliers, strategy);
+    let mut scores = vec![];
+    let mut labels = vec![];
+    for (values, is_outlier) in training_data
+        .iter()
+        .map(|values| (values, false))
+        .chain(outliers.iter().map(|values| (values, true)))
+    {
+        scores.push(forest.score(values));
+        labels.push(is_outlier);
+    }
+
+    let mean = |is_outlier: bool| {
+        let (sum, count) = scores
+            .iter()
+            .zip(labels.iter())
+            .filter(|(_, label)| **label == is_outlier)
+            .fold((0.0, 0usize), |(sum, count), (score, _)| {
+                (sum + score, count + 1)
+            });
+        sum / count.max(1) as f64
+    };
+
+    Separation {
+        training_mean: mean(false),
+        outlier_mean: mean(true),
+        auc: auc(&scores, &labels),
+        threshold: youden_threshold(&scores, &labels),
+    }
+}
+
+/// Importance of each feature for separating the labeled anomalies in `data` from the
+/// normal items.
+///
+/// Each column is shuffled in turn and the importance of the feature is the resulting
+/// drop of the ROC AUC of the scores, so features which are irrelevant for the outcome
+/// get an importance close to zero. Fails with [`Error::DimensionMismatch`] when the
+/// number of labels differs from the number of rows.
+pub fn permutation_importance<'de, T, const N: usize>(
+    forest: &Forest<T, N>,
+    data: &[[T; N]],
+    labels: &[bool],
+) -> Result<[f64; N], Error>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    check_labels(data.len(), labels)?;
+    let rng = &mut rand::thread_rng();
+    let scores: Vec<_> = data.iter().map(|values| forest.score(values)).collect();
+    let baseline = auc(&scores, labels);
+
+    let mut importance = [0.0; N];
+    let mut permuted = data.to_vec();
+    for (feature, importance) in importance.iter_mut().enumerate() {
+        let mut column: Vec<_> = data.iter().map(|values| values[feature]).collect();
+        column.shuffle(rng);
+        for (values, v) in permuted.iter_mut().zip(column) {
+            values[feature] = v;
+        }
+
+        let scores: Vec<_> = permuted.iter().map(|values| forest.score(values)).collect();
+        *importance = baseline - auc(&scores, labels);
+
+        for (values, original) in permuted.iter_mut().zip(data.iter()) {
+            values[feature] = original[feature];
+        }
+    }
+    Ok(importance)
+}
+
+/// What [`cross_validate`] measures on each held-out fold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Objective<'a> {
+    /// ROC AUC separating the held-out fold from `n_outliers` synthetic outliers generated
+    /// from it. Requires no labels.
+    SyntheticOutliers {
+        n_outliers: usize,
+        strategy: OutlierStrategy,
+    },
+
+    /// ROC AUC of the scores of the held-out fold against `labels`, one per row of the data.
+    RocAuc { labels: &'a [bool] },
+
+    /// Precision among the `k` highest scored items of the held-out fold against `labels`,
+    /// one per row of the data.
+    PrecisionAtK { labels: &'a [bool], k: usize },
+}
+
+/// Result of evaluating a single fold, see [`cross_validate`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FoldResult {
+    /// Number of rows the forest was trained on.
+    pub n_train: usize,
+
+    /// Number of held-out rows.
+    pub n_test: usize,
+
+    /// The value of the objective on the held-out rows.
+    pub value: f64,
+}
+
+/// Result of [`cross_validate`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossValidation {
+    /// The results of the individual folds.
+    pub folds: Vec<FoldResult>,
+
+    /// Mean value of the objective over all folds.
+    pub mean: f64,
+
+    /// Standard deviation of the value of the objective over all folds.
+    pub std_dev: f64,
+}
+
+/// k-fold cross-validation of forests built with `options`.
+///
+/// The rows of `data` are shuffled and split into `k` folds of nearly equal size. For every
+/// fold a forest is trained on the remaining folds and the held-out fold is evaluated using
+/// `objective`. Fails with [`Error::InsufficientTrainingData`] when `k` is less than 2 or
+/// exceeds the number of rows, and with [`Error::DimensionMismatch`] when the number of
+/// labels differs from the number of rows.
+pub fn cross_validate<'de, T, const N: usize>(
+    data: &[[T; N]],
+    options: &ForestOptions,
+    k: usize,
+    objective: Objective,
+) -> Result<CrossValidation, Error>
+where
+    T: ForestFloat<'de> + SampleUniform + Default + Send + Sync,
+    StandardNormal: Distribution<T>,
+{
+    if k < 2 || k > data.len() {
+        return Err(Error::InsufficientTrainingData);
+    }
+    let labels = match objective {
+        Objective::SyntheticOutliers { .. } => None,
+        Objective::RocAuc { labels } | Objective::PrecisionAtK { labels, .. } => Some(labels),
+    };
+    if let Some(labels) = labels {
+        check_labels(data.len(), labels)?;
+    }
+
+    let mut rows: Vec<_> = (0..data.len()).collect();
+    rows.shuffle(&mut rand::thread_rng());
+
+    let mut folds = Vec::with_capacity(k);
+    for fold in 0..k {
+        // the first `data.len() % k` folds hold one additional row
+        let start = fold * (data.len() / k) + fold.min(data.len() % k);
+        let end = start + data.len() / k + usize::from(fold < data.len() % k);
+        let (test_rows, train_rows) = (&rows[start..end], [&rows[..start], &rows[end..]]);
+
+        let train: Vec<_> = train_rows.concat().iter().map(|row| data[*row]).collect();
+        let test: Vec<_> = test_rows.iter().map(|row| data[*row]).collect();
+        let forest = Forest::from_slice(&train, options)?;
+
+        let value = match objective {
+            Objective::SyntheticOutliers {
+                n_outliers,
+                strategy,
+            } => score_separation(&forest, &test, n_outliers, strategy).auc,
+            Objective::RocAuc { labels } | Objective::PrecisionAtK { labels, .. } => {
+                let scores: Vec<_> = test.iter().map(|values| forest.score(values)).collect();
+                let labels: Vec<_> = test_rows.iter().map(|row| labels[*row]).collect();
+                match objective {
+                    Objective::PrecisionAtK { k, .. } => precision_at_k(&scores, &labels, k)?,
+                    _ => auc(&scores, &labels),
+                }
+            }
+        };
+        folds.push(FoldResult {
+            n_train: train.len(),
+            n_test: test.len(),
+            value,
+        });
+    }
+
+    let mean = folds.iter().map(|fold| fold.value).sum::<f64>() / k as f64;
+    let variance = folds
+        .iter()
+        .map(|fold| (fold.value - mean).powi(2))
+        .sum::<f64>()
+        / k as f64;
+    Ok(CrossValidation {
+        folds,
+        mean,
+        std_dev: variance.sqrt(),
+    })
+}
+
+/// Scores of validation data computed from the first trees of a forest, see
+/// [`Forest::convergence_curve`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConvergencePoint {
+    /// Number of trees included.
+    pub n_trees: usize,
+
+    /// Mean raw score of the validation data, `NaN` if there is none.
+    pub mean_score: f64,
+
+    /// Spearman rank correlation between the raw scores and the raw scores of the complete
+    /// forest. `1.0` when the trees included rank the items like the complete forest.
+    pub rank_correlation: f64,
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// How the raw scores of `validation_data` stabilize as the trees of the forest are
+    /// included one after another, with one point for each number of trees.
+    ///
+    /// Once the mean score and the rank correlation with the complete forest level off,
+    /// adding more trees hardly changes the scores.
+    pub fn convergence_curve(&self, validation_data: &[[T; N]]) -> Vec<ConvergencePoint> {
+        let items: Vec<_> = validation_data
+            .iter()
+            .map(|values| self.pipeline.transform(values))
+            .collect();
+        let mut path_lengths = vec![0.0; items.len()];
+        let scores_by_tree: Vec<Vec<f64>> = self
+            .trees
+            .iter()
+            .enumerate()
+            .map(|(i, tree)| {
+                path_lengths
+                    .iter_mut()
+                    .zip(items.iter())
+                    .map(|(path_length, values)| {
+                        *path_length += tree.adjusted_path_length(values) * tree.path_length_c_inv;
+                        math::exp2(-*path_length / (i + 1) as f64)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let final_ranks = scores_by_tree.last().map(|scores| ranks(scores));
+        scores_by_tree
+            .iter()
+            .enumerate()
+            .map(|(i, scores)| ConvergencePoint {
+                n_trees: i + 1,
+                mean_score: scores.iter().sum::<f64>() / scores.len() as f64,
+                rank_correlation: final_ranks
+                    .as_ref()
+                    .and_then(|final_ranks| pearson_correlation(&ranks(scores), final_ranks))
+                    .unwrap_or(1.0),
+            })
+            .collect()
+    }
+}
+
+/// Values of the options tried by [`successive_halving`]. Every combination of the values
+/// is a candidate, empty lists keep the value of the base options.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchSpace {
+    pub n_trees: Vec<usize>,
+    pub sample_size: Vec<usize>,
+    pub extension_level: Vec<ExtensionLevel>,
+}
+
+impl SearchSpace {
+    /// All combinations of the values applied to `base`.
+    pub fn candidates(&self, base: &ForestOptions) -> Vec<ForestOptions> {
+        let or_base = |values: &[usize], base: usize| {
+            if values.is_empty() {
+                vec![base]
+            } else {
+                values.to_vec()
+            }
+        };
+        let extension_levels = if self.extension_level.is_empty() {
+            vec![base.extension_level]
+        } else {
+            self.extension_level.clone()
+        };
+        let mut candidates = vec![];
+        for n_trees in or_base(&self.n_trees, base.n_trees) {
+            for sample_size in or_base(&self.sample_size, base.sample_size) {
+                for extension_level in extension_levels.iter().copied() {
+                    candidates.push(ForestOptions {
+                        n_trees,
+                        sample_size,
+                        extension_level,
+                        ..base.clone()
+                    });
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Settings of [`successive_halving`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Halving {
+    /// Only the best `1 / eta` of the candidates advance to the next round, which uses
+    /// `eta` times as many rows.
+    pub eta: usize,
+
+    /// Number of folds of the cross-validation evaluating each candidate.
+    pub folds: usize,
+
+    /// Max. number of candidates evaluated in parallel.
+    pub max_threads: usize,
+}
+
+impl Default for Halving {
+    fn default() -> Self {
+        Self {
+            eta: 3,
+            folds: 3,
+            max_threads: 1,
+        }
+    }
+}
+
+/// Evaluation of a candidate in a round of [`successive_halving`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trial {
+    /// The round, starting at `0`.
+    pub round: usize,
+
+    /// Number of rows of the data the candidate was evaluated on.
+    pub n_rows: usize,
+
+    pub options: ForestOptions,
+
+    /// Mean value of the objective over the folds, `None` if no forest could be built with
+    /// the options, e.g. because the sample size exceeds the number of rows.
+    pub value: Option<f64>,
+}
+
+/// Result of [`successive_halving`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    /// The candidate with the highest value in the last round.
+    pub best: ForestOptions,
+
+    /// The value of the best candidate in the last round.
+    pub value: f64,
+
+    /// All trials in the order of the rounds.
+    pub trials: Vec<Trial>,
+}
+
+/// Tune the options of forests by successive halving over the candidates of `space`.
+///
+/// All candidates are evaluated by cross-validation, see [`cross_validate`], on a random
+/// subset of the rows in the first round. After each round only the best `1 / eta` of the
+/// candidates are kept, while the number of rows grows by the factor `eta` up to all rows
+/// in the last round. This spends most of the budget on promising candidates. Higher
+/// values of `objective` are better.
+///
+/// Fails with [`Error::InsufficientTrainingData`] when no candidate can be evaluated on the
+/// rows of the first round, and like [`cross_validate`] for mismatched labels.
+pub fn successive_halving<'de, T, const N: usize>(
+    data: &[[T; N]],
+    base: &ForestOptions,
+    space: &SearchSpace,
+    objective: Objective,
+    halving: &Halving,
+) -> Result<Tuning, Error>
+where
+    T: ForestFloat<'de> + SampleUniform + Default + Send + Sync,
+    StandardNormal: Distribution<T>,
+{
+    let labels = match objective {
+        Objective::SyntheticOutliers { .. } => None,
+        Objective::RocAuc { labels } | Objective::PrecisionAtK { labels, .. } => Some(labels),
+    };
+    if let Some(labels) = labels {
+        check_labels(data.len(), labels)?;
+    }
+    let eta = halving.eta.max(2);
+    let mut candidates = space.candidates(base);
+    // the last round evaluates at most `eta` candidates
+    let mut n_rounds = 1;
+    let mut n_candidates = candidates.len();
+    while n_candidates > eta {
+        n_candidates = (n_candidates + eta - 1) / eta;
+        n_rounds += 1;
+    }
+
+    let mut rows: Vec<_> = (0..data.len()).collect();
+    rows.shuffle(&mut rand::thread_rng());
+    let mut trials = vec![];
+    for round in 0..n_rounds {
+        let n_rows = data.len() / eta.pow((n_rounds - 1 - round) as u32);
+        let subset: Vec<_> = rows[..n_rows].iter().map(|row| data[*row]).collect();
+        let subset_labels: Vec<_> = labels.map_or(vec![], |labels| {
+            rows[..n_rows].iter().map(|row| labels[*row]).collect()
+        });
+        let objective = match objective {
+            Objective::SyntheticOutliers { .. } => objective,
+            Objective::RocAuc { .. } => Objective::RocAuc {
+                labels: &subset_labels,
+            },
+            Objective::PrecisionAtK { k, .. } => Objective::PrecisionAtK {
+                labels: &subset_labels,
+                k,
+            },
+        };
+
+        let mut values = vec![None; candidates.len()];
+        let chunk_size =
+            (candidates.len() + halving.max_threads.max(1) - 1) / halving.max_threads.max(1);
+        std::thread::scope(|scope| {
+            for (options_chunk, values_chunk) in candidates
+                .chunks(chunk_size.max(1))
+                .zip(values.chunks_mut(chunk_size.max(1)))
+            {
+                let subset = &subset;
+                scope.spawn(move || {
+                    for (options, value) in options_chunk.iter().zip(values_chunk.iter_mut()) {
+                        *value = cross_validate(subset, options, halving.folds, objective)
+                            .ok()
+                            .map(|result| result.mean);
+                    }
+                });
+            }
+        });
+
+        let mut ranked: Vec<_> = candidates
+            .iter()
+            .zip(values.iter())
+            .filter_map(|(options, value)| value.map(|value| (options.clone(), value)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        trials.extend(
+            candidates
+                .into_iter()
+                .zip(values)
+                .map(|(options, value)| Trial {
+                    round,
+                    n_rows,
+                    options,
+                    value,
+                }),
+        );
+        if round + 1 == n_rounds || ranked.len() <= 1 {
+            return match ranked.into_iter().next() {
+                Some((best, value)) => Ok(Tuning {
+                    best,
+                    value,
+                    trials,
+                }),
+                None => Err(Error::InsufficientTrainingData),
+            };
+        }
+        ranked.truncate((ranked.len() + eta - 1) / eta);
+        candidates = ranked.into_iter().map(|(options, _)| options).collect();
+    }
+    Err(Error::InsufficientTrainingData)
+}
+
+/// Area under the ROC curve: the probability of a randomly chosen positive item being
+/// scored higher than a randomly chosen negative item. Ties count half.
+///
+/// Returns `0.5` when either class is absent. Fails with [`Error::DimensionMismatch`] when
+/// the number of labels differs from the number of scores.
+pub fn roc_auc(scores: &[f64], labels: &[bool]) -> Result<f64, Error> {
+    check_labels(scores.len(), labels)?;
+    Ok(auc(scores, labels))
+}
+
+/// [`roc_auc`] of scores and labels of equal length.
+fn auc(scores: &[f64], labels: &[bool]) -> f64 {
+    let mut ranked: Vec<_> = scores.iter().copied().zip(labels.iter().copied()).collect();
+    ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let n_positive = ranked.iter().filter(|(_, label)| *label).count();
+    let n_negative = ranked.len() - n_positive;
+    if n_positive == 0 || n_negative == 0 {
+        return 0.5;
+    }
+
+    // sum of the ranks of the positives, using the average rank for ties
+    let mut rank_sum = 0.0;
+    let mut i = 0;
+    while i < ranked.len() {
+        let mut j = i;
+        while j < ranked.len() && ranked[j].0 == ranked[i].0 {
+            j += 1;
+        }
+        let average_rank = (i + j + 1) as f64 / 2.0;
+        rank_sum += average_rank * ranked[i..j].iter().filter(|(_, label)| *label).count() as f64;
+        i = j;
+    }
+
+    (rank_sum - (n_positive * (n_positive + 1)) as f64 / 2.0) / (n_positive * n_negative) as f64
+}
+
+/// Fail with [`Error::DimensionMismatch`] unless there is one label per item.
+fn check_labels(n_items: usize, labels: &[bool]) -> Result<(), Error> {
+    if labels.len() == n_items {
+        Ok(())
+    } else {
+        Err(Error::DimensionMismatch {
+            expected: n_items,
+            actual: labels.len(),
+        })
+    }
+}
+
+/// Precision and recall of flagging all items scored at or above `threshold`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThresholdPoint {
+    pub threshold: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f_beta: f64,
+}
+
+/// Result of [`best_threshold`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThresholdSelection {
+    /// The point with the highest Fβ score.
+    pub best: ThresholdPoint,
+
+    /// All candidate thresholds in descending order.
+    pub sweep: Vec<ThresholdPoint>,
+}
+
+/// Select the threshold maximizing the Fβ score of flagging labeled anomalies.
+///
+/// Every distinct score is a candidate threshold, an item is flagged when its score is at
+/// or above the threshold. `beta` > 1 weighs recall higher than precision, `beta` < 1
+/// weighs precision higher. Returns `None` for empty scores. Fails with
+/// [`Error::DimensionMismatch`] when the number of labels differs from the number of scores.
+pub fn best_threshold(
+    scores: &[f64],
+    labels: &[bool],
+    beta: f64,
+) -> Result<Option<ThresholdSelection>, Error> {
+    check_labels(scores.len(), labels)?;
+    let n_positive = labels.iter().filter(|label| **label).count();
+    let beta2 = beta * beta;
+
+    let sweep: Vec<_> = confusion_counts(scores, labels)
+        .into_iter()
+        .map(|(threshold, tp, fp)| {
+            let precision = tp as f64 / (tp + fp) as f64;
+            let recall = if n_positive > 0 {
+                tp as f64 / n_positive as f64
+            } else {
+                0.0
+            };
+            let f_beta = if precision + recall > 0.0 {
+                (1.0 + beta2) * precision * recall / (beta2 * precision + recall)
+            } else {
+                0.0
+            };
+            ThresholdPoint {
+                threshold,
+                precision,
+                recall,
+                f_beta,
+            }
+        })
+        .collect();
+
+    let best = sweep.iter().copied().fold(None, |best, point| match best {
+        Some(ThresholdPoint { f_beta, .. }) if f_beta >= point.f_beta => best,
+        _ => Some(point),
+    });
+    Ok(best.map(|best| ThresholdSelection { best, sweep }))
+}
+
+/// A row of [`threshold_report`]: the consequences of flagging all items scored at or
+/// above `threshold`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThresholdReportRow {
+    pub threshold: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub false_positive_rate: f64,
+
+    /// Fraction of all items which are flagged.
+    pub alert_rate: f64,
+}
+
+impl ThresholdReportRow {
+    /// Expected number of alerts per day when `items_per_day` items are scored, assuming
+    /// they are distributed like the evaluated items.
+    pub fn alerts_per_day(&self, items_per_day: f64) -> f64 {
+        self.alert_rate * items_per_day
+    }
+}
+
+/// Precision, recall, false-positive rate and alert rate of every distinct score as
+/// threshold, in order of descending thresholds, to weigh detection against the number of
+/// alerts.
+///
+/// Recall is `0.0` when there are no anomalies, the false-positive rate is `0.0` when
+/// there are only anomalies. Fails with [`Error::DimensionMismatch`] when the number of
+/// labels differs from the number of scores.
+pub fn threshold_report(scores: &[f64], labels: &[bool]) -> Result<Vec<ThresholdReportRow>, Error> {
+    check_labels(scores.len(), labels)?;
+    let n_positive = labels.iter().filter(|label| **label).count();
+    let n_negative = labels.len() - n_positive;
+    let rate = |count: usize, total: usize| {
+        if total > 0 {
+            count as f64 / total as f64
+        } else {
+            0.0
+        }
+    };
+
+    Ok(confusion_counts(scores, labels)
+        .into_iter()
+        .map(|(threshold, tp, fp)| ThresholdReportRow {
+            threshold,
+            precision: tp as f64 / (tp + fp) as f64,
+            recall: rate(tp, n_positive),
+            false_positive_rate: rate(fp, n_negative),
+            alert_rate: rate(tp + fp, labels.len()),
+        })
+        .collect())
+}
+
+/// Fraction of anomalies among the `k` highest scored items.
+///
+/// Returns `0.0` for `k == 0`. Fails with [`Error::DimensionMismatch`] when the number of
+/// labels differs from the number of scores.
+pub fn precision_at_k(scores: &[f64], labels: &[bool], k: usize) -> Result<f64, Error> {
+    let ranked = ranked_labels(scores, labels)?;
+    let k = k.min(scores.len());
+    if k == 0 {
+        return Ok(0.0);
+    }
+    let hits = ranked.into_iter().take(k).filter(|label| *label).count();
+    Ok(hits as f64 / k as f64)
+}
+
+/// Fraction of all anomalies found among the `k` highest scored items.
+///
+/// Returns `0.0` when there are no anomalies. Fails with [`Error::DimensionMismatch`] when
+/// the number of labels differs from the number of scores.
+pub fn recall_at_k(scores: &[f64], labels: &[bool], k: usize) -> Result<f64, Error> {
+    let ranked = ranked_labels(scores, labels)?;
+    let n_positive = labels.iter().filter(|label| **label).count();
+    if n_positive == 0 {
+        return Ok(0.0);
+    }
+    let hits = ranked.into_iter().take(k).filter(|label| *label).count();
+    Ok(hits as f64 / n_positive as f64)
+}
+
+/// Lift of reviewing the `k` highest scored items, for every `k` from 1 to the number of
+/// items: the precision at `k` divided by the fraction of anomalies among all items.
+///
+/// A lift of 1 means the ranking is no better than picking items at random. Returns an
+/// empty vec when there are no anomalies. Fails with [`Error::DimensionMismatch`] when the
+/// number of labels differs from the number of scores.
+pub fn lift_curve(scores: &[f64], labels: &[bool]) -> Result<Vec<f64>, Error> {
+    let ranked = ranked_labels(scores, labels)?;
+    let n_positive = ranked.iter().filter(|label| **label).count();
+    if n_positive == 0 {
+        return Ok(vec![]);
+    }
+    let base_rate = n_positive as f64 / ranked.len() as f64;
+
+    let mut hits = 0;
+    Ok(ranked
+        .iter()
+        .enumerate()
+        .map(|(i, label)| {
+            hits += usize::from(*label);
+            hits as f64 / (i + 1) as f64 / base_rate
+        })
+        .collect())
+}
+
+/// The labels sorted by descending score, see [`check_labels`].
+fn ranked_labels(scores: &[f64], labels: &[bool]) -> Result<Vec<bool>, Error> {
+    check_labels(scores.len(), labels)?;
+    let mut ranked: Vec<_> = scores.iter().copied().zip(labels.iter().copied()).collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+    Ok(ranked.into_iter().map(|(_, label)| label).collect())
+}
+
+/// Number of true and false positives when flagging items scored at or above each
+/// distinct score, in order of descending thresholds.
+fn confusion_counts(scores: &[f64], labels: &[bool]) -> Vec<(f64, usize, usize)> {
+    let mut ranked: Vec<_> = scores.iter().copied().zip(labels.iter().copied()).collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut counts: Vec<(f64, usize, usize)> = vec![];
+    let (mut tp, mut fp) = (0, 0);
+    for (score, label) in ranked {
+        if label {
+            tp += 1;
+        } else {
+            fp += 1;
+        }
+        match counts.last_mut() {
+            Some(last) if last.0 == score => *last = (score, tp, fp),
+            _ => counts.push((score, tp, fp)),
+        }
+    }
+    counts
+}
+
+/// The score threshold maximizing true-positive rate minus false-positive rate.
+fn youden_threshold(scores: &[f64], labels: &[bool]) -> f64 {
+    let n_positive = labels.iter().filter(|label| **label).count().max(1) as f64;
+    let n_negative = labels.iter().filter(|label| !**label).count().max(1) as f64;
+
+    let mut best = (f64::NEG_INFINITY, 0.5);
+    for (threshold, tp, fp) in confusion_counts(scores, labels) {
+        let j = tp as f64 / n_positive - fp as f64 / n_negative;
+        if j >= best.0 {
+            best = (j, threshold);
+        }
+    }
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::{
+        best_threshold, cross_validate, lift_curve, permutation_importance, precision_at_k, ranks,
+        recall_at_k, roc_auc, score_separation, successive_halving, synthetic_outliers,
+        threshold_report, Halving, Objective, OutlierStrategy, SearchSpace,
+    };
+    use crate::datasets::uniform;
+    use crate::{Error, ExtensionLevel, Forest, ForestOptions};
+
+    #[test]
+    fn auc() {
+        assert_eq!(
+            roc_auc(&[0.1, 0.2, 0.8, 0.9], &[false, false, true, true]).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            roc_auc(&[0.1, 0.2, 0.8, 0.9], &[true, true, false, false]).unwrap(),
+            0.0
+        );
+        assert_eq!(roc_auc(&[0.5, 0.5], &[true, false]).unwrap(), 0.5);
+        assert_eq!(roc_auc(&[0.5, 0.7], &[true, true]).unwrap(), 0.5);
+        assert!(matches!(
+            roc_auc(&[0.5, 0.7], &[true]),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn synthetic_separation() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+
+        let outliers = synthetic_outliers(
+            &values,
+            10,
+            OutlierStrategy::RandomRays {
+                distance_factor: 2.0,
+            },
+        );
+        assert_eq!(outliers.len(), 10);
+        // the max. distance from the centroid is at most sqrt(32)
+        assert!(outliers
+            .iter()
+            .all(|o: &[f64; 2]| ((o[0] - 5.0).powi(2) + (o[1] - 5.0).powi(2)).sqrt() > 8.0));
+
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let separation = score_separation(
+            &forest,
+            &values,
+            200,
+            OutlierStrategy::InflatedBoundingBox { inflation: 2.0 },
+        );
+        assert!(separation.outlier_mean > separation.training_mean);
+        assert!(separation.auc > 0.7);
+        assert!(separation.threshold > separation.training_mean);
+    }
+
+    #[test]
+    fn permutation_importance_of_features() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let mut values: Vec<[f64; 2]> = uniform(1000);
+        let mut labels = vec![false; values.len()];
+        // anomalies only differ from the normal items in the first feature
+        values.extend((0..50).map(|_| [rng.sample(distribution) + 20.0, rng.sample(distribution)]));
+        labels.resize(values.len(), true);
+
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let importance = permutation_importance(&forest, &values, &labels).unwrap();
+        assert!(importance[0] > 0.2);
+        assert!(importance[1].abs() < 0.1);
+        assert!(matches!(
+            permutation_importance(&forest, &values, &labels[1..]),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn best_f_beta_threshold() {
+        let scores = [0.3, 0.4, 0.5, 0.6, 0.6, 0.7, 0.8];
+        let labels = [false, false, true, false, true, true, true];
+
+        let selection = best_threshold(&scores, &labels, 1.0).unwrap().unwrap();
+        assert_eq!(
+            selection
+                .sweep
+                .iter()
+                .map(|point| point.threshold)
+                .collect::<Vec<_>>(),
+            vec![0.8, 0.7, 0.6, 0.5, 0.4, 0.3]
+        );
+        assert_eq!(selection.best.threshold, 0.5);
+        assert_eq!(selection.best.precision, 0.8);
+        assert_eq!(selection.best.recall, 1.0);
+
+        // weighing precision higher prefers a stricter threshold
+        let selection = best_threshold(&scores, &labels, 0.1).unwrap().unwrap();
+        assert_eq!(selection.best.threshold, 0.7);
+
+        assert!(best_threshold(&[], &[], 1.0).unwrap().is_none());
+        assert!(matches!(
+            best_threshold(&scores, &labels[1..], 1.0),
+            Err(Error::DimensionMismatch {
+                expected: 7,
+                actual: 6
+            })
+        ));
+    }
+
+    #[test]
+    fn alert_budget_report() {
+        let scores = [0.3, 0.4, 0.5, 0.6, 0.6, 0.7, 0.8, 0.2];
+        let labels = [false, false, true, false, true, true, true, false];
+
+        let report = threshold_report(&scores, &labels).unwrap();
+        assert_eq!(report.len(), 7);
+        assert_eq!(report[0].threshold, 0.8);
+        let row = report[3];
+        assert_eq!(row.threshold, 0.5);
+        assert_eq!(row.precision, 0.8);
+        assert_eq!(row.recall, 1.0);
+        assert_eq!(row.false_positive_rate, 0.25);
+        assert_eq!(row.alert_rate, 5.0 / 8.0);
+        assert_eq!(row.alerts_per_day(8000.0), 5000.0);
+        assert_eq!(report[6].alert_rate, 1.0);
+        assert_eq!(report[6].false_positive_rate, 1.0);
+
+        let report = threshold_report(&[0.1, 0.2], &[false, false]).unwrap();
+        assert_eq!(report[0].recall, 0.0);
+        assert_eq!(report[1].false_positive_rate, 1.0);
+        assert!(threshold_report(&[], &[]).unwrap().is_empty());
+        assert!(matches!(
+            threshold_report(&scores, &labels[1..]),
+            Err(Error::DimensionMismatch {
+                expected: 8,
+                actual: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn ranking_metrics() {
+        let scores = [0.9, 0.3, 0.8, 0.5, 0.7, 0.4];
+        let labels = [true, false, false, true, false, false];
+
+        assert_eq!(precision_at_k(&scores, &labels, 1).unwrap(), 1.0);
+        assert_eq!(precision_at_k(&scores, &labels, 4).unwrap(), 0.5);
+        assert_eq!(precision_at_k(&scores, &labels, 0).unwrap(), 0.0);
+        assert_eq!(recall_at_k(&scores, &labels, 3).unwrap(), 0.5);
+        assert_eq!(recall_at_k(&scores, &labels, 10).unwrap(), 1.0);
+        let lift = lift_curve(&scores, &labels).unwrap();
+        assert_eq!(lift.len(), 6);
+        for (lift, expected) in lift.iter().zip([3.0, 1.5, 1.0, 1.5, 1.2, 1.0]) {
+            assert!((lift - expected).abs() < 1e-9);
+        }
+        assert!(lift_curve(&scores, &[false; 6]).unwrap().is_empty());
+
+        let mismatch = |error| {
+            matches!(
+                error,
+                Error::DimensionMismatch {
+                    expected: 6,
+                    actual: 5
+                }
+            )
+        };
+        assert!(mismatch(
+            precision_at_k(&scores, &labels[..5], 1).unwrap_err()
+        ));
+        assert!(mismatch(recall_at_k(&scores, &labels[..5], 1).unwrap_err()));
+        assert!(mismatch(lift_curve(&scores, &labels[..5]).unwrap_err()));
+    }
+
+    #[test]
+    fn k_fold_cross_validation() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let mut values: Vec<[f64; 2]> = uniform(1000);
+        let mut labels = vec![false; values.len()];
+        values.extend((0..50).map(|_| [rng.sample(distribution) + 20.0, rng.sample(distribution)]));
+        labels.resize(values.len(), true);
+
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let result =
+            cross_validate(&values, &options, 5, Objective::RocAuc { labels: &labels }).unwrap();
+        assert_eq!(result.folds.len(), 5);
+        assert!(result.folds.iter().all(|fold| fold.n_test == 210));
+        assert!(result.folds.iter().all(|fold| fold.n_train == 840));
+        assert!(result.mean > 0.9);
+
+        let result = cross_validate(
+            &values[..1000],
+            &options,
+            3,
+            Objective::SyntheticOutliers {
+                n_outliers: 100,
+                strategy: OutlierStrategy::InflatedBoundingBox { inflation: 2.0 },
+            },
+        )
+        .unwrap();
+        let n_test: Vec<_> = result.folds.iter().map(|fold| fold.n_test).collect();
+        assert_eq!(n_test, vec![334, 333, 333]);
+        assert!(result.mean > 0.7);
+
+        assert!(matches!(
+            cross_validate(&values, &options, 1, Objective::RocAuc { labels: &labels }),
+            Err(Error::InsufficientTrainingData)
+        ));
+        assert!(matches!(
+            cross_validate(&values, &options, 5, Objective::RocAuc { labels: &[] }),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn convergence() {
+        assert_eq!(ranks(&[0.3, 0.1, 0.3, 0.2]), vec![3.5, 1.0, 3.5, 2.0]);
+
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        let curve = forest.convergence_curve(&values[..200]);
+        assert_eq!(curve.len(), 100);
+        assert_eq!(curve[0].n_trees, 1);
+        let last = curve[99];
+        assert!((last.rank_correlation - 1.0).abs() < 1e-12);
+        let mean = values[..200]
+            .iter()
+            .map(|values| forest.score_raw(values))
+            .sum::<f64>()
+            / 200.0;
+        assert!((last.mean_score - mean).abs() < 1e-12);
+        assert!(curve[9].rank_correlation < curve[89].rank_correlation);
+    }
+
+    #[test]
+    fn tune_successive_halving() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let mut values: Vec<[f64; 2]> = uniform(900);
+        values.extend((0..100).map(|_| [rng.sample(distribution) + 20.0, 5.0]));
+        let labels: Vec<_> = (0..1000).map(|row| row >= 900).collect();
+
+        let space = SearchSpace {
+            n_trees: vec![10, 50],
+            sample_size: vec![32, 64, 2000],
+            extension_level: vec![ExtensionLevel::Standard, ExtensionLevel::Full],
+        };
+        let halving = Halving {
+            max_threads: 4,
+            ..Halving::default()
+        };
+        let tuning = successive_halving(
+            &values,
+            &ForestOptions::default(),
+            &space,
+            Objective::RocAuc { labels: &labels },
+            &halving,
+        )
+        .unwrap();
+
+        // 12 candidates of which 8 can be evaluated, then 3 and 1 on all rows
+        assert_eq!(tuning.trials.len(), 16);
+        assert_eq!(tuning.trials.last().unwrap().n_rows, 1000);
+        // samples larger than the data can not be evaluated
+        assert!(tuning
+            .trials
+            .iter()
+            .all(|trial| trial.value.is_none() == (trial.options.sample_size == 2000)));
+        assert!(tuning.value > 0.9);
+        assert_ne!(tuning.best.sample_size, 2000);
+
+        assert!(matches!(
+            successive_halving(
+                &values,
+                &ForestOptions::default(),
+                &space,
+                Objective::RocAuc {
+                    labels: &labels[1..]
+                },
+                &halving,
+            ),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,719 @@
+//! Tools for explaining the scores of a forest.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{determinate_direction, Direction, Error, Forest, ForestFloat, Node, TreeTransform};
+
+/// Comparison of a single feature against a threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition<T> {
+    LessOrEqual { feature: usize, threshold: T },
+    Greater { feature: usize, threshold: T },
+}
+
+impl<T: Float> Condition<T> {
+    /// Check whether the item satisfies the condition.
+    pub fn matches<const N: usize>(&self, values: &[T; N]) -> bool {
+        match self {
+            Self::LessOrEqual { feature, threshold } => values[*feature] <= *threshold,
+            Self::Greater { feature, threshold } => values[*feature] > *threshold,
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Condition<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LessOrEqual { feature, threshold } => {
+                write!(f, "x[{}] <= {}", feature, threshold)
+            }
+            Self::Greater { feature, threshold } => write!(f, "x[{}] > {}", feature, threshold),
+        }
+    }
+}
+
+/// A conjunction of conditions together with the average score of the items matching it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule<T> {
+    pub conditions: Vec<Condition<T>>,
+
+    /// Average anomaly score of the training items matching the rule.
+    pub score: f64,
+
+    /// Number of training items matching the rule.
+    pub support: usize,
+}
+
+impl<T: Float> Rule<T> {
+    /// Check whether the item satisfies all conditions of the rule.
+    pub fn matches<const N: usize>(&self, values: &[T; N]) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(values))
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Rule<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.conditions.is_empty() {
+            write!(f, "always")?;
+        }
+        for (i, condition) in self.conditions.iter().enumerate() {
+            if i > 0 {
+                write!(f, " AND ")?;
+            }
+            write!(f, "{}", condition)?;
+        }
+        write!(f, " => score {:.3} (n={})", self.score, self.support)
+    }
+}
+
+/// Rules approximating the scores of a forest, see [`distill`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distillation<T> {
+    /// Disjoint rules covering the complete feature space, sorted by descending score.
+    pub rules: Vec<Rule<T>>,
+
+    /// Coefficient of determination (R²) of the rules approximating the scores of the
+    /// training data.
+    pub fidelity: f64,
+}
+
+impl<T: Float> Distillation<T> {
+    /// The rule matching the item.
+    pub fn rule_for<const N: usize>(&self, values: &[T; N]) -> Option<&Rule<T>> {
+        self.rules.iter().find(|rule| rule.matches(values))
+    }
+}
+
+/// Approximate the scores of `forest` by a shallow tree of axis-aligned splits fitted to
+/// the scores of `data`, and return the leaves of this tree as human-readable rules.
+///
+/// The tree has at most `max_depth` levels and every rule matches at least
+/// `min_support` items of `data`.
+pub fn distill<'de, T, const N: usize>(
+    forest: &Forest<T, N>,
+    data: &[[T; N]],
+    max_depth: usize,
+    min_support: usize,
+) -> Distillation<T>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    let scores: Vec<_> = data.iter().map(|values| forest.score(values)).collect();
+    let mut indices: Vec<_> = (0..data.len()).collect();
+    let mut rules = vec![];
+    fit_rules(
+        data,
+        &scores,
+        &mut indices,
+        max_depth,
+        min_support.max(1),
+        &mut vec![],
+        &mut rules,
+    );
+    rules.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+    let mean = scores.iter().sum::<f64>() / scores.len().max(1) as f64;
+    let total: f64 = scores.iter().map(|score| (score - mean).powi(2)).sum();
+    let residual: f64 = data
+        .iter()
+        .zip(scores.iter())
+        .map(|(values, score)| {
+            let predicted = rules
+                .iter()
+                .find(|rule| rule.matches(values))
+                .map_or(mean, |rule| rule.score);
+            (score - predicted).powi(2)
+        })
+        .sum();
+    let fidelity = if total > 0.0 {
+        1.0 - residual / total
+    } else {
+        1.0
+    };
+
+    Distillation { rules, fidelity }
+}
+
+fn fit_rules<T: Float, const N: usize>(
+    data: &[[T; N]],
+    scores: &[f64],
+    indices: &mut [usize],
+    depth_left: usize,
+    min_support: usize,
+    conditions: &mut Vec<Condition<T>>,
+    rules: &mut Vec<Rule<T>>,
+) {
+    let split = if depth_left > 0 {
+        best_split(data, scores, indices, min_support)
+    } else {
+        None
+    };
+
+    match split {
+        Some((feature, threshold)) => {
+            // partition the indices in place
+            let mut split_idx = 0;
+            for i in 0..indices.len() {
+                if data[indices[i]][feature] <= threshold {
+                    indices.swap(i, split_idx);
+                    split_idx += 1;
+                }
+            }
+            let (left, right) = indices.split_at_mut(split_idx);
+
+            conditions.push(Condition::LessOrEqual { feature, threshold });
+            fit_rules(
+                data,
+                scores,
+                left,
+                depth_left - 1,
+                min_support,
+                conditions,
+                rules,
+            );
+            conditions.pop();
+
+            conditions.push(Condition::Greater { feature, threshold });
+            fit_rules(
+                data,
+                scores,
+                right,
+                depth_left - 1,
+                min_support,
+                conditions,
+                rules,
+            );
+            conditions.pop();
+        }
+        None => rules.push(Rule {
+            conditions: conditions.clone(),
+            score: indices.iter().map(|idx| scores[*idx]).sum::<f64>()
+                / indices.len().max(1) as f64,
+            support: indices.len(),
+        }),
+    }
+}
+
+/// Find the split of the items minimizing the sum of squared errors of the scores.
+#[allow(clippy::needless_range_loop)]
+fn best_split<T: Float, const N: usize>(
+    data: &[[T; N]],
+    scores: &[f64],
+    indices: &[usize],
+    min_support: usize,
+) -> Option<(usize, T)> {
+    if indices.len() < 2 * min_support {
+        return None;
+    }
+    let total_sum: f64 = indices.iter().map(|idx| scores[*idx]).sum();
+    let total_sq_sum: f64 = indices.iter().map(|idx| scores[*idx].powi(2)).sum();
+    let count = indices.len() as f64;
+    let mut best_sse = total_sq_sum - total_sum * total_sum / count;
+    let mut best = None;
+
+    let mut sorted = indices.to_vec();
+    for feature in 0..N {
+        sorted.sort_unstable_by(|a, b| {
+            data[*a][feature]
+                .partial_cmp(&data[*b][feature])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let (mut left_sum, mut left_sq_sum) = (0.0, 0.0);
+        for i in 0..sorted.len() - 1 {
+            let score = scores[sorted[i]];
+            left_sum += score;
+            left_sq_sum += score * score;
+
+            let left_count = (i + 1) as f64;
+            let right_count = count - left_count;
+            let (value, next_value) = (data[sorted[i]][feature], data[sorted[i + 1]][feature]);
+            if i + 1 < min_support || sorted.len() - i - 1 < min_support || value >= next_value {
+                continue;
+            }
+
+            let right_sum = total_sum - left_sum;
+            let sse = (left_sq_sum - left_sum * left_sum / left_count)
+                + (total_sq_sum - left_sq_sum - right_sum * right_sum / right_count);
+            if sse < best_sse - 1e-12 {
+                best_sse = sse;
+                let threshold = value + (next_value - value) / (T::one() + T::one());
+                best = Some((feature, threshold));
+            }
+        }
+    }
+    best
+}
+
+/// Summary of the score of a single item, see [`Forest::report`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AnomalyReport {
+    /// The score of the item, see [`Forest::score`].
+    pub score: f64,
+
+    /// Percentile (in `[0, 100]`) of the raw score among the scores of the training data.
+    /// `None` for forests without training statistics, see
+    /// [`crate::ForestOptions::record_score_statistics`].
+    pub percentile: Option<f64>,
+
+    /// The threshold the score has been compared to.
+    pub threshold: f64,
+
+    /// Whether the score exceeds the threshold.
+    pub is_anomaly: bool,
+
+    /// Share of each feature in isolating the item, see [`Forest::feature_attribution`].
+    pub attribution: Vec<f64>,
+
+    /// Variance of the raw scores of the individual trees. A high variance means the
+    /// trees disagree about the item.
+    pub tree_score_variance: f64,
+}
+
+/// Partial dependence of the anomaly score on the feature `feature_index`: for each value of
+/// `grid`, the average score of the items of `data` with this feature set to the value.
+///
+/// The grid values are scored in parallel using `n_threads` threads, like
+/// [`Forest::score_batch_threads`]. Returns one average per grid value, `NaN` if `data` is
+/// empty.
+///
+/// Fails with [`Error::InvalidOption`] if `feature_index` is not less than `N`.
+pub fn partial_dependence<'de, T, const N: usize>(
+    forest: &Forest<T, N>,
+    data: &[[T; N]],
+    feature_index: usize,
+    grid: &[T],
+    n_threads: usize,
+) -> Result<Vec<f64>, Error>
+where
+    T: ForestFloat<'de> + SampleUniform + Default + Sync,
+    StandardNormal: Distribution<T>,
+{
+    if feature_index >= N {
+        return Err(Error::InvalidOption {
+            name: "feature_index",
+        });
+    }
+    let mut averages = vec![0.0; grid.len()];
+    if grid.is_empty() {
+        return Ok(averages);
+    }
+    let chunk_size = (grid.len() + n_threads.max(1) - 1) / n_threads.max(1);
+
+    std::thread::scope(|scope| {
+        for (grid_chunk, averages_chunk) in
+            grid.chunks(chunk_size).zip(averages.chunks_mut(chunk_size))
+        {
+            scope.spawn(move || {
+                for (value, average) in grid_chunk.iter().zip(averages_chunk.iter_mut()) {
+                    let sum: f64 = data
+                        .iter()
+                        .map(|values| {
+                            let mut values = *values;
+                            values[feature_index] = *value;
+                            forest.score(&values)
+                        })
+                        .sum();
+                    *average = sum / data.len() as f64;
+                }
+            });
+        }
+    });
+    Ok(averages)
+}
+
+/// Evaluate the scores of `forest` on a 2D grid of the features `x_feature` and `y_feature`,
+/// with all other features set to the values of `base`.
+///
+/// Returns one row per value of `y_grid` with one score per value of `x_grid`, e.g. to plot
+/// the decision surface of forests with different extension levels.
+///
+/// Panics if a feature index is not less than `N`.
+pub fn score_map<'de, T, const N: usize>(
+    forest: &Forest<T, N>,
+    base: &[T; N],
+    (x_feature, x_grid): (usize, &[T]),
+    (y_feature, y_grid): (usize, &[T]),
+) -> Vec<Vec<f64>>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    assert!(x_feature < N && y_feature < N, "feature index out of range");
+    y_grid
+        .iter()
+        .map(|y| {
+            x_grid
+                .iter()
+                .map(|x| {
+                    let mut values = *base;
+                    values[x_feature] = *x;
+                    values[y_feature] = *y;
+                    forest.score(&values)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A node passed by an item, see [`TreeTrace`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TraceStep {
+    /// Depth of the node, `0` for the root.
+    pub depth: usize,
+
+    /// The features with a non-zero coordinate in the normal of the hyperplane.
+    pub features: Vec<usize>,
+
+    /// Signed distance of the item to the hyperplane. Items with a distance `<= 0` continue
+    /// in the left child.
+    pub distance: f64,
+}
+
+/// The path of an item through a tree, see [`Forest::score_trace`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TreeTrace {
+    /// The internal nodes passed by the item, starting at the root.
+    pub steps: Vec<TraceStep>,
+
+    /// Index of the leaf the item ends up in, see [`Forest::leaf_indices`].
+    pub leaf: u32,
+
+    /// Number of training samples of the leaf.
+    pub num_samples: usize,
+
+    /// Path length of the item including the adjustment for the samples of the leaf,
+    /// normalized by the average path length of the tree.
+    pub normalized_path_length: f64,
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Record the path of an item through each tree, for custom explanations and debugging.
+    ///
+    /// The features and distances refer to the space of the tree, i.e. after preprocessing
+    /// and the rotation or feature map of the tree, if any.
+    pub fn score_trace(&self, values: &[T; N]) -> Vec<TreeTrace> {
+        let values = &self.pipeline.transform(values);
+        self.trees
+            .iter()
+            .map(|tree| {
+                let values = &tree.transform(values);
+                let mut steps = vec![];
+                let mut node = &tree.root;
+                loop {
+                    match node {
+                        Node::Ex(ex_node) => {
+                            let depth = steps.len() as f64;
+                            return TreeTrace {
+                                steps,
+                                leaf: ex_node.id,
+                                num_samples: ex_node.num_samples,
+                                normalized_path_length: (depth + ex_node.path_length)
+                                    * tree.path_length_c_inv,
+                            };
+                        }
+                        Node::In(in_node) => {
+                            let to_f64 = |v: T| v.to_f64().unwrap_or(f64::NAN);
+                            let norm = in_node
+                                .n
+                                .iter()
+                                .map(|n| to_f64(*n).powi(2))
+                                .sum::<f64>()
+                                .sqrt();
+                            let projection = values
+                                .iter()
+                                .zip(in_node.p.iter())
+                                .zip(in_node.n.iter())
+                                .map(|((v, p), n)| to_f64(*v - *p) * to_f64(*n))
+                                .sum::<f64>();
+                            steps.push(TraceStep {
+                                depth: steps.len(),
+                                features: (0..N).filter(|i| !in_node.n[*i].is_zero()).collect(),
+                                distance: projection / norm,
+                            });
+                            node = match determinate_direction(values, &in_node.n, &in_node.p) {
+                                Direction::Left => &in_node.left,
+                                Direction::Right => &in_node.right,
+                            };
+                        }
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Share of each feature in isolating the item, summing to `1.0`.
+    ///
+    /// Each hyperplane on the path of the item distributes its weight over the features in
+    /// proportion to the absolute components of its normal. The weight of a hyperplane is
+    /// the share of the samples of its node it separates from the item, and trees isolating
+    /// the item after a shorter path get a higher weight. The features are the ones after
+    /// preprocessing, see [`crate::ForestOptions::preprocessing`]; trees using a random
+    /// feature map are ignored. All shares are zero if no tree splits the item.
+    pub fn feature_attribution(&self, values: &[T; N]) -> [f64; N] {
+        let values = &self.pipeline.transform(values);
+        let mut attribution = [0.0; N];
+        for tree in self.trees.iter() {
+            let rotation = match &tree.transform {
+                Some(TreeTransform::FeatureMap { .. }) => continue,
+                Some(TreeTransform::Rotation(matrix)) => Some(matrix),
+                None => None,
+            };
+            let tree_values = &tree.transform(values);
+            let weight = 1.0 / tree.adjusted_path_length(values).max(1.0);
+
+            let mut node = &tree.root;
+            while let Node::In(in_node) = node {
+                let mut shares = [0.0; N];
+                for (i, share) in shares.iter_mut().enumerate() {
+                    // rotate the normal back into the space of the features
+                    *share = match rotation {
+                        Some(matrix) => (0..N)
+                            .map(|j| matrix[j * N + i] * in_node.n[j].to_f64().unwrap_or(0.0))
+                            .sum::<f64>(),
+                        None => in_node.n[i].to_f64().unwrap_or(0.0),
+                    }
+                    .abs();
+                }
+                let next = match determinate_direction(tree_values, &in_node.n, &in_node.p) {
+                    Direction::Left => &in_node.left,
+                    Direction::Right => &in_node.right,
+                };
+                let next_samples = match next.as_ref() {
+                    Node::Ex(ex_node) => ex_node.num_samples,
+                    Node::In(in_node) => in_node.num_samples,
+                };
+                let separated = 1.0 - next_samples as f64 / in_node.num_samples.max(1) as f64;
+
+                let total: f64 = shares.iter().sum();
+                if total > 0.0 {
+                    for (a, share) in attribution.iter_mut().zip(shares) {
+                        *a += weight * separated * share / total;
+                    }
+                }
+                node = next;
+            }
+        }
+
+        let total: f64 = attribution.iter().sum();
+        if total > 0.0 {
+            attribution.iter_mut().for_each(|a| *a /= total);
+        }
+        attribution
+    }
+
+    /// Summarize the score of an item, compared to `threshold` on the scale of
+    /// [`Forest::score`], in a single report which can e.g. be attached to an alert.
+    pub fn report(&self, values: &[T; N], threshold: f64) -> AnomalyReport {
+        let transformed = &self.pipeline.transform(values);
+        let tree_scores: Vec<_> = self
+            .trees
+            .iter()
+            .map(|tree| {
+                self.score_from_path_length(
+                    tree.adjusted_path_length(transformed) * tree.path_length_c_inv,
+                )
+            })
+            .collect();
+        let count = tree_scores.len().max(1) as f64;
+        let mean = tree_scores.iter().sum::<f64>() / count;
+        let tree_score_variance =
+            tree_scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count;
+
+        let score = self.score(values);
+        AnomalyReport {
+            score,
+            percentile: self
+                .score_statistics
+                .as_ref()
+                .map(|statistics| statistics.percentile(self.score_raw(values))),
+            threshold,
+            is_anomaly: score > threshold,
+            attribution: self.feature_attribution(values).to_vec(),
+            tree_score_variance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+    use rand_distr::Normal;
+
+    use super::{distill, partial_dependence, score_map};
+    use crate::datasets::uniform;
+    use crate::{Error, ExtensionLevel, Forest, ForestOptions};
+
+    #[test]
+    fn distill_rules() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let mut values: Vec<[f64; 2]> = uniform(1000);
+        // a sparse region of outliers with large values in the first dimension
+        values.extend((0..20).map(|_| [rng.sample(distribution) + 30.0, 5.0]));
+
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let distillation = distill(&forest, &values, 2, 10);
+
+        assert!(distillation.rules.len() <= 4);
+        assert_eq!(
+            distillation
+                .rules
+                .iter()
+                .map(|rule| rule.support)
+                .sum::<usize>(),
+            values.len()
+        );
+        assert!(distillation.fidelity > 0.0);
+
+        // the rule with the highest score describes (part of) the outliers
+        let top_rule = &distillation.rules[0];
+        assert!(!top_rule.matches(&[5.0, 5.0]));
+        assert!(top_rule.to_string().contains("x[0] >"));
+        let outlier_rule = distillation.rule_for(&[35.0, 5.0]).unwrap();
+        let normal_rule = distillation.rule_for(&[5.0, 5.0]).unwrap();
+        assert!(outlier_rule.score > normal_rule.score);
+    }
+
+    #[test]
+    fn report_outlier() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Normal::new(5., 1.).unwrap();
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        for extension_level in [ExtensionLevel::Standard, ExtensionLevel::Full] {
+            let options = ForestOptions {
+                n_trees: 100,
+                sample_size: 256,
+                extension_level,
+                record_score_statistics: true,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+            let report = forest.report(&[30.0, 5.0], 0.55);
+            assert!(report.is_anomaly);
+            assert_eq!(report.score, forest.score(&[30.0, 5.0]));
+            assert!(report.percentile.unwrap() > 95.0);
+            assert_eq!(report.attribution.len(), 2);
+            assert!((report.attribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            if extension_level == ExtensionLevel::Standard {
+                // only splits of the first dimension separate the item from the data
+                assert!(report.attribution[0] > 0.6);
+            }
+
+            let report = forest.report(&[5.0, 5.0], 0.55);
+            assert!(!report.is_anomaly);
+            assert!(report.percentile.unwrap() < 50.0);
+            assert!(report.tree_score_variance > 0.0);
+        }
+    }
+
+    #[test]
+    fn trace_paths() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        let item = [2.0, 7.0];
+        let traces = forest.score_trace(&item);
+        assert_eq!(traces.len(), 20);
+        let leaves: Vec<_> = traces.iter().map(|trace| trace.leaf).collect();
+        assert_eq!(leaves, forest.leaf_indices(&item));
+        for trace in traces.iter() {
+            for (depth, step) in trace.steps.iter().enumerate() {
+                assert_eq!(step.depth, depth);
+                // axis-parallel hyperplanes with the standard extension level
+                assert_eq!(step.features.len(), 1);
+            }
+        }
+        let mean = traces
+            .iter()
+            .map(|trace| trace.normalized_path_length)
+            .sum::<f64>()
+            / 20.0;
+        assert!((forest.score_raw(&item) - (-mean).exp2()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn partial_dependence_curve() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        let grid = [-20.0, 5.0, 30.0];
+        let curve = partial_dependence(&forest, &values[..200], 0, &grid, 2).unwrap();
+        assert_eq!(curve.len(), 3);
+        // values outside of the training range are more anomalous on average
+        assert!(curve[0] > curve[1]);
+        assert!(curve[2] > curve[1]);
+
+        let mut shifted = values[0];
+        shifted[1] = 30.0;
+        for n_threads in [0, 1, 4] {
+            let curve = partial_dependence(&forest, &values[..1], 1, &[30.0], n_threads).unwrap();
+            assert_eq!(curve, vec![forest.score(&shifted)]);
+        }
+        assert!(partial_dependence(&forest, &values, 1, &[], 2)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            partial_dependence(&forest, &values, 2, &grid, 2),
+            Err(Error::InvalidOption {
+                name: "feature_index"
+            })
+        ));
+    }
+
+    #[test]
+    fn score_map_grid() {
+        let values: Vec<[f64; 3]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        let x_grid = [-10.0, 5.0, 20.0];
+        let y_grid = [5.0, 30.0];
+        let map = score_map(&forest, &[5.0, 5.0, 5.0], (0, &x_grid), (2, &y_grid));
+        assert_eq!(map.len(), 2);
+        assert!(map.iter().all(|row| row.len() == 3));
+        assert_eq!(map[1][0], forest.score(&[-10.0, 5.0, 30.0]));
+        assert!(map[0][1] < map[0][0]);
+        assert!(map[0][1] < map[1][1]);
+    }
+}
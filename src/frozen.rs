@@ -0,0 +1,265 @@
+//! A frozen representation of a forest for scoring, with the nodes of all trees stored in a
+//! single array and linked by `u32` indices instead of boxes.
+//!
+//! The nodes of each tree are stored in preorder, so the left child of an internal node
+//! directly follows it and only the index of the right child is stored. Without pointers
+//! the node array can be moved, copied or mapped into memory as a whole, and traversing a
+//! tree touches consecutive memory.
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::math;
+use crate::pipeline::Pipeline;
+use crate::stats::ScoreStatistics;
+use crate::{
+    determinate_direction, Direction, Forest, ForestFloat, Node, NonFiniteHandling, ScoreScale,
+    TreeTransform,
+};
+
+/// A node of a [`FrozenForest`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct FrozenNode<T, const N: usize> {
+    /// Normal vector of the hyperplane, zero for leaves.
+    pub normal: [T; N],
+
+    /// Point through which the hyperplane passes, zero for leaves.
+    pub intercept: [T; N],
+
+    /// Contribution of a leaf to the path length of the items reaching it, on top of its
+    /// depth. Zero for internal nodes.
+    pub path_length: f64,
+
+    /// Index of the right child within the node array, `0` for leaves.
+    pub right: u32,
+
+    /// Number of training samples present at the node.
+    pub num_samples: u32,
+}
+
+impl<T, const N: usize> FrozenNode<T, N> {
+    pub fn is_leaf(&self) -> bool {
+        self.right == 0
+    }
+}
+
+/// A tree of a [`FrozenForest`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct FrozenTree {
+    /// Index of the root node within the node array.
+    pub(crate) root: u32,
+
+    /// Inverse of the average path length normalizing the path lengths of the tree.
+    path_length_c_inv: f64,
+
+    transform: Option<TreeTransform>,
+}
+
+/// Everything of a [`FrozenForest`] but its nodes, shared with the `MappedForest` of the
+/// `mapped` feature, which reads the nodes from a byte buffer.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct FrozenModel {
+    pipeline: Pipeline,
+    score_scale: ScoreScale,
+    score_statistics: Option<ScoreStatistics>,
+    non_finite_handling: NonFiniteHandling,
+    pub(crate) trees: Vec<FrozenTree>,
+}
+
+impl FrozenModel {
+    /// Whether the pipeline and the transforms of the trees fit items with `N` features.
+    #[cfg(feature = "mapped")]
+    pub(crate) fn is_valid<const N: usize>(&self) -> bool {
+        self.pipeline.is_valid::<N>()
+            && self.trees.iter().all(|tree| {
+                tree.transform
+                    .as_ref()
+                    .map_or(true, |transform| transform.is_valid::<N>())
+            })
+    }
+
+    pub(crate) fn scale_score(&self, score: f64) -> f64 {
+        self.score_scale
+            .apply(score, self.score_statistics.as_ref())
+    }
+
+    /// Raw score of an item, where `node` returns the node at an index.
+    #[inline]
+    pub(crate) fn score_raw<T, F, const N: usize>(&self, values: &[T; N], node: F) -> f64
+    where
+        T: Float,
+        F: Fn(usize) -> FrozenNode<T, N>,
+    {
+        if let Some(score) = self.non_finite_handling.score() {
+            if values.iter().any(|value| !value.is_finite()) {
+                return score;
+            }
+        }
+        let values = &self.pipeline.transform(values);
+        let path_length: f64 = self
+            .trees
+            .iter()
+            .map(|tree| path_length(tree, values, &node) * tree.path_length_c_inv)
+            .sum();
+        math::exp2(-path_length / self.trees.len() as f64)
+    }
+}
+
+#[inline]
+fn path_length<T, F, const N: usize>(tree: &FrozenTree, values: &[T; N], node: &F) -> f64
+where
+    T: Float,
+    F: Fn(usize) -> FrozenNode<T, N>,
+{
+    let values = &match &tree.transform {
+        Some(transform) => transform.apply(values),
+        None => *values,
+    };
+    let mut index = tree.root as usize;
+    let mut depth = 0.0;
+    loop {
+        let node = node(index);
+        if node.is_leaf() {
+            return depth + node.path_length;
+        }
+        index = match determinate_direction(values, &node.normal, &node.intercept) {
+            Direction::Left => index + 1,
+            Direction::Right => node.right as usize,
+        };
+        depth += 1.0;
+    }
+}
+
+/// A forest frozen for scoring, see [`Forest::freeze`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrozenForest<T, const N: usize> {
+    pub(crate) model: FrozenModel,
+    nodes: Vec<FrozenNode<T, N>>,
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Convert the forest into a frozen representation for scoring, which scores items
+    /// exactly like the forest.
+    ///
+    /// The number of nodes of all trees and the number of samples per node are limited to
+    /// `u32`.
+    pub fn freeze(&self) -> FrozenForest<T, N> {
+        let mut nodes = vec![];
+        let trees = self
+            .trees
+            .iter()
+            .map(|tree| {
+                let root = nodes.len() as u32;
+                push_node(&mut nodes, &tree.root);
+                FrozenTree {
+                    root,
+                    path_length_c_inv: tree.path_length_c_inv,
+                    transform: tree.transform.clone(),
+                }
+            })
+            .collect();
+        FrozenForest {
+            model: FrozenModel {
+                pipeline: self.pipeline.clone(),
+                score_scale: self.score_scale,
+                score_statistics: self.score_statistics.clone(),
+                non_finite_handling: self.non_finite_handling,
+                trees,
+            },
+            nodes,
+        }
+    }
+}
+
+fn push_node<T: Float, const N: usize>(nodes: &mut Vec<FrozenNode<T, N>>, node: &Node<T, N>) {
+    let index = nodes.len();
+    match node {
+        Node::Ex(ex_node) => nodes.push(FrozenNode {
+            normal: [T::zero(); N],
+            intercept: [T::zero(); N],
+            path_length: ex_node.path_length,
+            right: 0,
+            num_samples: ex_node.num_samples as u32,
+        }),
+        Node::In(in_node) => {
+            nodes.push(FrozenNode {
+                normal: in_node.n,
+                intercept: in_node.p,
+                path_length: 0.0,
+                right: 0,
+                num_samples: in_node.num_samples as u32,
+            });
+            push_node(nodes, &in_node.left);
+            nodes[index].right = nodes.len() as u32;
+            push_node(nodes, &in_node.right);
+        }
+    }
+}
+
+impl<'de, T, const N: usize> FrozenForest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// The nodes of all trees, each tree in preorder.
+    pub fn nodes(&self) -> &[FrozenNode<T, N>] {
+        &self.nodes
+    }
+
+    /// Number of trees of the forest.
+    pub fn n_trees(&self) -> usize {
+        self.model.trees.len()
+    }
+
+    /// Compute the anomaly score for an item, see [`Forest::score`].
+    pub fn score(&self, values: &[T; N]) -> f64 {
+        self.model.scale_score(self.score_raw(values))
+    }
+
+    /// Compute the anomaly score for an item without applying the score scale, see
+    /// [`Forest::score_raw`].
+    pub fn score_raw(&self, values: &[T; N]) -> f64 {
+        self.model.score_raw(values, |index| self.nodes[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datasets::uniform;
+    use crate::{Forest, ForestOptions, ScoreScale};
+
+    #[test]
+    fn frozen_forest_3d_f64() {
+        let values: Vec<[f64; 3]> = uniform(1000);
+        for rotation_subset_size in [None, Some(2)] {
+            let options = ForestOptions {
+                n_trees: 20,
+                sample_size: 64,
+                rotation_subset_size,
+                score_scale: ScoreScale::Percentile,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+            let frozen = forest.freeze();
+            assert_eq!(frozen.n_trees(), 20);
+            assert_eq!(
+                frozen.nodes().iter().filter(|node| node.is_leaf()).count() as u32,
+                forest.leaf_counts().iter().sum::<u32>()
+            );
+            for values in values[..100].iter().chain([[20.0, 20.0, 20.0]].iter()) {
+                assert_eq!(frozen.score(values), forest.score(values));
+                assert_eq!(frozen.score_raw(values), forest.score_raw(values));
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Fusion of the scores of a forest with those of other detectors, e.g. z-scores of single
+//! features or the reconstruction error of an autoencoder, into a single anomaly score.
+//!
+//! Detectors produce scores on unrelated scales, so every scorer is normalized by the
+//! distribution of its scores on reference data, usually the training data, before the
+//! scores are combined.
+
+use crate::stats::ScoreStatistics;
+use crate::{AnomalyScorer, Error};
+
+/// How the scores of a single scorer are normalized before they are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Normalization {
+    /// The percentile of the score among the reference scores, in `[0, 100]`.
+    #[default]
+    Percentile,
+
+    /// The number of standard deviations the score lies above the mean reference score.
+    ZScore,
+
+    /// The score mapped linearly from the range of the reference scores to `[0, 1]`.
+    /// Scores outside of that range are not clamped.
+    MinMax,
+}
+
+impl Normalization {
+    fn apply(self, score: f64, statistics: &ScoreStatistics) -> f64 {
+        match self {
+            Self::Percentile => statistics.percentile(score),
+            Self::ZScore => statistics.z_score(score),
+            Self::MinMax => {
+                let (min, max) = (statistics.min(), statistics.max());
+                if max > min {
+                    (score - min) / (max - min)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// How the normalized scores of all scorers are combined.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FusionStrategy {
+    /// The mean percentile of the scores among the reference scores of their scorer, in
+    /// `[0, 100]`.
+    #[default]
+    AverageRank,
+
+    /// The highest normalized score, so an item is anomalous if any scorer considers it
+    /// anomalous.
+    Max(Normalization),
+
+    /// The sum of the normalized scores multiplied by `weights`, one per scorer.
+    WeightedSum {
+        weights: Vec<f64>,
+        normalization: Normalization,
+    },
+}
+
+/// Several scorers of items with the same number of dimensions combined into a single
+/// anomaly score.
+///
+/// A [`crate::Forest`] is a scorer itself, other detectors are added by implementing
+/// [`AnomalyScorer`].
+pub struct FusedScorer<'a> {
+    scorers: Vec<Box<dyn AnomalyScorer + 'a>>,
+    statistics: Vec<ScoreStatistics>,
+    strategy: FusionStrategy,
+}
+
+impl<'a> FusedScorer<'a> {
+    /// Combine `scorers`, normalizing each of them by its scores of the `reference` items.
+    ///
+    /// Fails with [`Error::InsufficientTrainingData`] without scorers or reference items,
+    /// with [`Error::DimensionMismatch`] when the scorers differ in their number of
+    /// dimensions or the number of weights differs from the number of scorers, and with
+    /// [`Error::RaggedRow`] when a reference item has the wrong number of values.
+    pub fn new<R: AsRef<[f64]>>(
+        scorers: Vec<Box<dyn AnomalyScorer + 'a>>,
+        reference: &[R],
+        strategy: FusionStrategy,
+    ) -> Result<Self, Error> {
+        let dims = scorers
+            .first()
+            .ok_or(Error::InsufficientTrainingData)?
+            .dims();
+        if let Some(scorer) = scorers.iter().find(|scorer| scorer.dims() != dims) {
+            return Err(Error::DimensionMismatch {
+                expected: dims,
+                actual: scorer.dims(),
+            });
+        }
+        if let FusionStrategy::WeightedSum { weights, .. } = &strategy {
+            if weights.len() != scorers.len() {
+                return Err(Error::DimensionMismatch {
+                    expected: scorers.len(),
+                    actual: weights.len(),
+                });
+            }
+        }
+        if let Some((row, values)) = reference
+            .iter()
+            .enumerate()
+            .find(|(_, values)| values.as_ref().len() != dims)
+        {
+            return Err(Error::RaggedRow {
+                row,
+                expected: dims,
+                actual: values.as_ref().len(),
+            });
+        }
+
+        let statistics = scorers
+            .iter()
+            .map(|scorer| {
+                let scores = reference
+                    .iter()
+                    .map(|values| scorer.score_slice(values.as_ref()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                ScoreStatistics::new(scores).ok_or(Error::InsufficientTrainingData)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            scorers,
+            statistics,
+            strategy,
+        })
+    }
+
+    /// Number of scorers.
+    pub fn n_scorers(&self) -> usize {
+        self.scorers.len()
+    }
+
+    /// The normalized score of every scorer for an item, in the order of the scorers. For
+    /// [`FusionStrategy::AverageRank`] these are the percentiles.
+    pub fn normalized_scores(&self, values: &[f64]) -> Result<Vec<f64>, Error> {
+        let normalization = match &self.strategy {
+            FusionStrategy::AverageRank => Normalization::Percentile,
+            FusionStrategy::Max(normalization)
+            | FusionStrategy::WeightedSum { normalization, .. } => *normalization,
+        };
+        self.scorers
+            .iter()
+            .zip(self.statistics.iter())
+            .map(|(scorer, statistics)| {
+                Ok(normalization.apply(scorer.score_slice(values)?, statistics))
+            })
+            .collect()
+    }
+}
+
+impl AnomalyScorer for FusedScorer<'_> {
+    fn score_slice(&self, values: &[f64]) -> Result<f64, Error> {
+        let scores = self.normalized_scores(values)?;
+        Ok(match &self.strategy {
+            FusionStrategy::AverageRank => scores.iter().sum::<f64>() / scores.len() as f64,
+            FusionStrategy::Max(_) => scores.into_iter().fold(f64::NEG_INFINITY, f64::max),
+            FusionStrategy::WeightedSum { weights, .. } => {
+                scores.iter().zip(weights.iter()).map(|(s, w)| s * w).sum()
+            }
+        })
+    }
+
+    fn dims(&self) -> usize {
+        self.scorers[0].dims()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FusedScorer, FusionStrategy, Normalization};
+    use crate::datasets::uniform;
+    use crate::{AnomalyScorer, Error, Forest, ForestOptions};
+
+    /// Distance of the first feature from `5.0`, a detector the forest knows nothing about.
+    struct FirstFeature;
+
+    impl AnomalyScorer for FirstFeature {
+        fn score_slice(&self, values: &[f64]) -> Result<f64, Error> {
+            Ok((values[0] - 5.0).abs())
+        }
+
+        fn dims(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn fused_scores() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        let scorers = || -> Vec<Box<dyn AnomalyScorer>> {
+            vec![Box::new(forest.clone()), Box::new(FirstFeature)]
+        };
+
+        let fused = FusedScorer::new(scorers(), &values, FusionStrategy::default()).unwrap();
+        assert_eq!(fused.n_scorers(), 2);
+        assert_eq!(fused.dims(), 2);
+        let normalized = fused.normalized_scores(&[20.0, 20.0]).unwrap();
+        assert!(normalized[0] > 95.0);
+        assert_eq!(normalized[1], 100.0);
+        let center = fused.score_slice(&[5.0, 5.0]).unwrap();
+        assert!(center < 50.0);
+        let far = fused.score_slice(&[20.0, 20.0]).unwrap();
+        assert_eq!(far, (normalized[0] + normalized[1]) / 2.0);
+
+        // only the forest flags the second feature, which the maximum picks up
+        let max = FusedScorer::new(
+            scorers(),
+            &values,
+            FusionStrategy::Max(Normalization::MinMax),
+        )
+        .unwrap();
+        let item = [5.0, 20.0];
+        let normalized = max.normalized_scores(&item).unwrap();
+        assert!(normalized[0] > max.normalized_scores(&[5.0, 5.0]).unwrap()[0]);
+        assert!(normalized[1] <= 0.0);
+        assert_eq!(max.score_slice(&item).unwrap(), normalized[0]);
+
+        let weighted = FusedScorer::new(
+            scorers(),
+            &values,
+            FusionStrategy::WeightedSum {
+                weights: vec![0.0, 2.0],
+                normalization: Normalization::ZScore,
+            },
+        )
+        .unwrap();
+        let normalized = weighted.normalized_scores(&[9.0, 5.0]).unwrap();
+        assert_eq!(
+            weighted.score_slice(&[9.0, 5.0]).unwrap(),
+            2.0 * normalized[1]
+        );
+
+        assert!(matches!(
+            FusedScorer::new(
+                scorers(),
+                &values,
+                FusionStrategy::WeightedSum {
+                    weights: vec![1.0],
+                    normalization: Normalization::ZScore,
+                },
+            ),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            FusedScorer::new(
+                scorers(),
+                &[vec![1.0, 2.0], vec![1.0]],
+                FusionStrategy::default()
+            ),
+            Err(Error::RaggedRow { row: 1, .. })
+        ));
+        assert!(matches!(
+            FusedScorer::new(scorers(), &[] as &[[f64; 2]], FusionStrategy::default()),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+//! A shared handle to a forest which can be replaced while it is in use.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+use serde::de::DeserializeOwned;
+
+use crate::{Error, Forest, ForestFloat};
+
+/// Holds the forest used by a long-running service.
+///
+/// Loading and swapping the forest are lock-free, so a newly trained forest can be swapped
+/// in while other threads keep scoring. Items being scored during a swap are scored
+/// completely by either the old or the new forest.
+pub struct ModelHandle<T, const N: usize> {
+    current: ArcSwap<Forest<T, N>>,
+}
+
+impl<T, const N: usize> ModelHandle<T, N> {
+    pub fn new(forest: Forest<T, N>) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(forest),
+        }
+    }
+
+    /// The current forest. It stays valid when the forest of the handle is swapped.
+    pub fn load(&self) -> Arc<Forest<T, N>> {
+        self.current.load_full()
+    }
+
+    /// Replace the forest, returning the previous one.
+    pub fn swap(&self, forest: Forest<T, N>) -> Arc<Forest<T, N>> {
+        self.current.swap(Arc::new(forest))
+    }
+
+    /// Replace the forest by the forest serialized as JSON in the file at `path`.
+    ///
+    /// The current forest is kept when reading or parsing the file fails. Both fail with
+    /// [`Error::Io`], files not containing a valid forest with an error of kind
+    /// [`std::io::ErrorKind::InvalidData`] describing the problem.
+    pub fn reload_from<P: AsRef<Path>>(&self, path: P) -> Result<(), Error>
+    where
+        Forest<T, N>: DeserializeOwned,
+    {
+        let reader = BufReader::new(File::open(path).map_err(Error::Io)?);
+        let forest = serde_json::from_reader(reader).map_err(|err| Error::Io(err.into()))?;
+        self.swap(forest);
+        Ok(())
+    }
+}
+
+impl<'de, T, const N: usize> ModelHandle<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Compute the anomaly score for an item using the current forest.
+    pub fn score(&self, values: &[T; N]) -> f64 {
+        self.current.load().score(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::ModelHandle;
+    use crate::{Error, Forest, ForestOptions};
+
+    fn make_forest(offset: f64) -> Forest<f64, 2> {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(offset, offset + 1.0);
+        let values: Vec<_> = (0..500)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 100,
+            ..ForestOptions::default()
+        };
+        Forest::from_slice(values.as_slice(), &options).unwrap()
+    }
+
+    #[test]
+    fn reload_model() {
+        let handle = Arc::new(ModelHandle::new(make_forest(0.0)));
+        let before = handle.load();
+        assert!(handle.score(&[10.5, 10.5]) > handle.score(&[0.5, 0.5]));
+
+        let path = std::env::temp_dir().join(format!("eif-handle-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&make_forest(10.0)).unwrap()).unwrap();
+
+        let scorer = {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                (0..1000)
+                    .map(|_| handle.score(&[0.5, 0.5]))
+                    .all(|score| score > 0.0)
+            })
+        };
+        handle.reload_from(&path).unwrap();
+        assert!(scorer.join().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(handle.score(&[0.5, 0.5]) > handle.score(&[10.5, 10.5]));
+        // the forest loaded before keeps working
+        assert!(before.score(&[10.5, 10.5]) > before.score(&[0.5, 0.5]));
+
+        assert!(matches!(
+            handle.reload_from(path.with_extension("missing")),
+            Err(Error::Io(_))
+        ));
+
+        std::fs::write(&path, "{\"trees\": 1}").unwrap();
+        let err = handle.reload_from(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(&err, Error::Io(err) if err.kind() == std::io::ErrorKind::InvalidData));
+        assert!(std::error::Error::source(&err)
+            .unwrap()
+            .to_string()
+            .contains("line 1"));
+        assert!(handle.score(&[0.5, 0.5]) > handle.score(&[10.5, 10.5]));
+    }
+}
@@ -0,0 +1,76 @@
+//! Contribution of the external nodes of the trees to the path lengths of the items.
+
+use crate::c_factor;
+
+/// An external node reached by an item.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LeafContext {
+    /// Number of training samples which ended up in the leaf.
+    pub num_samples: usize,
+
+    /// Depth of the leaf, `0` for a tree consisting of a single leaf.
+    pub depth: usize,
+
+    /// Number of samples the tree was built from.
+    pub sample_size: usize,
+}
+
+/// Defines how much an external node adds to the path length of an item ending up in it,
+/// on top of the depth of the node.
+pub trait LeafScore {
+    fn leaf_path_length(&self, leaf: &LeafContext) -> f64;
+}
+
+/// The adjustment of the original algorithm: the average path length of an unsuccessful
+/// search in a binary search tree built from the samples of the leaf, accounting for the
+/// subtree which has not been built because of the depth limit.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AveragePathLength;
+
+impl LeafScore for AveragePathLength {
+    fn leaf_path_length(&self, leaf: &LeafContext) -> f64 {
+        if leaf.num_samples <= 1 {
+            0.0
+        } else {
+            c_factor(leaf.num_samples)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::{LeafContext, LeafScore};
+    use crate::{Forest, ForestOptions};
+
+    /// Ignores the number of samples of the leaves.
+    struct DepthOnly;
+
+    impl LeafScore for DepthOnly {
+        fn leaf_path_length(&self, _leaf: &LeafContext) -> f64 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn custom_leaf_score() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 2.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 100,
+            max_tree_depth: Some(3),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        // with a depth of at most 3, the leaves reached by normal items hold many samples
+        let normal = [1.5, 1.5];
+        assert!(forest.score_with(&normal, &DepthOnly) > forest.score(&normal));
+    }
+}
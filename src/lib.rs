@@ -4,16 +4,31 @@
 //! and implemented in [https://github.com/sahandha/eif](https://github.com/sahandha/eif). For a detailed description see the paper or the
 //! github repository.
 //!
-//! This crate requires rust >= 1.51 as it makes use of `min_const_generics`.
+//! This crate requires rust >= 1.63 as it makes use of `min_const_generics` and scoped threads.
 //!
-//! Includes optional serde support with the `serde` feature.
+//! Includes optional serde support with the `serde` feature. The `nalgebra` feature allows
+//! training and scoring with `nalgebra` vectors. The `handle` feature adds
+//! [`handle::ModelHandle`] for swapping forests in long-running services. The `mapped`
+//! feature adds `mapped::MappedForest`, which scores a frozen forest directly from a byte
+//! buffer shared by several processes.
+//!
+//! ## Deterministic scoring
+//!
+//! Scores are computed from the trees in a fixed order, independent of the number of
+//! threads. The transcendental functions like `exp2` and `ln` of the standard library
+//! however use the math library of the platform, whose results may differ in the last
+//! bits, e.g. between `x86_64` and `aarch64`. With the `deterministic` feature these are
+//! replaced by implementations using only basic arithmetic operations, which IEEE 754
+//! defines exactly, so the same serialized model yields bit-identical scores on all
+//! platforms. The scores then differ from those computed without the feature by a few
+//! units in the last place.
 //!
 //! ## Example
 //!
 //! ```rust
 //! use rand::distributions::Uniform;
 //! use rand::Rng;
-//! use extended_isolation_forest::{Forest, ForestOptions};
+//! use extended_isolation_forest::{ExtensionLevel, Forest, ForestOptions};
 //!
 //! fn make_f64_forest() -> Forest<f64, 3> {
 //!     let rng = &mut rand::thread_rng();
@@ -27,7 +42,8 @@
 //!         n_trees: 150,
 //!         sample_size: 200,
 //!         max_tree_depth: None,
-//!         extension_level: 1,
+//!         extension_level: ExtensionLevel::Level(1),
+//!         ..ForestOptions::default()
 //!     };
 //!     Forest::from_slice(values.as_slice(), &options).unwrap()
 //! }
@@ -47,24 +63,80 @@
 //! ```
 
 use std::boxed::Box;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
 use std::result::Result;
+use std::time::{Duration, Instant};
 
-use num_traits::{Float, FloatConst};
+use num_traits::Float;
 use rand::{
-    distributions::{uniform::SampleUniform, Uniform},
-    rngs::ThreadRng,
-    seq::{IteratorRandom, SliceRandom},
-    Rng,
+    distributions::uniform::SampleUniform,
+    rngs::{StdRng, ThreadRng},
+    seq::{index, SliceRandom},
+    Rng, SeedableRng,
 };
 use rand_distr::{Distribution, StandardNormal};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 pub use crate::error::Error;
+use crate::leaf::{AveragePathLength, LeafContext, LeafScore};
+use crate::linalg::symmetric_eigen;
+use crate::pipeline::{Pipeline, Step};
+use crate::profile::Profiler;
+use crate::ranked::Ranked;
+use crate::sample::Sample;
+use crate::split::{Hyperplane, RandomHyperplane, SplitContext, SplitStrategy};
+use crate::stats::{quantile_sorted, sort_scores, ScoreStatistics};
 
+pub mod bundle;
+pub mod compact;
+pub mod convert;
+pub mod datasets;
+pub mod drift;
+pub mod entity;
 mod error;
+pub mod eval;
+pub mod explain;
+pub mod frozen;
+pub mod fusion;
+#[cfg(feature = "handle")]
+pub mod handle;
+pub mod leaf;
+mod linalg;
+#[cfg(feature = "mapped")]
+pub mod mapped;
+mod math;
+pub mod monitor;
+pub mod pidforest;
+pub mod pipeline;
+pub mod profile;
+mod ranked;
+pub mod replacement;
+pub mod sample;
 #[cfg(feature = "serde")]
 mod serde_array;
+pub mod smoothing;
+pub mod split;
+mod stats;
+#[cfg(feature = "stream")]
+mod stream;
+pub mod tensor;
+pub mod timeseries;
+#[cfg(feature = "nalgebra")]
+mod vectors;
+pub mod visit;
+
+/// Minimum number of trees evaluated by [`Forest::score_approx`] before it considers stopping.
+const MIN_APPROX_TREES: usize = 10;
+
+/// Number of rows scored together by [`Forest::score_batch_columnar`].
+const COLUMNAR_BLOCK_SIZE: usize = 4096;
+
+/// Max. number of rows of the training data scored to collect the statistics required by
+/// [`ForestOptions::score_scale`].
+const SCORE_STATISTICS_SAMPLES: usize = 1000;
 
 #[cfg(not(feature = "serde"))]
 pub trait ForestFloat<'de>: Float {}
@@ -75,22 +147,318 @@ pub trait ForestFloat<'de>: Float + Serialize + Deserialize<'de> {}
 impl<'de> ForestFloat<'de> for f32 {}
 impl<'de> ForestFloat<'de> for f64 {}
 
-#[derive(Clone, Eq, PartialEq)]
+/// Degree of freedom in choosing the hyperplanes for dividing up the data.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExtensionLevel {
+    /// Extension level 0, the hyperplanes are parallel to the coordinate axes. This
+    /// is equivalent to the standard isolation forest.
+    #[default]
+    Standard,
+
+    /// An explicit extension level. Must be smaller than the dimension n of the dataset.
+    Level(usize),
+
+    /// The fully extended forest, using an extension level of n-1 for a dataset of
+    /// dimension n. This is the recommended setting of the paper.
+    Full,
+}
+
+impl ExtensionLevel {
+    /// Resolve the extension level for a dataset of dimension `n`.
+    pub fn resolve(&self, n: usize) -> Result<usize, Error> {
+        match self {
+            Self::Standard => Ok(0),
+            Self::Level(level) if *level < n => Ok(*level),
+            Self::Level(_) => Err(Error::ExtensionLevelExceedsDimensions),
+            Self::Full => Ok(n.saturating_sub(1)),
+        }
+    }
+}
+
+impl From<usize> for ExtensionLevel {
+    fn from(level: usize) -> Self {
+        Self::Level(level)
+    }
+}
+
+/// Behavior when the training data contains fewer rows than the requested sample size.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SampleSizePolicy {
+    /// Fail with [`Error::InsufficientTrainingData`].
+    #[default]
+    Strict,
+
+    /// Reduce the sample size to the number of rows of the training data.
+    Clamp,
+
+    /// Sample the rows of the training data with replacement up to the requested sample size.
+    Bootstrap,
+}
+
+/// Spread of the samples the coordinates of the random normal vectors are divided by, see
+/// [`ForestOptions::normal_scaling`]. Only the split strategies of this crate apply it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NormalScaling {
+    /// The normal vectors are not scaled.
+    #[default]
+    None,
+
+    /// Divide by the range of the samples of the node in each dimension.
+    NodeRange,
+
+    /// Divide by the standard deviation of the samples of the tree in each dimension.
+    SampleStdDev,
+}
+
+/// Distribution of the coordinates of the random normal vectors, see
+/// [`ForestOptions::normal_distribution`]. Only the split strategies of this crate apply it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NormalDistribution {
+    /// Coordinates drawn from a standard Gaussian distribution, as in the paper.
+    #[default]
+    Gaussian,
+
+    /// Sparse random projections: each coordinate is `+1` or `-1` with a probability of
+    /// `density / 2` each and `0` otherwise. Normal vectors without any non-zero coordinate
+    /// are drawn again, up to 100 times before a random coordinate is set to `+1` or `-1`.
+    /// The dot products of the hyperplanes then reduce to additions of few features, which is
+    /// much cheaper in high dimensions. The density has to be within `(0, 1]`.
+    Sparse { density: f64 },
+}
+
+impl NormalDistribution {
+    /// The sparse distribution of Achlioptas with a density of `1/3`, which preserves
+    /// distances like Gaussian random projections.
+    pub fn achlioptas() -> Self {
+        Self::Sparse { density: 1.0 / 3.0 }
+    }
+}
+
+/// Random nonlinear map of the features applied by each tree, see
+/// [`ForestOptions::feature_map`].
+///
+/// Both maps project the standardized features onto `N` random directions before applying
+/// a nonlinearity, so the number of dimensions stays the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FeatureMap {
+    /// Random Fourier features `sqrt(2) * cos(w * x + b)`, approximating a Gaussian kernel
+    /// whose width is `bandwidth` times the standard deviation of the features. Bandwidths
+    /// which are not finite and positive fail with [`Error::InvalidOption`].
+    Fourier { bandwidth: f64 },
+
+    /// A random projection followed by a rectified linear unit `max(0, w * x + b)`.
+    Relu,
+}
+
+/// Scale of the scores returned by [`Forest::score`], based on the scores of the training
+/// data. Forests without training statistics, see
+/// [`ForestOptions::record_score_statistics`], always return raw scores.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ScoreScale {
+    /// The score of the paper in `(0, 1]`, where scores close to `1.0` indicate anomalies.
+    #[default]
+    Raw,
+
+    /// The percentile of the raw score among the training scores, in `[0, 100]`.
+    Percentile,
+
+    /// The number of standard deviations the raw score lies above the mean training score.
+    ZScore,
+
+    /// The raw score mapped linearly from the range between the smallest training score
+    /// and `1.0` to `[0, 100]`. Scores below the smallest training score map to `0`.
+    Linear0to100,
+}
+
+impl ScoreScale {
+    fn apply(self, score: f64, statistics: Option<&ScoreStatistics>) -> f64 {
+        let statistics = match statistics {
+            Some(statistics) if !score.is_nan() => statistics,
+            _ => return score,
+        };
+        match self {
+            Self::Raw => score,
+            Self::Percentile => statistics.percentile(score),
+            Self::ZScore => statistics.z_score(score),
+            Self::Linear0to100 => {
+                let min = statistics.min();
+                if min < 1.0 {
+                    ((score - min) / (1.0 - min)).max(0.0) * 100.0
+                } else {
+                    100.0
+                }
+            }
+        }
+    }
+}
+
+/// Statistic of the training data stored for imputing missing values, see
+/// [`Forest::score_imputed`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Imputation {
+    Mean,
+    Median,
+}
+
+/// Scoring of items with infinite or NaN values, see [`ForestOptions::non_finite_handling`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NonFiniteHandling {
+    /// Pass the item through the trees like any other. Infinite values lie beyond every
+    /// hyperplane on their side, and hyperplanes whose dot product with the item overflows
+    /// are evaluated on a rescaled item, so the score of such items stays finite. At a split
+    /// depending on a NaN value the item goes to the right child.
+    #[default]
+    Saturate,
+
+    /// Assign the highest raw score `1.0` to items with an infinite or NaN value.
+    MaxScore,
+    /// Reject items with an infinite or NaN value, so data errors surface instead of
+    /// producing plausible scores: [`Forest::try_score`] fails with
+    /// [`Error::NonFiniteValue`], [`Forest::score`] and the other scoring methods return
+    /// NaN.
+    Reject,
+}
+
+impl NonFiniteHandling {
+    /// Raw score of items with an infinite or NaN value, `None` if they are scored like
+    /// any other item.
+    fn score(self) -> Option<f64> {
+        match self {
+            Self::Saturate => None,
+            Self::MaxScore => Some(1.0),
+            Self::Reject => Some(f64::NAN),
+        }
+    }
+}
+
+/// Options for building a forest.
+///
+/// With the `serde` feature the options can be serialized, e.g. to store them with the
+/// forests of a [`bundle::ForestMap`]. Missing fields take their default values.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ForestOptions {
     /// `n_trees` is the number of trees to be created.
     pub n_trees: usize,
 
     /// `sample_size` is the number of samples of the training data to be used in
-    /// creation of each tree. Must be smaller than `training_data.len()`.
+    /// creation of each tree. Must not be larger than `training_data.len()`, unless
+    /// a different `sample_size_policy` is used.
     pub sample_size: usize,
 
+    /// `max_sample_size` enables a different sample size for each tree, drawn uniformly
+    /// from `sample_size..=max_sample_size`. Trees built from small samples isolate global
+    /// outliers quickly, while trees built from large samples resolve local anomalies.
+    pub max_sample_size: Option<usize>,
+
+    /// `sample_size_policy` defines how to handle training data with fewer rows than
+    /// `sample_size`.
+    pub sample_size_policy: SampleSizePolicy,
+
     /// `max_tree_depth` is the max. allowed tree depth. This is by default set to average
     /// length of an unsuccessful search in a binary tree.
     pub max_tree_depth: Option<usize>,
 
     /// `extension_level` specifies degree of freedom in choosing the hyperplanes for dividing up
-    /// data. Must be smaller than the dimension n of the dataset.
-    pub extension_level: usize,
+    /// data. See [`ExtensionLevel`].
+    pub extension_level: ExtensionLevel,
+
+    /// `max_threads` is the max. number of threads used to build the trees. With a value
+    /// of `0` or `1` all trees are built on the calling thread. Training always runs on the
+    /// CPU, so more threads are the way to speed up building large forests.
+    pub max_threads: usize,
+
+    /// `split_tolerance` is the relative tolerance used to decide whether the samples of a
+    /// node span a range in a dimension. Two values are treated as equal when they differ by
+    /// at most `split_tolerance` relative to the larger magnitude, or by at most
+    /// `f64::EPSILON`. Very small values can make training slow on data containing many
+    /// near-duplicates. Negative and NaN values fail with [`Error::InvalidOption`].
+    pub split_tolerance: f64,
+
+    /// `record_leaf_bounds` enables recording the bounding box of the training samples at
+    /// each leaf. See [`Forest::leaf_bounds`].
+    pub record_leaf_bounds: bool,
+
+    /// `record_samples` enables retaining the indices of the training rows each tree has been
+    /// built from. See [`Forest::oob_scores`].
+    pub record_samples: bool,
+
+    /// `random_tie_breaking` sends training samples lying exactly on a hyperplane to a
+    /// random side instead of always to the left while building the trees. This avoids
+    /// biased trees on quantized or duplicated data. Scoring stays deterministic.
+    pub random_tie_breaking: bool,
+
+    /// `normal_scaling` divides the coordinates of the random normal vectors by the spread of
+    /// the samples in each dimension, so features with large numeric ranges do not dominate
+    /// the orientation of the hyperplanes of data which has not been scaled. See
+    /// [`NormalScaling`].
+    pub normal_scaling: NormalScaling,
+
+    /// `normal_distribution` is the distribution the coordinates of the random normal
+    /// vectors are drawn from. See [`NormalDistribution`].
+    pub normal_distribution: NormalDistribution,
+
+    /// `node_features` limits the hyperplane of each node to the given number of randomly
+    /// selected features, whose coordinates of the normal vector are all drawn from
+    /// `normal_distribution`. This replaces `extension_level`, which is ignored when set.
+    /// Intended for data with hundreds or thousands of dimensions, where each node only
+    /// draws a few coordinates while the trees together still cover all features.
+    pub node_features: Option<usize>,
+
+    /// `rotation_subset_size` enables building each tree in its own rotated space, as in
+    /// rotation forests: the features are split into random subsets of the given size and
+    /// rotated onto the principal components of the sample of the tree. This helps with
+    /// strongly correlated features. The bounds recorded by `record_leaf_bounds` refer to
+    /// the rotated space.
+    pub rotation_subset_size: Option<usize>,
+
+    /// `feature_map` is an experimental option building each tree in its own space obtained
+    /// from a random nonlinear map of the features, in the spirit of deep isolation forests.
+    /// This helps with anomalies which can not be separated from the normal data by
+    /// hyperplanes, e.g. items in the center of a ring. Takes precedence over
+    /// `rotation_subset_size`. The bounds recorded by `record_leaf_bounds` refer to the
+    /// mapped space.
+    pub feature_map: Option<FeatureMap>,
+
+    /// `max_total_nodes` bounds the number of nodes of all trees together, which bounds the
+    /// memory used by the forest. The budget is split evenly between the trees, each of
+    /// which keeps at least its root. Within a tree, the budget of a node is split between
+    /// its children in proportion to their samples. Subtrees exceeding their budget are
+    /// truncated into leaves, whose path lengths are corrected for the unbuilt subtree like
+    /// those of leaves at the depth limit.
+    pub max_total_nodes: Option<usize>,
+
+    /// `score_scale` is the scale of the scores returned by [`Forest::score`]. The
+    /// statistics required for scaling are collected from up to 1000 rows of the training
+    /// data for all scales but [`ScoreScale::Raw`].
+    pub score_scale: ScoreScale,
+
+    /// `record_score_statistics` enables collecting the statistics of the scores of the
+    /// training data also for [`ScoreScale::Raw`], so the scale can be changed later using
+    /// [`Forest::set_score_scale`]. This requires scoring up to 1000 rows after training.
+    pub record_score_statistics: bool,
+
+    /// `preprocessing` are the steps fit on the training data and applied to every item
+    /// before it is passed to the trees. See [`pipeline::Step`].
+    pub preprocessing: Vec<Step>,
+
+    /// `imputation` is the statistic of each feature stored with the forest to replace
+    /// missing values by [`Forest::score_imputed`]. It is computed from the values of the
+    /// training data which are not NaN.
+    pub imputation: Option<Imputation>,
+
+    /// `non_finite_handling` defines the scores of items with infinite or NaN values. It is
+    /// stored with the forest.
+    pub non_finite_handling: NonFiniteHandling,
 }
 
 impl Default for ForestOptions {
@@ -98,384 +466,2943 @@ impl Default for ForestOptions {
         Self {
             n_trees: 20,
             sample_size: 20,
+            max_sample_size: None,
+            sample_size_policy: SampleSizePolicy::Strict,
             max_tree_depth: None,
-            extension_level: 0,
+            extension_level: ExtensionLevel::Standard,
+            max_threads: 1,
+            split_tolerance: 1e-4,
+            record_leaf_bounds: false,
+            record_samples: false,
+            random_tie_breaking: false,
+            normal_scaling: NormalScaling::None,
+            normal_distribution: NormalDistribution::Gaussian,
+            node_features: None,
+            rotation_subset_size: None,
+            feature_map: None,
+            max_total_nodes: None,
+            score_scale: ScoreScale::Raw,
+            record_score_statistics: false,
+            preprocessing: vec![],
+            imputation: None,
+            non_finite_handling: NonFiniteHandling::Saturate,
+        }
+    }
+}
+
+impl ForestOptions {
+    /// Options following the guidance of the papers on isolation forests for `data`: 100
+    /// trees built from 256 samples each, or from all rows of smaller datasets, using the
+    /// fully extended hyperplanes and all available cores.
+    ///
+    /// Datasets with more than 100 features get one tree per feature, so each feature is
+    /// split by many trees.
+    pub fn auto<T, const N: usize>(data: &[[T; N]]) -> Self {
+        Self {
+            n_trees: N.max(100),
+            sample_size: data.len().clamp(1, 256),
+            extension_level: ExtensionLevel::Full,
+            max_threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            ..Self::default()
         }
     }
 }
 
+#[derive(Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Forest<T, const N: usize> {
-    /// Multiplicative factor used in computing the anomaly scores.
+    /// Average of the normalization constants of the trees. The scores are computed from
+    /// the constants of the individual trees, see [`Tree::path_length_c`].
     avg_path_length_c: f64,
 
+    /// Number of samples each tree was built from. `0` for forests serialized by
+    /// older versions.
+    #[cfg_attr(feature = "serde", serde(default))]
+    sample_size: usize,
+
+    /// The sample size policy which was used while building the trees.
+    #[cfg_attr(feature = "serde", serde(default))]
+    sample_size_policy: SampleSizePolicy,
+
+    /// Preprocessing applied to the items before passing them to the trees.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pipeline: Pipeline,
+
+    /// Range of the training data, `None` for forests serialized by older versions.
+    #[cfg_attr(feature = "serde", serde(default))]
+    training_range: Option<LeafBounds<T, N>>,
+
+    /// Scale of the scores returned by [`Forest::score`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    score_scale: ScoreScale,
+
+    /// Scores of the training data, `None` for forests serialized by older versions.
+    #[cfg_attr(feature = "serde", serde(default))]
+    score_statistics: Option<ScoreStatistics>,
+
+    /// Values replacing missing values of each feature, see [`ForestOptions::imputation`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, deserialize_with = "deserialize_imputation_values::<_, N>")
+    )]
+    imputation_values: Option<Vec<f64>>,
+
+    /// Scoring of items with infinite or NaN values.
+    #[cfg_attr(feature = "serde", serde(default))]
+    non_finite_handling: NonFiniteHandling,
+
+    /// Counters of the visited nodes, see [`Forest::set_profiling`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    profiler: Option<Profiler>,
+
     trees: Box<[Tree<T, N>]>,
 }
 
+/// Deserialize the imputation values of a forest, failing unless there is one value per
+/// feature.
+#[cfg(feature = "serde")]
+fn deserialize_imputation_values<'de, D, const N: usize>(
+    deserializer: D,
+) -> Result<Option<Vec<f64>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values = Option::<Vec<f64>>::deserialize(deserializer)?;
+    match &values {
+        Some(values) if values.len() != N => Err(serde::de::Error::invalid_length(
+            values.len(),
+            &"one imputation value per feature",
+        )),
+        _ => Ok(values),
+    }
+}
+
 impl<'de, T, const N: usize> Forest<T, N>
 where
     T: ForestFloat<'de> + SampleUniform + Default,
     StandardNormal: Distribution<T>,
 {
     /// Build a new forest from the given training data
-    pub fn from_slice(training_data: &[[T; N]], options: &ForestOptions) -> Result<Self, Error> {
-        if training_data.len() < options.sample_size || N == 0 {
-            return Err(Error::InsufficientTrainingData);
-        } else if options.extension_level > (N - 1) {
-            return Err(Error::ExtensionLevelExceedsDimensions);
+    pub fn from_slice(training_data: &[[T; N]], options: &ForestOptions) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        Self::from_slice_with_split(training_data, options, &RandomHyperplane)
+    }
+
+    /// Build a new forest from training data given as tuples or other [`Sample`]s, which
+    /// are converted to arrays.
+    pub fn from_samples<S>(training_data: &[S], options: &ForestOptions) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+        S: Sample<T, N>,
+    {
+        Self::from_slice(&S::to_rows(training_data), options)
+    }
+
+    /// Build a new forest from the given training data, splitting the nodes of the trees
+    /// using a custom [`SplitStrategy`].
+    pub fn from_slice_with_split(
+        training_data: &[[T; N]],
+        options: &ForestOptions,
+        split_strategy: &dyn SplitStrategy<T, N>,
+    ) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        Self::build(training_data, None, options, split_strategy)
+    }
+
+    /// Build a new forest from the given training data, where `groups` assigns each row to
+    /// a group, e.g. a segment or cluster.
+    ///
+    /// The sample of each tree is stratified over the groups, so each group is represented
+    /// in proportion to its share of the training data, and with at least one row as long
+    /// as the sample size is not smaller than the number of groups. This avoids flagging
+    /// rare but legitimate groups which would be absent from many samples otherwise.
+    pub fn from_slice_stratified(
+        training_data: &[[T; N]],
+        groups: &[usize],
+        options: &ForestOptions,
+    ) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        if groups.len() != training_data.len() {
+            return Err(Error::DimensionMismatch {
+                expected: training_data.len(),
+                actual: groups.len(),
+            });
         }
+        Self::build(training_data, Some(groups), options, &RandomHyperplane)
+    }
 
-        let max_tree_depth = if let Some(mdt) = options.max_tree_depth {
-            mdt
-        } else {
-            (options.sample_size as f64).log2().ceil() as usize
-        };
+    /// Build a new forest from the items of an iterator with known length, e.g. rows streamed
+    /// from a database.
+    ///
+    /// The items are collected into a single buffer of the exact size, as each tree samples
+    /// from all of them.
+    pub fn from_iter(
+        iter: impl ExactSizeIterator<Item = [T; N]>,
+        options: &ForestOptions,
+    ) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        let mut training_data = Vec::with_capacity(iter.len());
+        training_data.extend(iter);
+        Self::from_slice(&training_data, options)
+    }
 
-        // build the trees
+    /// Build a new forest from a uniform random sample of at most `max_rows` items of an
+    /// iterator of unknown length.
+    ///
+    /// The sample is drawn by reservoir sampling in a single pass, so no more than
+    /// `max_rows` items are held in memory. The training range and the statistics of the
+    /// training scores refer to the sample.
+    pub fn from_iter_sampled(
+        iter: impl IntoIterator<Item = [T; N]>,
+        max_rows: usize,
+        options: &ForestOptions,
+    ) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
         let rng = &mut rand::thread_rng();
-        let trees = (0..options.n_trees)
-            .map(|_| {
-                let tree_sample: Vec<_> = training_data
-                    .choose_multiple(rng, options.sample_size)
-                    .collect();
+        let iter = iter.into_iter();
+        // `max_rows` may be a generous upper bound, so only the known length is reserved
+        let mut reservoir = Vec::with_capacity(max_rows.min(iter.size_hint().0));
+        for (row, values) in iter.enumerate() {
+            if row < max_rows {
+                reservoir.push(values);
+            } else {
+                let position = rng.gen_range(0..=row);
+                if position < max_rows {
+                    reservoir[position] = values;
+                }
+            }
+        }
+        Self::from_slice(&reservoir, options)
+    }
 
-                Tree::new(
-                    tree_sample.as_slice(),
-                    rng,
-                    max_tree_depth,
-                    options.extension_level,
-                )
+    /// Build a new forest from training data given feature by feature, with one slice of
+    /// equal length per feature, as handed over by columnar stores.
+    ///
+    /// The nodes of the trees are split on rows, see [`SplitContext::data`], so the samples
+    /// of each tree are copied into rows while building it, but the columns are never copied
+    /// as a whole. Only fitting [`ForestOptions::preprocessing`] needs a copy of all values.
+    /// Fails with [`Error::DimensionMismatch`] when there are not exactly `N` columns or the
+    /// columns differ in length.
+    pub fn from_columns(columns: &[&[T]], options: &ForestOptions) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        let n_rows = column_length(columns, N)?;
+        Self::build(
+            &Columns { columns, n_rows },
+            None,
+            options,
+            &RandomHyperplane,
+        )
+    }
+
+    /// Build a new forest from training data given as rows of varying length, e.g. as parsed
+    /// from CSV or JSON.
+    ///
+    /// Fails with [`Error::RaggedRow`] for the first row which does not contain exactly `N`
+    /// values.
+    pub fn try_from_rows(rows: &[Vec<T>], options: &ForestOptions) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        let training_data = rows
+            .iter()
+            .enumerate()
+            .map(|(row, values)| {
+                values.as_slice().try_into().map_err(|_| Error::RaggedRow {
+                    row,
+                    expected: N,
+                    actual: values.len(),
+                })
             })
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+            .collect::<Result<Vec<[T; N]>, _>>()?;
+        Self::from_slice(&training_data, options)
+    }
 
+    /// Build a new forest from the given training data and score the training data with it.
+    ///
+    /// The scores are returned in the order of `training_data` and computed using up to
+    /// [`ForestOptions::max_threads`] threads, see [`Forest::score_batch_threads`]. They are
+    /// commonly inspected to pick a threshold.
+    pub fn fit_scores(
+        training_data: &[[T; N]],
+        options: &ForestOptions,
+    ) -> Result<(Self, Vec<f64>), Error>
+    where
+        T: Send + Sync,
+    {
+        let forest = Self::from_slice(training_data, options)?;
+        let scores = forest.score_batch_threads(training_data, options.max_threads);
+        Ok((forest, scores))
+    }
+
+    fn build<R: TrainingRows<T, N> + Sync + ?Sized>(
+        training_data: &R,
+        groups: Option<&[usize]>,
+        options: &ForestOptions,
+        split_strategy: &dyn SplitStrategy<T, N>,
+    ) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        let rows = || (0..training_data.n_rows()).map(|row| training_data.row(row));
+        let training_range = rows().next().map(|first| {
+            let (mut min, mut max) = (first, first);
+            for values in rows() {
+                for (i, v) in values.iter().copied().enumerate() {
+                    min[i] = min[i].min(v);
+                    max[i] = max[i].max(v);
+                }
+            }
+            LeafBounds { min, max }
+        });
+        let pipeline = Pipeline::fit_rows(&options.preprocessing, rows());
+        let (sample_size, trees) = match training_data.as_slice() {
+            _ if pipeline.is_empty() => build_trees(
+                training_data,
+                groups,
+                options,
+                options.n_trees,
+                split_strategy,
+            )?,
+            Some(training_data) => {
+                let transformed: Vec<_> = training_data
+                    .iter()
+                    .map(|values| pipeline.transform(values))
+                    .collect();
+                build_trees(
+                    transformed.as_slice(),
+                    groups,
+                    options,
+                    options.n_trees,
+                    split_strategy,
+                )?
+            }
+            None => build_trees(
+                &Preprocessed {
+                    rows: training_data,
+                    pipeline: &pipeline,
+                },
+                groups,
+                options,
+                options.n_trees,
+                split_strategy,
+            )?,
+        };
+        let avg_path_length_c = trees
+            .iter()
+            .map(|tree| c_factor(tree.sample_size()))
+            .sum::<f64>()
+            / trees.len().max(1) as f64;
+
+        let mut forest = Self {
+            avg_path_length_c,
+            sample_size,
+            sample_size_policy: options.sample_size_policy,
+            pipeline,
+            training_range,
+            score_scale: options.score_scale,
+            score_statistics: None,
+            imputation_values: options
+                .imputation
+                .map(|imputation| imputation_values(training_data, imputation)),
+            non_finite_handling: options.non_finite_handling,
+            profiler: None,
+            trees: trees.into_boxed_slice(),
+        };
+        if options.record_score_statistics || options.score_scale != ScoreScale::Raw {
+            let n_rows = training_data.n_rows();
+            let rows = index::sample(
+                &mut rand::thread_rng(),
+                n_rows,
+                n_rows.min(SCORE_STATISTICS_SAMPLES),
+            );
+            forest.score_statistics = ScoreStatistics::new(
+                rows.iter()
+                    .map(|row| forest.score_raw(&training_data.row(row)))
+                    .collect(),
+            );
+        }
+        Ok(forest)
+    }
+
+    /// Combine trees, e.g. of forests trained on different views of the features, into a
+    /// new forest using the scoring of this crate. `sample_size` is reported by
+    /// [`Forest::sample_size`], the scores use the sample sizes of the individual trees.
+    ///
+    /// The items are passed to the trees without preprocessing and the scores are not
+    /// scaled, so the trees should be taken from forests built without
+    /// [`ForestOptions::preprocessing`]. Fails with [`Error::InsufficientTrainingData`]
+    /// without trees or with a `sample_size` of `0`.
+    pub fn from_trees(trees: Vec<Tree<T, N>>, sample_size: usize) -> Result<Self, Error> {
+        if trees.is_empty() || sample_size == 0 {
+            return Err(Error::InsufficientTrainingData);
+        }
+        let avg_path_length_c = trees
+            .iter()
+            .map(|tree| c_factor(tree.sample_size()))
+            .sum::<f64>()
+            / trees.len() as f64;
         Ok(Self {
-            avg_path_length_c: c_factor(options.sample_size),
-            trees,
+            avg_path_length_c,
+            sample_size,
+            sample_size_policy: SampleSizePolicy::Strict,
+            pipeline: Pipeline::default(),
+            training_range: None,
+            score_scale: ScoreScale::Raw,
+            score_statistics: None,
+            imputation_values: None,
+            non_finite_handling: NonFiniteHandling::Saturate,
+            profiler: None,
+            trees: trees.into_boxed_slice(),
         })
     }
 
-    /// compute anomaly score for an item
-    pub fn score(&self, values: &[T; N]) -> f64 {
-        let path_length: f64 = self.trees.iter().map(|tree| tree.path_length(values)).sum();
+    /// The trees of the forest.
+    pub fn trees(&self) -> &[Tree<T, N>] {
+        &self.trees
+    }
 
-        // Average of path length travelled by the point in all trees.
-        let eh = path_length / self.trees.len() as f64;
+    /// Take the trees out of the forest, e.g. to combine them with trees of other forests
+    /// using [`Forest::from_trees`].
+    pub fn into_trees(self) -> Vec<Tree<T, N>> {
+        self.trees.into_vec()
+    }
 
-        // Anomaly Score
-        2.0_f64.powf(-eh / self.avg_path_length_c)
+    /// Smallest and largest value of the training data in each dimension.
+    ///
+    /// `None` for forests deserialized from older versions.
+    pub fn training_range(&self) -> Option<&LeafBounds<T, N>> {
+        self.training_range.as_ref()
     }
-}
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-enum Node<T, const N: usize> {
-    Ex(ExNode),
-    In(InNode<T, N>),
-}
+    /// Values replacing missing values of each feature, `None` if the forest was built
+    /// without [`ForestOptions::imputation`].
+    pub fn imputation_values(&self) -> Option<&[f64]> {
+        self.imputation_values.as_deref()
+    }
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-struct InNode<T, const N: usize> {
-    /// Left child node.
-    left: Box<Node<T, N>>,
+    /// Compute the anomaly score for an item after replacing its NaN values by the values
+    /// stored with the forest, see [`ForestOptions::imputation`].
+    ///
+    /// Without stored values the item is scored unchanged.
+    pub fn score_imputed(&self, values: &[T; N]) -> f64 {
+        let imputed = match &self.imputation_values {
+            Some(imputation_values) => std::array::from_fn(|i| {
+                if values[i].is_nan() {
+                    T::from(imputation_values[i]).unwrap_or(values[i])
+                } else {
+                    values[i]
+                }
+            }),
+            None => *values,
+        };
+        self.score(&imputed)
+    }
 
-    /// Right child node.
-    right: Box<Node<T, N>>,
+    /// The scoring of items with infinite or NaN values.
+    pub fn non_finite_handling(&self) -> NonFiniteHandling {
+        self.non_finite_handling
+    }
 
-    /// Normal vector at the root of this tree, which is used in
-    /// creating hyperplanes for splitting criteria
-    #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
-    n: [T; N],
+    /// The preprocessing applied to items before passing them to the trees.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
 
-    /// Intercept point through which the hyperplane passes.
-    #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
-    p: [T; N],
-}
+    /// Number of samples each tree was built from.
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-struct ExNode {
-    /// Size of the dataset present at the node.
-    num_samples: usize,
-}
+    /// The sample size policy which was used while building the trees.
+    pub fn sample_size_policy(&self) -> SampleSizePolicy {
+        self.sample_size_policy
+    }
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-struct Tree<T, const N: usize> {
-    root: Node<T, N>,
-}
+    /// The scale of the scores returned by [`Forest::score`].
+    pub fn score_scale(&self) -> ScoreScale {
+        self.score_scale
+    }
 
-impl<'de, T, const N: usize> Tree<T, N>
-where
-    T: ForestFloat<'de> + SampleUniform + Default,
-    StandardNormal: Distribution<T>,
-{
-    pub fn new(
-        samples: &[&[T; N]],
-        rng: &mut ThreadRng,
-        max_tree_depth: usize,
-        extension_level: usize,
-    ) -> Self {
-        Self {
-            root: make_node(samples, rng, 0, max_tree_depth, extension_level),
-        }
+    /// Change the scale of the scores returned by [`Forest::score`]. Scales other than
+    /// [`ScoreScale::Raw`] require the statistics collected with
+    /// [`ForestOptions::record_score_statistics`] or a scaled
+    /// [`ForestOptions::score_scale`], otherwise the scores stay raw.
+    pub fn set_score_scale(&mut self, score_scale: ScoreScale) {
+        self.score_scale = score_scale;
     }
 
-    /// length of the path traversed by the point on the tree when it reaches an external node.
-    pub fn path_length(&self, values: &[T; N]) -> f64 {
-        path_length_recurse(&self.root, values)
+    /// compute anomaly score for an item, see [`ForestOptions::score_scale`]
+    #[inline]
+    pub fn score(&self, values: &[T; N]) -> f64 {
+        self.scale_score(self.score_raw(values))
     }
-}
 
-fn path_length_recurse<T, const N: usize>(node: &Node<T, N>, values: &[T; N]) -> f64
-where
-    T: Float,
-{
-    match node {
-        Node::Ex(ex_node) => {
-            if ex_node.num_samples <= 1 {
-                0.0
-            } else {
-                c_factor(ex_node.num_samples)
+    /// Compute the anomaly score for an item given as tuple or another [`Sample`], see
+    /// [`Forest::from_samples`].
+    pub fn score_sample<S: Sample<T, N>>(&self, values: &S) -> f64 {
+        self.score(&values.to_array())
+    }
+
+    /// Compute the anomaly score for an item, failing with [`Error::NonFiniteValue`] for
+    /// items with an infinite or NaN value if the forest was built with
+    /// [`NonFiniteHandling::Reject`]. Otherwise the score equals [`Forest::score`].
+    pub fn try_score(&self, values: &[T; N]) -> Result<f64, Error> {
+        if self.non_finite_handling == NonFiniteHandling::Reject {
+            if let Some(dimension) = values.iter().position(|value| !value.is_finite()) {
+                return Err(Error::NonFiniteValue {
+                    dimension,
+                    value: as_f64(&values[dimension]),
+                });
             }
         }
-        Node::In(in_node) => {
-            1.0 + path_length_recurse(
-                match determinate_direction(values, &in_node.n, &in_node.p) {
-                    Direction::Left => in_node.left.as_ref(),
-                    Direction::Right => in_node.right.as_ref(),
-                },
-                values,
-            )
+        Ok(self.score(values))
+    }
+
+    /// compute anomaly score for an item without applying the score scale
+    #[inline]
+    pub fn score_raw(&self, values: &[T; N]) -> f64 {
+        if let Some(score) = self.non_finite_score(values) {
+            return score;
         }
+        let values = &self.pipeline.transform(values);
+        if let Some(profiler) = &self.profiler {
+            return self.score_raw_profiled(values, profiler);
+        }
+        let path_length: f64 = self
+            .trees
+            .iter()
+            .map(|tree| tree.adjusted_path_length(values) * tree.path_length_c_inv)
+            .sum();
+        self.score_from_path_length(path_length / self.trees.len() as f64)
     }
-}
 
-fn as_f64<'de, T>(num: &T) -> f64
-where
-    T: ForestFloat<'de> + SampleUniform + Default,
-{
-    let (mantissa, exponent, sign) = num.integer_decode();
-    let sign_f = sign as f64;
-    let mantissa_f = mantissa as f64;
-    let exponent_f = 2f64.powf(exponent as f64);
+    /// Raw score of an item with an infinite or NaN value according to the
+    /// [`NonFiniteHandling`] of the forest, `None` if the item is passed through the trees.
+    #[inline]
+    fn non_finite_score(&self, values: &[T; N]) -> Option<f64> {
+        let score = self.non_finite_handling.score()?;
+        values
+            .iter()
+            .any(|value| !value.is_finite())
+            .then_some(score)
+    }
 
-    sign_f * mantissa_f * exponent_f
-}
+    /// [`Forest::score_raw`] of a preprocessed item, recording the paths in `profiler`.
+    #[cold]
+    fn score_raw_profiled(&self, values: &[T; N], profiler: &Profiler) -> f64 {
+        profiler.record_item();
+        let path_length: f64 = self
+            .trees
+            .iter()
+            .enumerate()
+            .map(|(i, tree)| {
+                let (leaf, depth) = tree.leaf_with_depth(values);
+                profiler.record_path(i, leaf.id, depth);
+                (depth as f64 + leaf.path_length) * tree.path_length_c_inv
+            })
+            .sum();
+        self.score_from_path_length(path_length / self.trees.len() as f64)
+    }
 
-fn are_equal<'de, T>(a: &T, b: &T) -> bool
-where
-    T: ForestFloat<'de> + SampleUniform + Default,
-{
-    let a = as_f64(a);
-    let b = as_f64(b);
-    ((a - b).abs() / a) < 1e-4
-}
+    #[inline]
+    fn scale_score(&self, score: f64) -> f64 {
+        self.score_scale
+            .apply(score, self.score_statistics.as_ref())
+    }
 
-fn make_node<'de, T, const N: usize>(
-    samples: &[&[T; N]],
-    rng: &mut ThreadRng,
-    current_tree_depth: usize,
-    max_tree_depth: usize,
-    extension_level: usize,
-) -> Node<T, N>
-where
-    T: ForestFloat<'de> + SampleUniform + Default,
-    StandardNormal: Distribution<T>,
-{
-    let num_samples = samples.len();
-    if current_tree_depth >= max_tree_depth || num_samples <= 1 {
-        Node::Ex(ExNode { num_samples })
-    } else {
-        // randomly select an intercept point p ~ ∈ IR |samples| in
-        // the range of the samples
-        let p = {
-            let mut maxs = *samples[0];
-            let mut mins = *samples[0];
-            samples.iter().skip(1).for_each(|s| {
-                s.iter().enumerate().for_each(|(i, v)| {
-                    maxs[i] = if *v > maxs[i] { *v } else { maxs[i] };
-                    mins[i] = if *v < mins[i] { *v } else { mins[i] };
-                })
-            });
+    /// Compute the anomaly score for an item using a custom contribution of the external
+    /// nodes to the path length, see [`LeafScore`]. The score is scaled like the score of
+    /// [`Forest::score`].
+    pub fn score_with<L: LeafScore + ?Sized>(&self, values: &[T; N], leaf_score: &L) -> f64 {
+        if let Some(score) = self.non_finite_score(values) {
+            return self.scale_score(score);
+        }
+        let values = &self.pipeline.transform(values);
+        let path_length: f64 = self
+            .trees
+            .iter()
+            .map(|tree| tree.path_length(values, leaf_score) * tree.path_length_c_inv)
+            .sum();
 
-            // randomly pick an intercept point using a uniform distribution
-            let mut p = [T::zero(); N];
-            mins.iter()
-                .zip(maxs.iter())
-                .zip(p.iter_mut())
-                .for_each(|((min_val, max_val), p_i)| {
-                    *p_i = if are_equal(min_val, max_val) {
-                        // sampling with lower and upper bound being equal panics
-                        *min_val
-                    } else {
-                        rng.sample(Uniform::new(*min_val, *max_val))
-                    }
-                });
-            p
-        };
+        // Average of the normalized path length travelled by the point in all trees.
+        let eh = path_length / self.trees.len() as f64;
 
-        // randomly select a normal vector ~n ∈ IR |samples| by drawing each coordinate
-        // of ~n from a standard Gaussian distribution.
-        let mut n = [T::zero(); N];
-        (0..N)
-            .zip(n.iter_mut())
-            .for_each(|(_, n_i)| *n_i = rng.sample(StandardNormal));
+        self.scale_score(self.score_from_path_length(eh))
+    }
 
-        // set coordinates of ~n to zero according to extension level
-        for idx in (0..N).choose_multiple(rng, N - extension_level - 1) {
-            n[idx] = T::zero();
+    /// Compute the anomaly score for an item using soft routing: when the item lies within
+    /// distance `margin` of the hyperplane of a node, it descends into both children,
+    /// weighted linearly by its position within the margin.
+    ///
+    /// This produces smoother scores for items close to split boundaries. With a `margin`
+    /// of `0.0` the result equals [`Forest::score`].
+    pub fn score_soft(&self, values: &[T; N], margin: f64) -> f64 {
+        if let Some(score) = self.non_finite_score(values) {
+            return self.scale_score(score);
         }
+        let values = &self.pipeline.transform(values);
+        let path_length: f64 = self
+            .trees
+            .iter()
+            .map(|tree| tree.soft_path_length(values, margin) * tree.path_length_c_inv)
+            .sum();
+        self.scale_score(self.score_from_path_length(path_length / self.trees.len() as f64))
+    }
+
+    /// Compute the anomaly score for an item given as slice, which is checked to contain
+    /// exactly `N` values.
+    pub fn score_slice(&self, values: &[T]) -> Result<f64, Error> {
+        let values: &[T; N] = values.try_into().map_err(|_| Error::DimensionMismatch {
+            expected: N,
+            actual: values.len(),
+        })?;
+        Ok(self.score(values))
+    }
+
+    /// Compute the anomaly scores of items given feature by feature, with one slice of
+    /// equal length per feature, as handed over by columnar engines.
+    ///
+    /// This crate does not depend on any query engine and provides no UDF itself, but this
+    /// is the body of a scalar UDF of an engine like DataFusion: wrap the forest in a
+    /// function taking `N` float arrays, e.g. `eif_score(col1, col2, col3)`, and return the
+    /// scores as a float array. Fails with [`Error::DimensionMismatch`] when there are not
+    /// exactly `N` columns or the columns differ in length.
+    pub fn score_columns(&self, columns: &[&[T]]) -> Result<Vec<f64>, Error> {
+        let n_rows = column_length(columns, N)?;
+        Ok((0..n_rows)
+            .map(|row| self.score(&std::array::from_fn(|i| columns[i][row])))
+            .collect())
+    }
 
-        let mut samples_left = vec![];
-        let mut samples_right = vec![];
+    /// Compute the anomaly score for an item and check whether it lies within the range of
+    /// the training data.
+    ///
+    /// A value counts as out of range when it exceeds the training range of its dimension by
+    /// more than `margin` times the width of that range. `handling` defines how the score
+    /// treats such items, see [`RangeHandling`].
+    pub fn score_checked(
+        &self,
+        values: &[T; N],
+        handling: RangeHandling,
+        margin: f64,
+    ) -> CheckedScore {
+        let range = match &self.training_range {
+            Some(range) => range,
+            None => {
+                return CheckedScore {
+                    score: self.score(values),
+                    out_of_range: vec![],
+                }
+            }
+        };
 
-        for sample in samples {
-            match determinate_direction(sample, &n, &p) {
-                Direction::Left => samples_left.push(*sample),
-                Direction::Right => samples_right.push(*sample),
+        let mut out_of_range = vec![];
+        let mut clamped = *values;
+        // largest distance outside of the allowed range relative to the width of the range
+        let mut excess: f64 = 0.0;
+        for (dimension, value) in values.iter().copied().enumerate() {
+            let (min, max) = (as_f64(&range.min[dimension]), as_f64(&range.max[dimension]));
+            let width = max - min;
+            let value = as_f64(&value);
+            let distance = (min - margin * width - value).max(value - max - margin * width);
+            if distance > 0.0 {
+                out_of_range.push(dimension);
+                clamped[dimension] = clamped[dimension]
+                    .max(range.min[dimension])
+                    .min(range.max[dimension]);
+                excess = excess.max(distance / width.max(f64::MIN_POSITIVE));
             }
         }
 
-        Node::In(InNode {
-            left: Box::new(make_node(
-                samples_left.as_slice(),
-                rng,
-                current_tree_depth + 1,
-                max_tree_depth,
-                extension_level,
-            )),
-            right: Box::new(make_node(
-                samples_right.as_slice(),
-                rng,
-                current_tree_depth + 1,
-                max_tree_depth,
-                extension_level,
-            )),
-            n,
-            p,
-        })
+        let score = match handling {
+            RangeHandling::Flag => self.score_raw(values),
+            RangeHandling::Clamp => self.score_raw(&clamped),
+            RangeHandling::Boost => 1.0 - (1.0 - self.score_raw(values)) * math::exp2(-excess),
+        };
+        CheckedScore {
+            score: self.scale_score(score),
+            out_of_range,
+        }
+    }
+
+    /// Compute the anomaly score for an item from `k` of the trees, chosen randomly using
+    /// `seed`.
+    ///
+    /// Fewer trees score faster but with more variance. The same seed selects the same
+    /// trees within a build of this crate, so scores with different seeds act as the scores
+    /// of different smaller forests, e.g. for ensembling experiments. The selection may
+    /// change with the version of `rand`, so seeds should not be stored to reproduce scores
+    /// later. The score is scaled using the statistics of the whole forest. At least one
+    /// tree is used, and all trees when `k` exceeds their number.
+    pub fn score_with_trees(&self, values: &[T; N], k: usize, seed: u64) -> f64 {
+        let trees = self.select_trees(k, seed);
+        self.scale_score(self.score_raw_with_trees(values, &trees))
+    }
+
+    /// Compute the anomaly scores for a batch of items from the same `k` trees, see
+    /// [`Forest::score_with_trees`].
+    pub fn score_batch_with_trees(&self, data: &[[T; N]], k: usize, seed: u64) -> Vec<f64> {
+        let trees = self.select_trees(k, seed);
+        data.iter()
+            .map(|values| self.scale_score(self.score_raw_with_trees(values, &trees)))
+            .collect()
+    }
+
+    /// Indices of `k` randomly chosen trees in ascending order, at least one.
+    fn select_trees(&self, k: usize, seed: u64) -> Vec<usize> {
+        let n_trees = self.trees.len();
+        let k = k.max(1).min(n_trees);
+        let mut trees = index::sample(&mut StdRng::seed_from_u64(seed), n_trees, k).into_vec();
+        trees.sort_unstable();
+        trees
+    }
+
+    fn score_raw_with_trees(&self, values: &[T; N], trees: &[usize]) -> f64 {
+        if let Some(score) = self.non_finite_score(values) {
+            return score;
+        }
+        let values = &self.pipeline.transform(values);
+        let path_length: f64 = trees
+            .iter()
+            .map(|i| self.trees[*i].score_contribution(values))
+            .sum();
+        self.score_from_path_length(path_length / trees.len() as f64)
+    }
+
+    /// Check whether the anomaly score of an item exceeds `threshold`, which has the scale
+    /// of [`Forest::score`].
+    ///
+    /// The trees are evaluated one after another and evaluation stops as soon as the
+    /// shortest and longest paths of the remaining trees cannot change the result. Most
+    /// items of a typical stream are clearly normal, so only a part of the trees is needed
+    /// for them. The result always equals `self.score(values) > threshold`.
+    pub fn score_exceeds(&self, values: &[T; N], threshold: f64) -> bool {
+        if let Some(score) = self.non_finite_score(values) {
+            return self.scale_score(score) > threshold;
+        }
+        let values = &self.pipeline.transform(values);
+        let n_trees = self.trees.len() as f64;
+        // bounds of the normalized path lengths of the trees not evaluated yet
+        let (mut remaining_min, mut remaining_max) =
+            self.trees.iter().fold((0.0, 0.0), |(min, max), tree| {
+                (
+                    min + tree.path_length_range.0 * tree.path_length_c_inv,
+                    max + tree.path_length_range.1 * tree.path_length_c_inv,
+                )
+            });
+        // covers the rounding errors of updating the bounds
+        let slack = remaining_max * 1e-12;
+        let mut path_length = 0.0;
+        for (i, tree) in self.trees.iter().enumerate() {
+            path_length += tree.adjusted_path_length(values) * tree.path_length_c_inv;
+            if i + 1 == self.trees.len() {
+                break;
+            }
+            remaining_min -= tree.path_length_range.0 * tree.path_length_c_inv;
+            remaining_max -= tree.path_length_range.1 * tree.path_length_c_inv;
+
+            // longer paths mean lower scores
+            let lowest = (path_length + remaining_max + slack) / n_trees;
+            if self.scale_score(self.score_from_path_length(lowest)) > threshold {
+                return true;
+            }
+            let highest = (path_length + (remaining_min - slack).max(0.0)) / n_trees;
+            if self.scale_score(self.score_from_path_length(highest)) <= threshold {
+                return false;
+            }
+        }
+        // all trees evaluated, the path length is summed up like in `score_raw`
+        self.scale_score(self.score_from_path_length(path_length / n_trees)) > threshold
+    }
+
+    /// Approximate the anomaly score for an item by evaluating the trees one after another.
+    ///
+    /// Evaluation stops as soon as the 95% confidence interval of the score derived from the
+    /// trees evaluated so far is narrower than `tolerance`. For large forests this avoids
+    /// evaluating most trees for the majority of items. The interval is measured on the
+    /// raw scores, the returned score is scaled like the score of [`Forest::score`].
+    pub fn score_approx(&self, values: &[T; N], tolerance: f64) -> f64 {
+        if let Some(score) = self.non_finite_score(values) {
+            return self.scale_score(score);
+        }
+        let values = &self.pipeline.transform(values);
+        let n_trees = self.trees.len();
+
+        // running mean and variance of the normalized path lengths (Welford's algorithm)
+        let mut mean = 0.0;
+        let mut m2 = 0.0;
+        for (i, tree) in self.trees.iter().enumerate() {
+            let count = (i + 1) as f64;
+            let path_length = tree.adjusted_path_length(values) * tree.path_length_c_inv;
+            let delta = path_length - mean;
+            mean += delta / count;
+            m2 += delta * (path_length - mean);
+
+            if i + 1 >= MIN_APPROX_TREES && i + 1 < n_trees {
+                // the trees form a finite population, so the standard error shrinks to
+                // zero once all of them have been evaluated.
+                let fpc = ((n_trees as f64 - count) / (n_trees as f64 - 1.0)).sqrt();
+                let std_err = (m2 / (count - 1.0)).sqrt() / count.sqrt() * fpc;
+                let half_width = 1.96 * std_err;
+
+                let upper = self.score_from_path_length(mean - half_width);
+                let lower = self.score_from_path_length(mean + half_width);
+                if upper - lower < tolerance {
+                    break;
+                }
+            }
+        }
+
+        self.scale_score(self.score_from_path_length(mean))
+    }
+
+    /// Lazily compute the anomaly scores of the items, so scoring can be fused into
+    /// iterator pipelines without collecting the items or the scores.
+    pub fn score_iter<'a, I>(&'a self, items: I) -> impl Iterator<Item = f64> + 'a
+    where
+        I: IntoIterator<Item = &'a [T; N]>,
+        I::IntoIter: 'a,
+    {
+        items.into_iter().map(move |values| self.score(values))
+    }
+
+    /// Compute the anomaly scores for all items of `data` and write them into `scores`, in
+    /// the order of `data`.
+    ///
+    /// No memory is allocated, so this is suitable for real-time pipelines reusing a
+    /// buffer. Fails with [`Error::DimensionMismatch`] when `scores` does not hold exactly
+    /// one score per item.
+    pub fn score_many_into(&self, data: &[[T; N]], scores: &mut [f64]) -> Result<(), Error> {
+        if scores.len() != data.len() {
+            return Err(Error::DimensionMismatch {
+                expected: data.len(),
+                actual: scores.len(),
+            });
+        }
+        for (values, score) in data.iter().zip(scores.iter_mut()) {
+            *score = self.score(values);
+        }
+        Ok(())
+    }
+
+    /// Compute the anomaly scores for all items of `data`, passing blocks of rows through
+    /// each tree together.
+    ///
+    /// Within a block the values are stored feature by feature and the rows reaching a node
+    /// are kept next to each other, so the projections onto its hyperplane are computed over
+    /// contiguous slices of many rows, which the compiler can vectorize. For large batches
+    /// this is faster than scoring the rows one by one, see `benches/score.rs`.
+    /// The scores are returned in the order of `data`.
+    pub fn score_batch_columnar(&self, data: &[[T; N]]) -> Vec<f64> {
+        let mut scores = Vec::with_capacity(data.len());
+        // buffers shared by all blocks
+        let block_size = data.len().min(COLUMNAR_BLOCK_SIZE);
+        let mut block = Vec::with_capacity(block_size);
+        let mut columns = vec![T::zero(); N * block_size];
+        let mut tree_columns = columns.clone();
+        let mut rows = Vec::with_capacity(block_size);
+        let mut projections = vec![T::zero(); block_size];
+        let mut path_lengths = Vec::with_capacity(block_size);
+        for items in data.chunks(COLUMNAR_BLOCK_SIZE) {
+            let n_rows = items.len();
+            block.clear();
+            block.extend(items.iter().map(|values| self.pipeline.transform(values)));
+            to_columns(block.iter().copied(), n_rows, &mut columns);
+
+            path_lengths.clear();
+            path_lengths.resize(n_rows, 0.0);
+            for tree in self.trees.iter() {
+                if tree.transform.is_some() {
+                    to_columns(
+                        block.iter().map(|values| tree.transform(values)),
+                        n_rows,
+                        &mut tree_columns,
+                    );
+                } else {
+                    tree_columns[..N * n_rows].copy_from_slice(&columns[..N * n_rows]);
+                }
+                rows.clear();
+                rows.extend(0..n_rows);
+                tree.add_path_lengths_columnar(
+                    &mut tree_columns[..N * n_rows],
+                    &mut rows,
+                    &mut projections[..n_rows],
+                    &mut path_lengths,
+                );
+            }
+            scores.extend(path_lengths.iter().zip(items).map(|(path_length, values)| {
+                self.scale_score(self.non_finite_score(values).unwrap_or_else(|| {
+                    self.score_from_path_length(path_length / self.trees.len() as f64)
+                }))
+            }));
+        }
+        scores
+    }
+
+    /// Compute the anomaly scores for all items of `data` using `n_threads` threads.
+    ///
+    /// The batch is split into contiguous chunks, one per thread. The scores are returned in
+    /// the order of `data`.
+    pub fn score_batch_threads(&self, data: &[[T; N]], n_threads: usize) -> Vec<f64>
+    where
+        T: Sync,
+    {
+        let mut scores = vec![0.0; data.len()];
+        if data.is_empty() {
+            return scores;
+        }
+        let chunk_size = (data.len() + n_threads.max(1) - 1) / n_threads.max(1);
+
+        std::thread::scope(|scope| {
+            for (data_chunk, scores_chunk) in
+                data.chunks(chunk_size).zip(scores.chunks_mut(chunk_size))
+            {
+                scope.spawn(move || {
+                    for (values, score) in data_chunk.iter().zip(scores_chunk.iter_mut()) {
+                        *score = self.score(values);
+                    }
+                });
+            }
+        });
+        scores
+    }
+
+    /// Compute the anomaly scores for all items of `data`, scoring each distinct item only
+    /// once.
+    ///
+    /// Items are distinct when any value differs in its bits, except for the sign of zero.
+    /// Batches of events often repeat the same few feature vectors, which are then scored
+    /// at the cost of hashing them. The scores are returned in the order of `data` and
+    /// equal those of [`Forest::score`].
+    pub fn score_batch_dedup(&self, data: &[[T; N]]) -> Vec<f64> {
+        let mut scores_by_item: HashMap<[u64; N], f64> = HashMap::new();
+        data.iter()
+            .map(|values| {
+                let key = values.map(|value| (as_f64(&value) + 0.0).to_bits());
+                *scores_by_item
+                    .entry(key)
+                    .or_insert_with(|| self.score(values))
+            })
+            .collect()
+    }
+
+    /// Anomaly score for an average normalized path length `eh`.
+    #[inline]
+    fn score_from_path_length(&self, eh: f64) -> f64 {
+        math::exp2(-eh)
+    }
+
+    /// Return the indices and scores of the `k` most anomalous rows of `data`, ordered
+    /// by descending score.
+    ///
+    /// Only a bounded heap of `k` entries is kept while scoring, so this avoids scoring
+    /// and sorting the complete batch.
+    pub fn top_k(&self, data: &[[T; N]], k: usize) -> Vec<(usize, f64)> {
+        if k == 0 {
+            return vec![];
+        }
+
+        // min-heap holding the k highest scores seen so far
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (index, values) in data.iter().enumerate() {
+            heap.push(Reverse(Ranked {
+                score: self.score(values),
+                item: index,
+            }));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| (ranked.item, ranked.score))
+            .collect()
+    }
+
+    /// Flag the `fraction` (in `[0, 1]`) of the rows of `data` with the highest scores, e.g.
+    /// `0.01` for the top 1%, as long as no absolute threshold is known.
+    ///
+    /// The number of flagged rows is `fraction * data.len()` rounded up, ignoring the
+    /// rounding error of the product, so a non-zero fraction flags at least one row. Which of several rows with the score at the boundary
+    /// are flagged is unspecified. The labels are returned in the order of `data`.
+    pub fn label_top_fraction(&self, data: &[[T; N]], fraction: f64) -> Vec<bool> {
+        let k = fraction.clamp(0.0, 1.0) * data.len() as f64;
+        // the product is rounded, e.g. 0.07 * 100 exceeds 7, which must not flag an 8th row
+        let k = (k - k * 4.0 * f64::EPSILON).ceil() as usize;
+        let mut labels = vec![false; data.len()];
+        for (index, _) in self.top_k(data, k.min(data.len())) {
+            labels[index] = true;
+        }
+        labels
+    }
+
+    /// Isolation kernel similarity of two items: the fraction of trees in which both
+    /// items end up in the same leaf.
+    ///
+    /// The similarity is `1.0` for identical items and decreases the more the items are
+    /// separated by the trees. It adapts to the density of the training data: items in
+    /// sparse regions are separated more quickly than items in dense regions.
+    pub fn similarity(&self, a: &[T; N], b: &[T; N]) -> f64 {
+        let (a, b) = (&self.pipeline.transform(a), &self.pipeline.transform(b));
+        let same_leaf = self
+            .trees
+            .iter()
+            .filter(|tree| std::ptr::eq(tree.leaf(a), tree.leaf(b)))
+            .count();
+        same_leaf as f64 / self.trees.len() as f64
+    }
+
+    /// Ids of the leaves the item ends up in, one per tree.
+    ///
+    /// The ids are stable for a forest and are in the range `0..n` for a tree with `n`
+    /// leaves, see [`Forest::leaf_counts`]. Together they can be used to embed items as
+    /// sparse one-hot vectors.
+    pub fn leaf_indices(&self, values: &[T; N]) -> Vec<u32> {
+        let values = &self.pipeline.transform(values);
+        self.trees.iter().map(|tree| tree.leaf(values).id).collect()
+    }
+
+    /// Number of leaves of each tree.
+    pub fn leaf_counts(&self) -> Vec<u32> {
+        self.trees.iter().map(|tree| tree.n_leaves).collect()
+    }
+
+    /// Statistics collected while building each tree.
+    ///
+    /// The statistics of forests deserialized from older versions are empty.
+    pub fn tree_stats(&self) -> Vec<&TreeStats> {
+        self.trees.iter().map(|tree| &tree.stats).collect()
+    }
+
+    /// Approximate number of bytes used by the forest, including all heap allocations of
+    /// its trees and preprocessing steps.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.pipeline.heap_size()
+            + self
+                .trees
+                .iter()
+                .map(|tree| std::mem::size_of::<Tree<T, N>>() + tree.heap_size())
+                .sum::<usize>()
+    }
+
+    /// Bounding boxes of the training samples in the leaves the item ends up in, one per
+    /// tree.
+    ///
+    /// `None` when the forest has been built without
+    /// [`ForestOptions::record_leaf_bounds`] or for leaves without any samples. The bounds
+    /// refer to the preprocessed items, see [`ForestOptions::preprocessing`].
+    pub fn leaf_bounds(&self, values: &[T; N]) -> Vec<Option<&LeafBounds<T, N>>> {
+        let values = &self.pipeline.transform(values);
+        self.trees
+            .iter()
+            .map(|tree| tree.leaf(values).bounds.as_deref())
+            .collect()
+    }
+
+    /// The dimensions in which the item lies outside of the bounding boxes of the leaves it
+    /// ended up in.
+    ///
+    /// For anomalies this describes which ranges of normal data have been exceeded. Requires
+    /// the forest to be built with [`ForestOptions::record_leaf_bounds`]. Values and bounds
+    /// refer to the preprocessed item.
+    pub fn bounds_violations(&self, values: &[T; N]) -> Vec<BoundsViolation<T>> {
+        let mut violations = vec![];
+        let bounds = self.leaf_bounds(values);
+        let values = self.pipeline.transform(values);
+        for (tree, bounds) in bounds.into_iter().enumerate() {
+            if let Some(bounds) = bounds {
+                for (dimension, value) in values.iter().copied().enumerate() {
+                    if value < bounds.min[dimension] || value > bounds.max[dimension] {
+                        violations.push(BoundsViolation {
+                            tree,
+                            dimension,
+                            value,
+                            min: bounds.min[dimension],
+                            max: bounds.max[dimension],
+                        })
+                    }
+                }
+            }
+        }
+        violations
+    }
+
+    /// Out-of-bag scores of the training data: each row is only scored by the trees which
+    /// have not been built from it.
+    ///
+    /// These scores are less optimistic than the scores of [`Forest::score`] and help
+    /// selecting thresholds without a holdout set. `training_data` must be the data the
+    /// forest has been built from. Returns `None` when the forest has been built without
+    /// [`ForestOptions::record_samples`]. Rows which every tree has been built from get a
+    /// score of NaN. Trees without recorded samples, e.g. trees replaced by
+    /// [`replacement::TreeReplacement`], are ignored.
+    pub fn oob_scores(&self, training_data: &[[T; N]]) -> Option<Vec<f64>> {
+        let trees: Vec<_> = self
+            .trees
+            .iter()
+            .filter_map(|tree| tree.samples.as_ref().map(|samples| (tree, samples)))
+            .collect();
+        if trees.is_empty() {
+            return None;
+        }
+
+        let scores = training_data
+            .iter()
+            .enumerate()
+            .map(|(row, values)| {
+                if let Some(score) = self.non_finite_score(values) {
+                    return self.scale_score(score);
+                }
+                let values = &self.pipeline.transform(values);
+                let (path_length, count) = trees
+                    .iter()
+                    .filter(|(_, samples)| samples.binary_search(&row).is_err())
+                    .fold((0.0, 0usize), |(sum, count), (tree, _)| {
+                        let path_length = tree.adjusted_path_length(values);
+                        (sum + path_length * tree.path_length_c_inv, count + 1)
+                    });
+                if count == 0 {
+                    f64::NAN
+                } else {
+                    self.scale_score(self.score_from_path_length(path_length / count as f64))
+                }
+            })
+            .collect();
+        Some(scores)
+    }
+
+    /// Mass-based dissimilarity of two items.
+    ///
+    /// For every tree the smallest node containing both items is determined. The
+    /// dissimilarity is the average number of training samples in these nodes relative
+    /// to the sample size of the tree, so items are dissimilar when they can only be grouped
+    /// together with a large share of the data. The result is within `[0, 1]`, `0` when
+    /// both items share a leaf without samples in every tree.
+    pub fn mass_dissimilarity(&self, a: &[T; N], b: &[T; N]) -> f64 {
+        let (a, b) = (&self.pipeline.transform(a), &self.pipeline.transform(b));
+        let mass: f64 = self
+            .trees
+            .iter()
+            .map(|tree| tree.shared_mass(a, b) as f64 / tree.sample_size().max(1) as f64)
+            .sum();
+        mass / self.trees.len() as f64
     }
 }
 
-/// Average path length of unsuccessful search in a binary search tree given n points
-/// n: Number of data points for the BST.
-///
-/// Returns the average path length of unsuccessful search in a BST
-fn c_factor(n: usize) -> f64 {
-    2.0 * ((n as f64 - 1.0).log(f64::E()) + 0.5772156649) - (2.0 * (n as f64 - 1.0) / n as f64)
+impl<T: fmt::Debug, const N: usize> fmt::Debug for Forest<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Forest")
+            .field("n_trees", &self.trees.len())
+            .field("sample_size", &self.sample_size)
+            .field("sample_size_policy", &self.sample_size_policy)
+            .field("avg_path_length_c", &self.avg_path_length_c)
+            .field("pipeline", &self.pipeline)
+            .field("training_range", &self.training_range)
+            .field("score_scale", &self.score_scale)
+            .finish_non_exhaustive()
+    }
 }
 
-enum Direction {
-    Left,
-    Right,
+/// Object-safe interface for scoring items, which allows holding models of different
+/// dimensionality or float type behind `Box<dyn AnomalyScorer>`.
+pub trait AnomalyScorer {
+    /// Compute the anomaly score for an item, which must contain exactly [`Self::dims`]
+    /// values.
+    fn score_slice(&self, values: &[f64]) -> Result<f64, Error>;
+
+    /// Number of dimensions of the items.
+    fn dims(&self) -> usize;
 }
 
-fn determinate_direction<T, const N: usize>(sample: &[T; N], n: &[T; N], p: &[T; N]) -> Direction
+impl<'de, T, const N: usize> AnomalyScorer for Forest<T, N>
 where
-    T: Float,
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
 {
-    let direction_value = sample
-        .iter()
-        .zip(p.iter())
-        .map(|(sample_val, p_val)| *sample_val - *p_val)
-        .zip(n.iter())
-        .fold(T::zero(), |sum, (sp_val, n_val)| sum + sp_val * (*n_val));
+    fn score_slice(&self, values: &[f64]) -> Result<f64, Error> {
+        if values.len() != N {
+            return Err(Error::DimensionMismatch {
+                expected: N,
+                actual: values.len(),
+            });
+        }
+        let mut converted = [T::zero(); N];
+        for (c, v) in converted.iter_mut().zip(values.iter()) {
+            *c = T::from(*v).unwrap_or_else(T::nan);
+        }
+        Ok(self.score(&converted))
+    }
 
-    if direction_value <= T::zero() {
-        Direction::Left
-    } else {
-        Direction::Right
+    fn dims(&self) -> usize {
+        N
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use rand::distributions::Uniform;
-    use rand::Rng;
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Node<T, const N: usize> {
+    Ex(ExNode<T, N>),
+    In(InNode<T, N>),
+}
 
-    use crate::{Forest, ForestOptions};
+impl<T, const N: usize> Node<T, N> {
+    /// Set the number of samples of internal nodes without samples to the sum of their
+    /// children, which restores the counts of forests serialized before they were stored.
+    /// Returns the number of samples of the node.
+    #[cfg(feature = "serde")]
+    fn restore_num_samples(&mut self) -> usize {
+        match self {
+            Node::Ex(ex_node) => ex_node.num_samples,
+            Node::In(in_node) => {
+                let num_samples =
+                    in_node.left.restore_num_samples() + in_node.right.restore_num_samples();
+                if in_node.num_samples == 0 {
+                    in_node.num_samples = num_samples;
+                }
+                in_node.num_samples
+            }
+        }
+    }
 
-    fn make_f64_forest() -> Forest<f64, 3> {
-        let rng = &mut rand::thread_rng();
-        let distribution = Uniform::new(-4., 4.);
-        let distribution2 = Uniform::new(10., 50.);
+    /// Length of the longest path from this node to an external node.
+    fn depth(&self) -> usize {
+        match self {
+            Node::Ex(_) => 0,
+            Node::In(in_node) => 1 + in_node.left.depth().max(in_node.right.depth()),
+        }
+    }
 
-        let values: Vec<_> = (0..6000)
-            .map(|_| {
-                [
-                    rng.sample(distribution),
-                    rng.sample(distribution),
-                    rng.sample(distribution2),
-                ]
-            })
-            .collect();
+    /// Shortest and longest path length of the leaves of the subtree including their
+    /// precomputed contributions, see [`Tree::adjusted_path_length`].
+    fn path_length_range(&self) -> (f64, f64) {
+        match self {
+            Node::Ex(ex_node) => (ex_node.path_length, ex_node.path_length),
+            Node::In(in_node) => {
+                let (left_min, left_max) = in_node.left.path_length_range();
+                let (right_min, right_max) = in_node.right.path_length_range();
+                (1.0 + left_min.min(right_min), 1.0 + left_max.max(right_max))
+            }
+        }
+    }
 
-        let options = ForestOptions {
-            n_trees: 150,
-            sample_size: 200,
-            max_tree_depth: None,
-            extension_level: 1,
-        };
-        Forest::from_slice(values.as_slice(), &options).unwrap()
+    /// Number of bytes allocated on the heap by this node and its subtree.
+    fn heap_size(&self) -> usize {
+        match self {
+            Node::Ex(ex_node) => ex_node
+                .bounds
+                .as_ref()
+                .map_or(0, |_| std::mem::size_of::<LeafBounds<T, N>>()),
+            Node::In(in_node) => {
+                2 * std::mem::size_of::<Self>()
+                    + in_node.left.heap_size()
+                    + in_node.right.heap_size()
+            }
+        }
+    }
+}
+
+/// Summarizes a node by its number of samples instead of printing the complete subtree.
+impl<T, const N: usize> fmt::Debug for Node<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Ex(ex_node) => f
+                .debug_struct("ExNode")
+                .field("num_samples", &ex_node.num_samples)
+                .field("id", &ex_node.id)
+                .finish(),
+            Node::In(in_node) => f
+                .debug_struct("InNode")
+                .field("num_samples", &in_node.num_samples)
+                .finish_non_exhaustive(),
+        }
     }
+}
 
-    fn assert_anomalies_forest_3d_f64(forest: &Forest<f64, 3>) {
-        // no anomaly
-        assert!(forest.score(&[1.0, 3.0, 25.0]) < 0.52);
-        assert!(forest.score(&[-1.0, 3.0, 25.0]) < 0.52);
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct InNode<T, const N: usize> {
+    /// Left child node.
+    left: Box<Node<T, N>>,
 
-        // anomalies
-        assert!(forest.score(&[-12.0, 6.0, 25.0]) > 0.5);
-        assert!(forest.score(&[-1.0, 2.0, 60.0]) > 0.5);
-        assert!(forest.score(&[-1.0, 2.0, 0.0]) > 0.5);
+    /// Right child node.
+    right: Box<Node<T, N>>,
+
+    /// Normal vector at the root of this tree, which is used in
+    /// creating hyperplanes for splitting criteria
+    #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
+    n: [T; N],
+
+    /// Intercept point through which the hyperplane passes.
+    #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
+    p: [T; N],
+
+    /// Size of the dataset present at the node. Missing in forests serialized by older
+    /// versions, where it is restored from the leaves, see [`Node::restore_num_samples`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    num_samples: usize,
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        from = "SerializedExNode<T, N>",
+        bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>")
+    )
+)]
+struct ExNode<T, const N: usize> {
+    /// Size of the dataset present at the node.
+    num_samples: usize,
+
+    /// Index of the leaf within its tree, assigned in depth-first order.
+    id: u32,
+
+    /// Bounding box of the samples present at the node. Only recorded when
+    /// requested by [`ForestOptions::record_leaf_bounds`].
+    bounds: Option<Box<LeafBounds<T, N>>>,
+
+    /// Contribution of the node to the path lengths of the items reaching it, see
+    /// [`AveragePathLength`]. Computed when the node is created instead of being stored,
+    /// so scoring does not need to evaluate logarithms.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    path_length: f64,
+}
+
+impl<T, const N: usize> ExNode<T, N> {
+    fn new(num_samples: usize, bounds: Option<Box<LeafBounds<T, N>>>) -> Self {
+        Self {
+            num_samples,
+            id: 0,
+            bounds,
+            path_length: AveragePathLength.leaf_path_length(&LeafContext {
+                num_samples,
+                depth: 0,
+                sample_size: num_samples,
+            }),
+        }
     }
+}
 
-    #[test]
-    fn score_forest_3d_f64() {
-        let forest = make_f64_forest();
-        assert_anomalies_forest_3d_f64(&forest);
+/// The serialized fields of an [`ExNode`].
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+struct SerializedExNode<T, const N: usize> {
+    num_samples: usize,
+    /// Missing in forests serialized by older versions, see [`SerializedTree::n_leaves`].
+    #[serde(default)]
+    id: u32,
+    bounds: Option<Box<LeafBounds<T, N>>>,
+}
+
+#[cfg(feature = "serde")]
+impl<T, const N: usize> From<SerializedExNode<T, N>> for ExNode<T, N> {
+    fn from(serialized: SerializedExNode<T, N>) -> Self {
+        Self {
+            id: serialized.id,
+            ..Self::new(serialized.num_samples, serialized.bounds)
+        }
     }
+}
 
-    #[test]
-    pub fn infinte_loop() {
-        // computing EIF for the following vector takes longer than 5 minutes, indicating it might
-        // be entering an infinite loop; this is fixed by using the `are_equal` function, or
-        // upgrading the `rand` dependency to version `0.9` (not released yet)
-        let values = vec![
-            [1.1281417500958157],
-            [1.1281417712248367],
-            [1.1275383774967553],
-            [1.1276021217987937],
-            [1.1277130163266273],
-            [1.1276901742756698],
-            [1.1283566295942595],
-            [1.1283568915441473],
-            [1.1283568915441473],
-            [1.1283568915441473],
-            [1.1283561184593798],
-            [1.1283561184593798],
-            [1.1283561184593798],
-            [1.1277881975313708],
-            [1.1277882030559299],
-            [1.1277882030559299],
-            [1.1277882030559299],
-            [1.1277882030559299],
-            [1.1277882030559299],
-            [1.1277882030559299],
-            [1.127989727033879],
-            [1.127989727033879],
-            [1.1279898045604704],
-            [1.1279898045604704],
-            [1.1279898045604704],
-            [1.1279898045604704],
-            [1.1279896357496864],
-            [1.1279871685111489],
-            [1.127987257926914],
-            [1.1279874922711894],
-            [1.1279874922711894],
-            [1.1279904753612784],
-            [1.1279904182835565],
-            [1.1279904182835565],
-            [1.1279904182835565],
-            [1.1279904182835565],
+/// How [`Forest::score_checked`] treats items outside of the range of the training data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RangeHandling {
+    /// Only report the dimensions which are out of range, the score is unchanged.
+    Flag,
+
+    /// Score the item after clamping it to the training range.
+    Clamp,
+
+    /// Move the score towards `1.0`, halving the distance for every range width the item
+    /// lies outside of the training range.
+    Boost,
+}
+
+/// Anomaly score together with the result of a range check, see
+/// [`Forest::score_checked`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckedScore {
+    pub score: f64,
+
+    /// The dimensions in which the item lies outside of the range of the training data.
+    pub out_of_range: Vec<usize>,
+}
+
+/// Axis-aligned bounding box of training samples, e.g. of the samples which ended up in
+/// a leaf.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))
+)]
+pub struct LeafBounds<T, const N: usize> {
+    /// Smallest value in each dimension.
+    #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
+    pub min: [T; N],
+
+    /// Largest value in each dimension.
+    #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
+    pub max: [T; N],
+}
+
+/// A dimension in which an item lies outside of the bounding box of the leaf it ended
+/// up in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundsViolation<T> {
+    /// Index of the tree.
+    pub tree: usize,
+
+    /// Index of the dimension.
+    pub dimension: usize,
+
+    /// The value of the item in this dimension.
+    pub value: T,
+
+    /// Smallest value of the training samples in the leaf in this dimension.
+    pub min: T,
+
+    /// Largest value of the training samples in the leaf in this dimension.
+    pub max: T,
+}
+
+/// Parameters controlling the construction of a single tree.
+struct TreeParameters<'a, T, const N: usize> {
+    max_tree_depth: usize,
+    extension_level: usize,
+    split_tolerance: f64,
+    record_leaf_bounds: bool,
+    record_samples: bool,
+    random_tie_breaking: bool,
+    rotation_subset_size: Option<usize>,
+    feature_map: Option<FeatureMap>,
+    max_nodes: usize,
+    normal_scaling: NormalScaling,
+    normal_distribution: NormalDistribution,
+    node_features: Option<usize>,
+    /// Standard deviation of the samples of the tree in each dimension, only computed for
+    /// [`NormalScaling::SampleStdDev`].
+    sample_std_dev: Option<[f64; N]>,
+    split_strategy: &'a dyn SplitStrategy<T, N>,
+}
+
+/// A single isolation tree of a [`Forest`], see [`Forest::trees`] and [`Forest::from_trees`].
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "SerializedTree<T, N>",
+        bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>")
+    )
+)]
+pub struct Tree<T, const N: usize> {
+    root: Node<T, N>,
+
+    /// Number of external nodes of the tree.
+    n_leaves: u32,
+
+    /// Transformation applied to the items before passing them to the root.
+    #[cfg_attr(feature = "serde", serde(default))]
+    transform: Option<TreeTransform>,
+
+    /// Sorted indices of the training rows the tree has been built from. Only recorded
+    /// when requested by [`ForestOptions::record_samples`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    samples: Option<Vec<usize>>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    stats: TreeStats,
+
+    /// Inverse of the average path length normalizing the path lengths of the tree, see
+    /// [`Tree::path_length_c`]. Computed when the tree is created instead of being stored.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    path_length_c_inv: f64,
+
+    /// Shortest and longest path length of any item, see [`Node::path_length_range`].
+    /// Computed when the tree is created instead of being stored.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    path_length_range: (f64, f64),
+}
+
+/// The serialized fields of a [`Tree`].
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+struct SerializedTree<T, const N: usize> {
+    root: Node<T, N>,
+    /// Missing in forests serialized by older versions together with the ids of the
+    /// leaves, which are then assigned again. Every tree has a leaf, so `0` is never
+    /// stored.
+    #[serde(default)]
+    n_leaves: u32,
+    #[serde(default)]
+    transform: Option<TreeTransform>,
+    #[serde(default)]
+    samples: Option<Vec<usize>>,
+    #[serde(default)]
+    stats: TreeStats,
+}
+
+#[cfg(feature = "serde")]
+impl<T, const N: usize> TryFrom<SerializedTree<T, N>> for Tree<T, N> {
+    type Error = &'static str;
+
+    fn try_from(mut serialized: SerializedTree<T, N>) -> Result<Self, Self::Error> {
+        if let Some(transform) = &serialized.transform {
+            if !transform.is_valid::<N>() {
+                return Err("tree transform does not fit the number of dimensions");
+            }
+        }
+        serialized.root.restore_num_samples();
+        let n_leaves = match serialized.n_leaves {
+            0 => assign_leaf_ids(&mut serialized.root, 0),
+            n_leaves => n_leaves,
+        };
+        Ok(Self::from_parts(
+            serialized.root,
+            n_leaves,
+            serialized.transform,
+            serialized.samples,
+            serialized.stats,
+        ))
+    }
+}
+
+/// Statistics collected while building a tree, see [`Forest::tree_stats`].
+///
+/// Equality ignores [`TreeStats::build_time`], so trees and forests compare equal
+/// independent of how long it took to build them.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TreeStats {
+    /// Number of nodes of the tree.
+    pub n_nodes: usize,
+
+    /// Length of the longest path from the root to a leaf.
+    pub max_depth: usize,
+
+    /// Number of splits which sent all samples of a node to one side. Many of them
+    /// indicate data the split strategy can barely separate, e.g. near-duplicates.
+    pub n_empty_splits: usize,
+
+    /// Time it took to build the tree.
+    pub build_time: Duration,
+}
+
+impl PartialEq for TreeStats {
+    fn eq(&self, other: &Self) -> bool {
+        self.n_nodes == other.n_nodes
+            && self.max_depth == other.max_depth
+            && self.n_empty_splits == other.n_empty_splits
+    }
+}
+
+impl TreeStats {
+    fn add_node<T, const N: usize>(&mut self, node: &Node<T, N>, depth: usize) {
+        self.n_nodes += 1;
+        self.max_depth = self.max_depth.max(depth);
+        if let Node::In(in_node) = node {
+            let is_empty = |node: &Node<T, N>| match node {
+                Node::Ex(ex_node) => ex_node.num_samples == 0,
+                Node::In(in_node) => in_node.num_samples == 0,
+            };
+            if is_empty(&in_node.left) || is_empty(&in_node.right) {
+                self.n_empty_splits += 1;
+            }
+            self.add_node(&in_node.left, depth + 1);
+            self.add_node(&in_node.right, depth + 1);
+        }
+    }
+}
+
+/// Transformation of the items specific to a single tree.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum TreeTransform {
+    /// Rotation by a row-major `N`x`N` matrix.
+    Rotation(Vec<f64>),
+
+    /// Random nonlinear map `map(weights * (x - center) + bias)` with a row-major `N`x`N`
+    /// matrix of weights.
+    FeatureMap {
+        map: FeatureMap,
+        weights: Vec<f64>,
+        center: Vec<f64>,
+        bias: Vec<f64>,
+    },
+}
+
+impl TreeTransform {
+    /// Whether the vectors have the lengths of items with `N` features, which is not the
+    /// case for malformed deserialized transforms.
+    fn is_valid<const N: usize>(&self) -> bool {
+        match self {
+            Self::Rotation(matrix) => matrix.len() == N * N,
+            Self::FeatureMap {
+                weights,
+                center,
+                bias,
+                ..
+            } => weights.len() == N * N && center.len() == N && bias.len() == N,
+        }
+    }
+
+    fn apply<T: Float, const N: usize>(&self, values: &[T; N]) -> [T; N] {
+        match self {
+            Self::Rotation(matrix) => {
+                let mut rotated = [T::zero(); N];
+                for (i, r) in rotated.iter_mut().enumerate() {
+                    let v: f64 = (0..N)
+                        .map(|j| matrix[i * N + j] * values[j].to_f64().unwrap_or(f64::NAN))
+                        .sum();
+                    *r = T::from(v).unwrap_or_else(T::nan);
+                }
+                rotated
+            }
+            Self::FeatureMap {
+                map,
+                weights,
+                center,
+                bias,
+            } => {
+                let mut mapped = [T::zero(); N];
+                for (i, m) in mapped.iter_mut().enumerate() {
+                    let v: f64 = bias[i]
+                        + (0..N)
+                            .map(|j| {
+                                weights[i * N + j]
+                                    * (values[j].to_f64().unwrap_or(f64::NAN) - center[j])
+                            })
+                            .sum::<f64>();
+                    let v = match map {
+                        FeatureMap::Fourier { .. } => std::f64::consts::SQRT_2 * math::cos(v),
+                        FeatureMap::Relu => v.max(0.0),
+                    };
+                    *m = T::from(v).unwrap_or_else(T::nan);
+                }
+                mapped
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Tree<T, N> {
+    fn from_parts(
+        root: Node<T, N>,
+        n_leaves: u32,
+        transform: Option<TreeTransform>,
+        samples: Option<Vec<usize>>,
+        stats: TreeStats,
+    ) -> Self {
+        let sample_size = match &root {
+            Node::Ex(ex_node) => ex_node.num_samples,
+            Node::In(in_node) => in_node.num_samples,
+        };
+        let path_length_range = root.path_length_range();
+        Self {
+            root,
+            n_leaves,
+            transform,
+            samples,
+            stats,
+            // trees built from a single sample only have paths of length zero
+            path_length_c_inv: 1.0 / c_factor(sample_size.max(2)),
+            path_length_range,
+        }
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for Tree<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tree")
+            .field("n_leaves", &self.n_leaves)
+            .field("depth", &self.root.depth())
+            .field("root", &self.root)
+            .finish()
+    }
+}
+
+impl<'de, T, const N: usize> Tree<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Build a tree from the rows of `data` referenced by `sample_indices`. `samples` are
+    /// the recorded indices of the training rows, see [`ForestOptions::record_samples`].
+    ///
+    /// The samples are copied to `transformed` and `transformed_indices` when the tree
+    /// transforms the items, which allows to reuse these buffers for several trees.
+    fn new(
+        data: &[[T; N]],
+        sample_indices: &mut [usize],
+        samples: Option<Vec<usize>>,
+        rng: &mut ThreadRng,
+        params: &TreeParameters<T, N>,
+        transformed: &mut Vec<[T; N]>,
+        transformed_indices: &mut Vec<usize>,
+    ) -> Self {
+        let start = Instant::now();
+        let transform = match (params.feature_map, params.rotation_subset_size) {
+            (Some(map), _) => Some(random_feature_map(data, sample_indices, map, rng)),
+            (None, Some(subset_size)) => Some(pca_rotation(data, sample_indices, subset_size, rng)),
+            (None, None) => None,
+        };
+
+        let (data, sample_indices) = if let Some(transform) = &transform {
+            transformed.clear();
+            transformed.extend(
+                sample_indices
+                    .iter()
+                    .map(|idx| transform.apply(&data[*idx])),
+            );
+            transformed_indices.clear();
+            transformed_indices.extend(0..transformed.len());
+            (transformed.as_slice(), transformed_indices.as_mut_slice())
+        } else {
+            (data, sample_indices)
+        };
+        let params = TreeParameters {
+            sample_std_dev: (params.normal_scaling == NormalScaling::SampleStdDev)
+                .then(|| std_dev(data, sample_indices)),
+            ..*params
+        };
+
+        let mut node_budget = params.max_nodes;
+        let mut root = make_node(data, sample_indices, rng, 0, &mut node_budget, &params);
+        let n_leaves = assign_leaf_ids(&mut root, 0);
+        let mut stats = TreeStats::default();
+        stats.add_node(&root, 0);
+        stats.build_time = start.elapsed();
+        Self::from_parts(root, n_leaves, transform, samples, stats)
+    }
+
+    /// Number of bytes allocated on the heap by the nodes and the transformation.
+    fn heap_size(&self) -> usize {
+        let transform = match &self.transform {
+            Some(TreeTransform::Rotation(matrix)) => matrix.capacity() * std::mem::size_of::<f64>(),
+            Some(TreeTransform::FeatureMap {
+                weights,
+                center,
+                bias,
+                ..
+            }) => {
+                (weights.capacity() + center.capacity() + bias.capacity())
+                    * std::mem::size_of::<f64>()
+            }
+            None => 0,
+        };
+        let samples = self.samples.as_ref().map_or(0, |samples| {
+            samples.capacity() * std::mem::size_of::<usize>()
+        });
+        self.root.heap_size() + transform + samples
+    }
+
+    /// Apply the transformation of the tree to an item.
+    fn transform(&self, values: &[T; N]) -> [T; N] {
+        match &self.transform {
+            Some(transform) => transform.apply(values),
+            None => *values,
+        }
+    }
+
+    /// length of the path traversed by the point on the tree when it reaches an external node.
+    ///
+    /// The leaves add the estimated path length of their samples given by `leaf_score`, like
+    /// [`AveragePathLength`] of the paper.
+    #[inline]
+    pub fn path_length<L: LeafScore + ?Sized>(&self, values: &[T; N], leaf_score: &L) -> f64 {
+        let values = &self.transform(values);
+        path_length_recurse(&self.root, values, 0, self.sample_size(), leaf_score)
+    }
+
+    /// Length of the path traversed by the point including the precomputed contribution of
+    /// the external node, equal to the path length with [`AveragePathLength`].
+    #[inline]
+    fn adjusted_path_length(&self, values: &[T; N]) -> f64 {
+        let values = &self.transform(values);
+        let mut node = &self.root;
+        let mut depth = 0.0;
+        loop {
+            match node {
+                Node::Ex(ex_node) => return depth + ex_node.path_length,
+                Node::In(in_node) => {
+                    node = match determinate_direction(values, &in_node.n, &in_node.p) {
+                        Direction::Left => in_node.left.as_ref(),
+                        Direction::Right => in_node.right.as_ref(),
+                    };
+                    depth += 1.0;
+                }
+            }
+        }
+    }
+
+    /// Add the normalized path lengths of a block of items to `path_lengths`.
+    ///
+    /// `columns` holds the values of the items feature by feature, with one value per item
+    /// of the block for each feature, and `rows` the index of the item at each position.
+    /// The items have to be transformed already. Both are reordered, so the items of each
+    /// node are stored contiguously and the projections onto its hyperplane are computed
+    /// over contiguous slices.
+    fn add_path_lengths_columnar(
+        &self,
+        columns: &mut [T],
+        rows: &mut [usize],
+        projections: &mut [T],
+        path_lengths: &mut [f64],
+    ) {
+        let n_rows = rows.len();
+        let mut block = ColumnarBlock {
+            columns,
+            rows,
+            projections,
+        };
+        block.add_path_lengths(
+            &self.root,
+            (0, n_rows),
+            0,
+            self.path_length_c_inv,
+            path_lengths,
+        );
+    }
+
+    /// Contribution of the tree to the scores of [`Forest::score_raw`]: the path length of
+    /// the item normalized by the average path length of the samples of the tree. The raw
+    /// score of a forest is `2^(-mean)` of the contributions of its trees.
+    pub fn score_contribution(&self, values: &[T; N]) -> f64 {
+        self.adjusted_path_length(values) * self.path_length_c_inv
+    }
+
+    /// Number of leaves of the tree.
+    pub fn n_leaves(&self) -> u32 {
+        self.n_leaves
+    }
+
+    /// Number of samples the tree was built from.
+    pub fn sample_size(&self) -> usize {
+        match &self.root {
+            Node::Ex(ex_node) => ex_node.num_samples,
+            Node::In(in_node) => in_node.num_samples,
+        }
+    }
+
+    /// Average path length of an unsuccessful search in a binary search tree built from the
+    /// samples of this tree, which normalizes its path lengths.
+    fn path_length_c(&self) -> f64 {
+        // trees built from a single sample only have paths of length zero
+        c_factor(self.sample_size().max(2))
+    }
+
+    /// Expected length of the path traversed by the point when routing it into both children
+    /// of nodes whose hyperplane is closer than `margin`.
+    pub fn soft_path_length(&self, values: &[T; N], margin: f64) -> f64 {
+        let values = &self.transform(values);
+        soft_path_length_recurse(&self.root, values, margin)
+    }
+
+    /// Number of samples in the smallest node containing both points.
+    pub fn shared_mass(&self, a: &[T; N], b: &[T; N]) -> usize {
+        let (a, b) = (&self.transform(a), &self.transform(b));
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Ex(ex_node) => return ex_node.num_samples,
+                Node::In(in_node) => {
+                    match (
+                        determinate_direction(a, &in_node.n, &in_node.p),
+                        determinate_direction(b, &in_node.n, &in_node.p),
+                    ) {
+                        (Direction::Left, Direction::Left) => node = in_node.left.as_ref(),
+                        (Direction::Right, Direction::Right) => node = in_node.right.as_ref(),
+                        _ => return in_node.num_samples,
+                    }
+                }
+            }
+        }
+    }
+
+    /// The external node the point ends up in.
+    fn leaf(&self, values: &[T; N]) -> &ExNode<T, N> {
+        self.leaf_with_depth(values).0
+    }
+
+    /// The external node an item ends up in together with its depth.
+    fn leaf_with_depth(&self, values: &[T; N]) -> (&ExNode<T, N>, usize) {
+        let values = &self.transform(values);
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            match node {
+                Node::Ex(ex_node) => return (ex_node, depth),
+                Node::In(in_node) => {
+                    node = match determinate_direction(values, &in_node.n, &in_node.p) {
+                        Direction::Left => in_node.left.as_ref(),
+                        Direction::Right => in_node.right.as_ref(),
+                    };
+                    depth += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Number the external nodes below `node` in depth-first order, starting with `next_id`.
+///
+/// Returns the next free id.
+fn assign_leaf_ids<T, const N: usize>(node: &mut Node<T, N>, next_id: u32) -> u32 {
+    match node {
+        Node::Ex(ex_node) => {
+            ex_node.id = next_id;
+            next_id + 1
+        }
+        Node::In(in_node) => {
+            let next_id = assign_leaf_ids(&mut in_node.left, next_id);
+            assign_leaf_ids(&mut in_node.right, next_id)
+        }
+    }
+}
+
+/// Rotation onto the principal components of the samples, computed separately for random
+/// subsets of `subset_size` features.
+fn pca_rotation<T: Float, const N: usize>(
+    data: &[[T; N]],
+    sample_indices: &[usize],
+    subset_size: usize,
+    rng: &mut ThreadRng,
+) -> TreeTransform {
+    let mut features: Vec<_> = (0..N).collect();
+    features.shuffle(rng);
+
+    let count = sample_indices.len().max(1) as f64;
+    let mut rotation = vec![0.0; N * N];
+    for subset in features.chunks(subset_size.clamp(1, N.max(1))) {
+        let k = subset.len();
+        let value = |idx: usize, feature: usize| data[idx][feature].to_f64().unwrap_or(0.0);
+        let means: Vec<_> = subset
+            .iter()
+            .map(|f| {
+                sample_indices
+                    .iter()
+                    .map(|idx| value(*idx, *f))
+                    .sum::<f64>()
+                    / count
+            })
+            .collect();
+        let mut covariance = vec![0.0; k * k];
+        for idx in sample_indices {
+            for i in 0..k {
+                for j in 0..k {
+                    covariance[i * k + j] += (value(*idx, subset[i]) - means[i])
+                        * (value(*idx, subset[j]) - means[j])
+                        / count;
+                }
+            }
+        }
+
+        let (_, vectors) = symmetric_eigen(covariance, k);
+        for i in 0..k {
+            for j in 0..k {
+                rotation[subset[i] * N + subset[j]] = vectors[i * k + j];
+            }
+        }
+    }
+    TreeTransform::Rotation(rotation)
+}
+
+/// Random nonlinear feature map, projecting the standardized samples onto random
+/// directions.
+fn random_feature_map<T: Float, const N: usize>(
+    data: &[[T; N]],
+    sample_indices: &[usize],
+    map: FeatureMap,
+    rng: &mut ThreadRng,
+) -> TreeTransform {
+    let count = sample_indices.len().max(1) as f64;
+    let value = |idx: usize, feature: usize| data[idx][feature].to_f64().unwrap_or(0.0);
+    let center: Vec<_> = (0..N)
+        .map(|j| sample_indices.iter().map(|idx| value(*idx, j)).sum::<f64>() / count)
+        .collect();
+    let std_dev = std_dev(data, sample_indices);
+
+    let scale = match map {
+        FeatureMap::Fourier { bandwidth } => 1.0 / bandwidth,
+        // keep the projections at the scale of a single standardized feature
+        FeatureMap::Relu => 1.0 / (N as f64).sqrt(),
+    };
+    let weights = (0..N * N)
+        .map(|k| {
+            let spread = std_dev[k % N];
+            let w: f64 = rng.sample(StandardNormal);
+            if spread > 0.0 {
+                w * scale / spread
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let bias = (0..N)
+        .map(|_| match map {
+            FeatureMap::Fourier { .. } => rng.gen_range(0.0..2.0 * std::f64::consts::PI),
+            FeatureMap::Relu => rng.gen_range(-1.0..1.0),
+        })
+        .collect();
+    TreeTransform::FeatureMap {
+        map,
+        weights,
+        center,
+        bias,
+    }
+}
+
+/// Number of rows of data given feature by feature. Fails with [`Error::DimensionMismatch`]
+/// when there are not exactly `n_columns` columns or the columns differ in length.
+fn column_length<T>(columns: &[&[T]], n_columns: usize) -> Result<usize, Error> {
+    if columns.len() != n_columns {
+        return Err(Error::DimensionMismatch {
+            expected: n_columns,
+            actual: columns.len(),
+        });
+    }
+    let n_rows = columns.first().map_or(0, |column| column.len());
+    if let Some(column) = columns.iter().find(|column| column.len() != n_rows) {
+        return Err(Error::DimensionMismatch {
+            expected: n_rows,
+            actual: column.len(),
+        });
+    }
+    Ok(n_rows)
+}
+
+/// Build `n_trees` trees from the training data. The samples of the trees are stratified
+/// over `groups` when given, which must contain one group per row.
+///
+/// Returns the effective sample size together with the trees.
+fn build_trees<'de, T, R, const N: usize>(
+    training_data: &R,
+    groups: Option<&[usize]>,
+    options: &ForestOptions,
+    n_trees: usize,
+    split_strategy: &dyn SplitStrategy<T, N>,
+) -> Result<(usize, Vec<Tree<T, N>>), Error>
+where
+    T: ForestFloat<'de> + SampleUniform + Default + Send + Sync,
+    R: TrainingRows<T, N> + Sync + ?Sized,
+    StandardNormal: Distribution<T>,
+{
+    let n_rows = training_data.n_rows();
+    if n_rows == 0 || N == 0 {
+        return Err(Error::InsufficientTrainingData);
+    }
+    // the effective sample size and whether it requires bootstrapping
+    let resolve_sample_size = |sample_size: usize| {
+        if n_rows >= sample_size {
+            Ok((sample_size, false))
+        } else {
+            match options.sample_size_policy {
+                SampleSizePolicy::Strict => Err(Error::InsufficientTrainingData),
+                SampleSizePolicy::Clamp => Ok((n_rows, false)),
+                SampleSizePolicy::Bootstrap => Ok((sample_size, true)),
+            }
+        }
+    };
+    let (sample_size, _) = resolve_sample_size(options.sample_size)?;
+    let max_sample_size = options
+        .max_sample_size
+        .map_or(options.sample_size, |max| max.max(options.sample_size));
+    resolve_sample_size(max_sample_size)?;
+    let extension_level = options.extension_level.resolve(N)?;
+    if options.split_tolerance.is_nan() || options.split_tolerance < 0.0 {
+        return Err(Error::InvalidOption {
+            name: "split_tolerance",
+        });
+    }
+    if let NormalDistribution::Sparse { density } = options.normal_distribution {
+        if !(density > 0.0 && density <= 1.0) {
+            return Err(Error::InvalidOption {
+                name: "normal_distribution",
+            });
+        }
+    }
+    if let Some(FeatureMap::Fourier { bandwidth }) = options.feature_map {
+        if !(bandwidth > 0.0 && bandwidth.is_finite()) {
+            return Err(Error::InvalidOption {
+                name: "feature_map",
+            });
+        }
+    }
+    let strata = groups.map(group_rows);
+
+    let tree_params = TreeParameters {
+        max_tree_depth: 0,
+        extension_level,
+        split_tolerance: options.split_tolerance,
+        record_leaf_bounds: options.record_leaf_bounds,
+        record_samples: options.record_samples,
+        random_tie_breaking: options.random_tie_breaking,
+        rotation_subset_size: options.rotation_subset_size,
+        feature_map: options.feature_map,
+        // the budget is based on the size of the whole forest, also when only some of its
+        // trees are built
+        max_nodes: options
+            .max_total_nodes
+            .map_or(usize::MAX, |total| (total / options.n_trees.max(1)).max(1)),
+        normal_scaling: options.normal_scaling,
+        normal_distribution: options.normal_distribution,
+        node_features: options.node_features,
+        sample_std_dev: None,
+        split_strategy,
+    };
+
+    // build the trees
+    let build_n_trees = |n_trees: usize| {
+        let rng = &mut rand::thread_rng();
+        let mut scratch = TrainScratch::default();
+        (0..n_trees)
+            .map(|_| {
+                let requested = rng.gen_range(options.sample_size..=max_sample_size);
+                // both bounds have been resolved successfully before
+                let (sample_size, bootstrap) =
+                    resolve_sample_size(requested).unwrap_or((sample_size, false));
+                let tree_params = TreeParameters {
+                    max_tree_depth: options
+                        .max_tree_depth
+                        .unwrap_or_else(|| (sample_size as f64).log2().ceil() as usize),
+                    ..tree_params
+                };
+
+                if let Some(strata) = &strata {
+                    scratch.sample = stratified_sample(strata, n_rows, sample_size, bootstrap, rng);
+                } else {
+                    scratch.sample_rows(n_rows, sample_size, bootstrap, rng);
+                }
+                let samples = tree_params.record_samples.then(|| {
+                    let mut samples = scratch.sample.clone();
+                    samples.sort_unstable();
+                    samples.dedup();
+                    samples
+                });
+
+                let (data, sample_indices) = match training_data.as_slice() {
+                    Some(data) => (data, &mut scratch.sample),
+                    None => {
+                        scratch.gathered.clear();
+                        scratch
+                            .gathered
+                            .extend(scratch.sample.iter().map(|row| training_data.row(*row)));
+                        scratch.gathered_indices.clear();
+                        scratch.gathered_indices.extend(0..scratch.gathered.len());
+                        (scratch.gathered.as_slice(), &mut scratch.gathered_indices)
+                    }
+                };
+                Tree::new(
+                    data,
+                    sample_indices,
+                    samples,
+                    rng,
+                    &tree_params,
+                    &mut scratch.transformed,
+                    &mut scratch.transformed_indices,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let n_threads = options.max_threads.clamp(1, n_trees.max(1));
+    let trees = if n_threads == 1 {
+        build_n_trees(n_trees)
+    } else {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..n_threads)
+                .map(|thread_idx| {
+                    // distribute the trees as evenly as possible over the threads
+                    let n_trees =
+                        n_trees / n_threads + usize::from(thread_idx < n_trees % n_threads);
+                    scope.spawn(move || build_n_trees(n_trees))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|err| std::panic::resume_unwind(err))
+                })
+                .collect()
+        })
+    };
+    Ok((sample_size, trees))
+}
+
+/// The mean or median of the values of each feature which are not NaN, `NaN` for features
+/// without such values.
+fn imputation_values<T: Float, R: TrainingRows<T, N> + ?Sized, const N: usize>(
+    data: &R,
+    imputation: Imputation,
+) -> Vec<f64> {
+    (0..N)
+        .map(|i| {
+            let mut column: Vec<_> = (0..data.n_rows())
+                .map(|row| data.row(row)[i].to_f64().unwrap_or(f64::NAN))
+                .filter(|v| !v.is_nan())
+                .collect();
+            match imputation {
+                Imputation::Mean => column.iter().sum::<f64>() / column.len() as f64,
+                Imputation::Median => {
+                    sort_scores(&mut column);
+                    quantile_sorted(&column, 0.5).unwrap_or(f64::NAN)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Training data which is read row by row while building a forest, so it does not have to
+/// be stored row by row, see [`Forest::from_columns`].
+trait TrainingRows<T, const N: usize> {
+    /// Number of rows.
+    fn n_rows(&self) -> usize;
+
+    /// The values of a row.
+    fn row(&self, row: usize) -> [T; N];
+
+    /// The rows if they are stored row by row, so the trees are built without copying
+    /// their samples.
+    fn as_slice(&self) -> Option<&[[T; N]]> {
+        None
+    }
+}
+
+impl<T: Copy, const N: usize> TrainingRows<T, N> for [[T; N]] {
+    fn n_rows(&self) -> usize {
+        self.len()
+    }
+
+    fn row(&self, row: usize) -> [T; N] {
+        self[row]
+    }
+
+    fn as_slice(&self) -> Option<&[[T; N]]> {
+        Some(self)
+    }
+}
+
+/// Training data given feature by feature, with `n_rows` values per feature.
+struct Columns<'a, T> {
+    columns: &'a [&'a [T]],
+    n_rows: usize,
+}
+
+impl<T: Copy, const N: usize> TrainingRows<T, N> for Columns<'_, T> {
+    fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    fn row(&self, row: usize) -> [T; N] {
+        std::array::from_fn(|i| self.columns[i][row])
+    }
+}
+
+/// Training data passed through a fitted pipeline row by row.
+struct Preprocessed<'a, R: ?Sized> {
+    rows: &'a R,
+    pipeline: &'a Pipeline,
+}
+
+impl<T: Float, R: TrainingRows<T, N> + ?Sized, const N: usize> TrainingRows<T, N>
+    for Preprocessed<'_, R>
+{
+    fn n_rows(&self) -> usize {
+        self.rows.n_rows()
+    }
+
+    fn row(&self, row: usize) -> [T; N] {
+        self.pipeline.transform(&self.rows.row(row))
+    }
+}
+
+/// Buffers reused for all trees built by a thread, so building a tree does not allocate
+/// besides its nodes.
+struct TrainScratch<T, const N: usize> {
+    /// Row indices of the sample of the current tree.
+    sample: Vec<usize>,
+
+    /// Rows drawn so far when sampling few of the rows without replacement.
+    drawn: HashSet<usize>,
+
+    /// Permutation of all rows when sampling most of the rows without replacement.
+    permutation: Vec<usize>,
+
+    /// Samples of the current tree copied from training data not stored row by row, see
+    /// [`TrainingRows::as_slice`].
+    gathered: Vec<[T; N]>,
+    gathered_indices: Vec<usize>,
+
+    /// Transformed samples of the current tree, see [`TreeTransform`].
+    transformed: Vec<[T; N]>,
+    transformed_indices: Vec<usize>,
+}
+
+impl<T, const N: usize> Default for TrainScratch<T, N> {
+    fn default() -> Self {
+        Self {
+            sample: vec![],
+            drawn: HashSet::new(),
+            permutation: vec![],
+            gathered: vec![],
+            gathered_indices: vec![],
+            transformed: vec![],
+            transformed_indices: vec![],
+        }
+    }
+}
+
+impl<T, const N: usize> TrainScratch<T, N> {
+    /// Draw `sample_size` of `n_rows` row indices into `self.sample`, with replacement if
+    /// `bootstrap` is set.
+    fn sample_rows(
+        &mut self,
+        n_rows: usize,
+        sample_size: usize,
+        bootstrap: bool,
+        rng: &mut ThreadRng,
+    ) {
+        self.sample.clear();
+        if bootstrap {
+            self.sample
+                .extend((0..sample_size).map(|_| rng.gen_range(0..n_rows)));
+        } else if sample_size >= n_rows {
+            // every tree is built from all rows, there is nothing to draw
+            self.sample.extend(0..n_rows);
+        } else if 2 * sample_size > n_rows {
+            // partial Fisher-Yates shuffle, which draws a uniform sample from any permutation
+            if self.permutation.len() != n_rows {
+                self.permutation.clear();
+                self.permutation.extend(0..n_rows);
+            }
+            for i in 0..sample_size {
+                let j = rng.gen_range(i..n_rows);
+                self.permutation.swap(i, j);
+            }
+            self.sample
+                .extend_from_slice(&self.permutation[..sample_size]);
+        } else {
+            // rejection sampling, which needs at most two draws per row on average
+            self.drawn.clear();
+            while self.sample.len() < sample_size {
+                let row = rng.gen_range(0..n_rows);
+                if self.drawn.insert(row) {
+                    self.sample.push(row);
+                }
+            }
+        }
+    }
+}
+
+/// The indices of the rows of each group, in order of the first occurrence of the groups.
+fn group_rows(groups: &[usize]) -> Vec<Vec<usize>> {
+    let mut positions = HashMap::new();
+    let mut strata: Vec<Vec<usize>> = vec![];
+    for (row, group) in groups.iter().enumerate() {
+        let position = *positions.entry(*group).or_insert_with(|| {
+            strata.push(vec![]);
+            strata.len() - 1
+        });
+        strata[position].push(row);
+    }
+    strata
+}
+
+/// Draw `sample_size` row indices, allocating them to the strata in proportion to their
+/// size using the largest remainder method.
+fn stratified_sample(
+    strata: &[Vec<usize>],
+    n_rows: usize,
+    sample_size: usize,
+    bootstrap: bool,
+    rng: &mut ThreadRng,
+) -> Vec<usize> {
+    let shares: Vec<_> = strata
+        .iter()
+        .map(|rows| (sample_size * rows.len()) as f64 / n_rows as f64)
+        .collect();
+    let mut allocation: Vec<_> = shares.iter().map(|share| share.floor() as usize).collect();
+    let mut by_remainder: Vec<_> = (0..strata.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        let remainder = |i: usize| shares[i] - allocation[i] as f64;
+        remainder(*b)
+            .partial_cmp(&remainder(*a))
+            .unwrap_or(Ordering::Equal)
+    });
+    let missing = sample_size - allocation.iter().sum::<usize>();
+    for i in by_remainder.into_iter().take(missing) {
+        allocation[i] += 1;
+    }
+
+    // move rows from the largest allocations to groups which would be absent otherwise
+    if sample_size >= strata.len() {
+        for i in 0..strata.len() {
+            if allocation[i] == 0 {
+                if let Some(largest) = (0..strata.len()).max_by_key(|j| allocation[*j]) {
+                    allocation[largest] -= 1;
+                    allocation[i] = 1;
+                }
+            }
+        }
+    }
+
+    let mut sample = Vec::with_capacity(sample_size);
+    for (rows, n) in strata.iter().zip(allocation) {
+        if bootstrap {
+            sample.extend((0..n).map(|_| rows[rng.gen_range(0..rows.len())]));
+        } else {
+            // the allocation never exceeds the size of a stratum without bootstrapping
+            sample.extend(rows.choose_multiple(rng, n.min(rows.len())).copied());
+        }
+    }
+    sample
+}
+
+fn path_length_recurse<T, L, const N: usize>(
+    node: &Node<T, N>,
+    values: &[T; N],
+    depth: usize,
+    sample_size: usize,
+    leaf_score: &L,
+) -> f64
+where
+    T: Float,
+    L: LeafScore + ?Sized,
+{
+    match node {
+        Node::Ex(ex_node) => leaf_score.leaf_path_length(&LeafContext {
+            num_samples: ex_node.num_samples,
+            depth,
+            sample_size,
+        }),
+        Node::In(in_node) => {
+            1.0 + path_length_recurse(
+                match determinate_direction(values, &in_node.n, &in_node.p) {
+                    Direction::Left => in_node.left.as_ref(),
+                    Direction::Right => in_node.right.as_ref(),
+                },
+                values,
+                depth + 1,
+                sample_size,
+                leaf_score,
+            )
+        }
+    }
+}
+
+fn soft_path_length_recurse<'de, T, const N: usize>(
+    node: &Node<T, N>,
+    values: &[T; N],
+    margin: f64,
+) -> f64
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+{
+    match node {
+        Node::Ex(ex_node) => ex_node.path_length,
+        Node::In(in_node) => {
+            let norm = as_f64(
+                &in_node
+                    .n
+                    .iter()
+                    .fold(T::zero(), |sum, n_val| sum + *n_val * *n_val)
+                    .sqrt(),
+            );
+            let distance = as_f64(&projection(values, &in_node.n, &in_node.p)) / norm;
+            // weight of the right child, items on the hyperplane go left like in
+            // `determinate_direction`
+            let weight_right = if distance.is_nan() {
+                match saturated_direction(values, &in_node.n, &in_node.p) {
+                    Direction::Left => 0.0,
+                    Direction::Right => 1.0,
+                }
+            } else if margin > 0.0 && norm > 0.0 {
+                (0.5 + distance / (2.0 * margin)).clamp(0.0, 1.0)
+            } else if distance > 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+
+            let mut path_length = 1.0;
+            if weight_right > 0.0 {
+                path_length +=
+                    weight_right * soft_path_length_recurse(&in_node.right, values, margin);
+            }
+            if weight_right < 1.0 {
+                path_length +=
+                    (1.0 - weight_right) * soft_path_length_recurse(&in_node.left, values, margin);
+            }
+            path_length
+        }
+    }
+}
+
+fn as_f64<'de, T>(num: &T) -> f64
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+{
+    num.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Absolute tolerance of [`are_equal`], so values differing only by rounding noise around
+/// zero compare equal, where the relative tolerance vanishes.
+const ABSOLUTE_TOLERANCE: f64 = f64::EPSILON;
+
+/// Compare two values using the relative `tolerance` and [`ABSOLUTE_TOLERANCE`].
+fn are_equal<'de, T>(a: &T, b: &T, tolerance: f64) -> bool
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+{
+    let a = as_f64(a);
+    let b = as_f64(b);
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()) + ABSOLUTE_TOLERANCE
+}
+
+fn make_node<'de, T, const N: usize>(
+    data: &[[T; N]],
+    sample_indices: &mut [usize],
+    rng: &mut ThreadRng,
+    current_tree_depth: usize,
+    node_budget: &mut usize,
+    params: &TreeParameters<T, N>,
+) -> Node<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    // number of nodes which may still be added to the tree, including this one
+    *node_budget = node_budget.saturating_sub(1);
+    let num_samples = sample_indices.len();
+    if num_samples == 0 {
+        return make_leaf(num_samples, None, params);
+    }
+
+    // range of the samples in each dimension
+    let mut maxs = data[sample_indices[0]];
+    let mut mins = data[sample_indices[0]];
+    sample_indices.iter().skip(1).for_each(|sample_idx| {
+        data[*sample_idx].iter().enumerate().for_each(|(i, v)| {
+            maxs[i] = if *v > maxs[i] { *v } else { maxs[i] };
+            mins[i] = if *v < mins[i] { *v } else { mins[i] };
+        })
+    });
+
+    if current_tree_depth >= params.max_tree_depth || num_samples <= 1 || *node_budget < 2 {
+        make_leaf(num_samples, Some((mins, maxs)), params)
+    } else if mins
+        .iter()
+        .zip(maxs.iter())
+        .all(|(min_val, max_val)| are_equal(min_val, max_val, params.split_tolerance))
+    {
+        // all samples are duplicates of each other, so they can not be separated by any
+        // split. Collapse them into a leaf weighted by their multiplicity.
+        make_leaf(num_samples, Some((mins, maxs)), params)
+    } else {
+        let node_range: [f64; N];
+        let normal_scales = match params.normal_scaling {
+            NormalScaling::None => None,
+            NormalScaling::NodeRange => {
+                node_range = std::array::from_fn(|i| as_f64(&maxs[i]) - as_f64(&mins[i]));
+                Some(&node_range)
+            }
+            NormalScaling::SampleStdDev => params.sample_std_dev.as_ref(),
+        };
+        let Hyperplane {
+            intercept: p,
+            normal: n,
+        } = params.split_strategy.split(
+            &SplitContext {
+                data,
+                sample_indices,
+                min: &mins,
+                max: &maxs,
+                depth: current_tree_depth,
+                extension_level: params.extension_level,
+                split_tolerance: params.split_tolerance,
+                normal_scales,
+                normal_distribution: params.normal_distribution,
+                node_features: params.node_features,
+            },
+            rng,
+        );
+
+        // partition the sample indices in place, so no new buffers need to be allocated for
+        // the child nodes: samples going to the left are moved to the front of the slice.
+        let mut split_idx = 0;
+        for i in 0..sample_indices.len() {
+            let goes_left = if params.random_tie_breaking {
+                let direction_value = projection(&data[sample_indices[i]], &n, &p);
+                direction_value < T::zero() || (direction_value == T::zero() && rng.gen())
+            } else {
+                matches!(
+                    determinate_direction(&data[sample_indices[i]], &n, &p),
+                    Direction::Left
+                )
+            };
+            if goes_left {
+                sample_indices.swap(i, split_idx);
+                split_idx += 1;
+            }
+        }
+        let (samples_left, samples_right) = sample_indices.split_at_mut(split_idx);
+
+        // split the budget between the children in proportion to their samples, keeping at
+        // least one node for each. The budget left unused by the left subtree goes to the
+        // right one.
+        let remaining = *node_budget;
+        let share = (remaining as u128 * samples_left.len() as u128 / num_samples as u128) as usize;
+        let mut left_budget = share.clamp(1, remaining - 1);
+        let left_reserved = left_budget;
+        let left = Box::new(make_node(
+            data,
+            samples_left,
+            rng,
+            current_tree_depth + 1,
+            &mut left_budget,
+            params,
+        ));
+        *node_budget = remaining - (left_reserved - left_budget);
+        let right = Box::new(make_node(
+            data,
+            samples_right,
+            rng,
+            current_tree_depth + 1,
+            node_budget,
+            params,
+        ));
+
+        Node::In(InNode {
+            left,
+            right,
+            n,
+            p,
+            num_samples,
+        })
+    }
+}
+
+/// Standard deviation of the rows of `data` referenced by `sample_indices` in each dimension.
+fn std_dev<T: Float, const N: usize>(data: &[[T; N]], sample_indices: &[usize]) -> [f64; N] {
+    let count = sample_indices.len().max(1) as f64;
+    let value = |idx: usize, i: usize| data[idx][i].to_f64().unwrap_or(0.0);
+    std::array::from_fn(|i| {
+        let mean = sample_indices.iter().map(|idx| value(*idx, i)).sum::<f64>() / count;
+        let variance = sample_indices
+            .iter()
+            .map(|idx| (value(*idx, i) - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        variance.sqrt()
+    })
+}
+
+fn make_leaf<T, const N: usize>(
+    num_samples: usize,
+    bounds: Option<([T; N], [T; N])>,
+    params: &TreeParameters<T, N>,
+) -> Node<T, N> {
+    Node::Ex(ExNode::new(
+        num_samples,
+        if params.record_leaf_bounds {
+            bounds.map(|(min, max)| Box::new(LeafBounds { min, max }))
+        } else {
+            None
+        },
+    ))
+}
+
+/// Items of a block stored feature by feature, see [`Forest::score_batch_columnar`].
+struct ColumnarBlock<'a, T> {
+    /// Values of the items feature by feature, `rows.len()` values per feature.
+    columns: &'a mut [T],
+
+    /// Index of the item at each position of the block.
+    rows: &'a mut [usize],
+
+    /// Buffer for the projections of the items onto a hyperplane.
+    projections: &'a mut [T],
+}
+
+impl<T: Float> ColumnarBlock<'_, T> {
+    /// Pass the items at the positions `start..end` down the subtree of `node`, reordering
+    /// them so the items of each child follow each other.
+    fn add_path_lengths<const N: usize>(
+        &mut self,
+        node: &Node<T, N>,
+        (start, end): (usize, usize),
+        depth: usize,
+        path_length_c_inv: f64,
+        path_lengths: &mut [f64],
+    ) {
+        let in_node = match node {
+            Node::Ex(ex_node) => {
+                let path_length = (depth as f64 + ex_node.path_length) * path_length_c_inv;
+                for row in self.rows[start..end].iter() {
+                    path_lengths[*row] += path_length;
+                }
+                return;
+            }
+            Node::In(in_node) => in_node,
+        };
+        let stride = self.rows.len();
+        let projections = &mut self.projections[start..end];
+        projections.fill(T::zero());
+        for j in 0..N {
+            let (n, p) = (in_node.n[j], in_node.p[j]);
+            let column = &self.columns[j * stride + start..j * stride + end];
+            for (projection, value) in projections.iter_mut().zip(column) {
+                *projection = *projection + (*value - p) * n;
+            }
+        }
+
+        // move the items going left to the front
+        let mut mid = start;
+        for i in start..end {
+            let projection = self.projections[i];
+            let direction = if projection <= T::zero() {
+                Direction::Left
+            } else if projection > T::zero() {
+                Direction::Right
+            } else {
+                let values: [T; N] = std::array::from_fn(|j| self.columns[j * stride + i]);
+                saturated_direction(&values, &in_node.n, &in_node.p)
+            };
+            if let Direction::Left = direction {
+                if i != mid {
+                    self.rows.swap(i, mid);
+                    for j in 0..N {
+                        self.columns.swap(j * stride + i, j * stride + mid);
+                    }
+                }
+                mid += 1;
+            }
+        }
+
+        let depth = depth + 1;
+        self.add_path_lengths(
+            &in_node.left,
+            (start, mid),
+            depth,
+            path_length_c_inv,
+            path_lengths,
+        );
+        self.add_path_lengths(
+            &in_node.right,
+            (mid, end),
+            depth,
+            path_length_c_inv,
+            path_lengths,
+        );
+    }
+}
+
+/// Store the items feature by feature in `columns`, with `n_rows` values per feature.
+fn to_columns<T: Copy, const N: usize>(
+    items: impl Iterator<Item = [T; N]>,
+    n_rows: usize,
+    columns: &mut [T],
+) {
+    for (row, values) in items.enumerate() {
+        for (j, v) in values.iter().enumerate() {
+            columns[j * n_rows + row] = *v;
+        }
+    }
+}
+
+/// Average path length of unsuccessful search in a binary search tree given n points
+/// n: Number of data points for the BST.
+///
+/// Returns the average path length of unsuccessful search in a BST
+fn c_factor(n: usize) -> f64 {
+    2.0 * (math::ln(n as f64 - 1.0) + 0.5772156649) - (2.0 * (n as f64 - 1.0) / n as f64)
+}
+
+enum Direction {
+    Left,
+    Right,
+}
+
+/// Dot product of `sample - p` and the normal vector `n`.
+fn projection<T, const N: usize>(sample: &[T; N], n: &[T; N], p: &[T; N]) -> T
+where
+    T: Float,
+{
+    sample
+        .iter()
+        .zip(p.iter())
+        .map(|(sample_val, p_val)| *sample_val - *p_val)
+        .zip(n.iter())
+        .fold(T::zero(), |sum, (sp_val, n_val)| sum + sp_val * (*n_val))
+}
+
+fn determinate_direction<T, const N: usize>(sample: &[T; N], n: &[T; N], p: &[T; N]) -> Direction
+where
+    T: Float,
+{
+    let direction_value = projection(sample, n, p);
+
+    if direction_value <= T::zero() {
+        Direction::Left
+    } else if direction_value > T::zero() {
+        Direction::Right
+    } else {
+        saturated_direction(sample, n, p)
+    }
+}
+
+/// Direction of an item whose projection is NaN, because of infinite values, a NaN value or
+/// an overflowing dot product.
+///
+/// Dimensions with a zero coordinate of the normal vector are ignored, infinite values are
+/// clamped to the largest finite values and the differences to the intercept are divided by
+/// their largest magnitude, so the sign of the dot product can be computed without overflow.
+/// A NaN value in a dimension the hyperplane depends on sends the item to the right.
+#[cold]
+fn saturated_direction<T: Float>(sample: &[T], n: &[T], p: &[T]) -> Direction {
+    let two = T::one() + T::one();
+    let dimensions = || (0..n.len()).filter(|i| n[*i] != T::zero());
+    if dimensions().any(|i| sample[i].is_nan()) {
+        return Direction::Right;
+    }
+    let difference =
+        |i: usize| sample[i].max(T::min_value()).min(T::max_value()) / two - p[i] / two;
+    let scale = dimensions().fold(T::zero(), |scale, i| scale.max(difference(i).abs()));
+    if scale == T::zero() {
+        return Direction::Left;
+    }
+    let direction_value = dimensions().fold(T::zero(), |sum, i| sum + difference(i) / scale * n[i]);
+    if direction_value <= T::zero() {
+        Direction::Left
+    } else {
+        Direction::Right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use rand::RngCore;
+
+    use crate::datasets::uniform;
+    use crate::leaf::AveragePathLength;
+    use crate::pipeline::Step;
+    use crate::split::{Hyperplane, SplitContext, SplitStrategy};
+    use crate::visit::NodeKind;
+    use crate::{
+        are_equal, c_factor, determinate_direction, AnomalyScorer, Direction, Error, ExNode,
+        ExtensionLevel, FeatureMap, Forest, ForestOptions, Imputation, InNode, Node,
+        NonFiniteHandling, NormalDistribution, NormalScaling, RangeHandling, SampleSizePolicy,
+        ScoreScale, TrainScratch,
+    };
+
+    fn make_f64_forest() -> Forest<f64, 3> {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(-4., 4.);
+        let distribution2 = Uniform::new(10., 50.);
+
+        let values: Vec<_> = (0..6000)
+            .map(|_| {
+                [
+                    rng.sample(distribution),
+                    rng.sample(distribution),
+                    rng.sample(distribution2),
+                ]
+            })
+            .collect();
+
+        let options = ForestOptions {
+            n_trees: 150,
+            sample_size: 200,
+            max_tree_depth: None,
+            extension_level: ExtensionLevel::Level(1),
+            record_score_statistics: true,
+            ..ForestOptions::default()
+        };
+        Forest::from_slice(values.as_slice(), &options).unwrap()
+    }
+
+    fn assert_anomalies_forest_3d_f64(forest: &Forest<f64, 3>) {
+        // no anomaly
+        assert!(forest.score(&[1.0, 3.0, 25.0]) < 0.52);
+        assert!(forest.score(&[-1.0, 3.0, 25.0]) < 0.52);
+
+        // anomalies
+        assert!(forest.score(&[-12.0, 6.0, 25.0]) > 0.5);
+        assert!(forest.score(&[-1.0, 2.0, 60.0]) > 0.5);
+        assert!(forest.score(&[-1.0, 2.0, 0.0]) > 0.5);
+    }
+
+    #[test]
+    fn score_forest_3d_f64() {
+        let forest = make_f64_forest();
+        assert_anomalies_forest_3d_f64(&forest);
+    }
+
+    #[test]
+    pub fn infinte_loop() {
+        // computing EIF for the following vector takes longer than 5 minutes, indicating it might
+        // be entering an infinite loop; this is fixed by using the `are_equal` function, or
+        // upgrading the `rand` dependency to version `0.9` (not released yet)
+        let values = vec![
+            [1.1281417500958157],
+            [1.1281417712248367],
+            [1.1275383774967553],
+            [1.1276021217987937],
+            [1.1277130163266273],
+            [1.1276901742756698],
+            [1.1283566295942595],
+            [1.1283568915441473],
+            [1.1283568915441473],
+            [1.1283568915441473],
+            [1.1283561184593798],
+            [1.1283561184593798],
+            [1.1283561184593798],
+            [1.1277881975313708],
+            [1.1277882030559299],
+            [1.1277882030559299],
+            [1.1277882030559299],
+            [1.1277882030559299],
+            [1.1277882030559299],
+            [1.1277882030559299],
+            [1.127989727033879],
+            [1.127989727033879],
+            [1.1279898045604704],
+            [1.1279898045604704],
+            [1.1279898045604704],
+            [1.1279898045604704],
+            [1.1279896357496864],
+            [1.1279871685111489],
+            [1.127987257926914],
+            [1.1279874922711894],
+            [1.1279874922711894],
+            [1.1279904753612784],
+            [1.1279904182835565],
+            [1.1279904182835565],
+            [1.1279904182835565],
+            [1.1279904182835565],
             [1.1279904182835565],
             [1.1279904182835565],
             [1.1279904182835565],
@@ -642,13 +3569,1538 @@ mod tests {
             [1.129153270183836],
         ];
         let options = ForestOptions {
-            n_trees: 150,
-            sample_size: 200,
-            max_tree_depth: None,
-            extension_level: 0,
+            n_trees: 150,
+            sample_size: 200,
+            max_tree_depth: None,
+            extension_level: ExtensionLevel::Standard,
+            ..ForestOptions::default()
+        };
+
+        Forest::from_slice(values.as_slice(), &options).unwrap();
+    }
+
+    #[test]
+    fn top_k_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let data = [
+            [1.0, 3.0, 25.0],
+            [-12.0, 6.0, 25.0],
+            [-1.0, 3.0, 25.0],
+            [-1.0, 2.0, 60.0],
+        ];
+
+        let top = forest.top_k(&data, 2);
+        assert_eq!(top.len(), 2);
+        assert!(top[0].1 >= top[1].1);
+        let mut indices: Vec<_> = top.iter().map(|(idx, _)| *idx).collect();
+        indices.sort_unstable();
+        assert_eq!(indices, vec![1, 3]);
+
+        assert_eq!(forest.top_k(&data, 10).len(), data.len());
+        assert!(forest.top_k(&data, 0).is_empty());
+    }
+
+    #[test]
+    fn label_top_fraction_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let data = [
+            [1.0, 3.0, 25.0],
+            [-12.0, 6.0, 25.0],
+            [-1.0, 3.0, 25.0],
+            [-1.0, 2.0, 60.0],
+        ];
+        assert_eq!(
+            forest.label_top_fraction(&data, 0.5),
+            vec![false, true, false, true]
+        );
+        assert_eq!(forest.label_top_fraction(&data, 0.01).len(), 4);
+        assert_eq!(
+            forest
+                .label_top_fraction(&data, 0.01)
+                .iter()
+                .filter(|label| **label)
+                .count(),
+            1
+        );
+        assert_eq!(forest.label_top_fraction(&data, 0.0), vec![false; 4]);
+        assert_eq!(forest.label_top_fraction(&data, 2.0), vec![true; 4]);
+        assert!(forest.label_top_fraction(&[], 0.1).is_empty());
+
+        let data: Vec<_> = (0..100).map(|i| [i as f64 / 10.0, 3.0, 25.0]).collect();
+        for (fraction, expected) in [(0.07, 7), (0.0701, 8), (0.29, 29), (0.57, 57)] {
+            let labels = forest.label_top_fraction(&data, fraction);
+            assert_eq!(labels.iter().filter(|label| **label).count(), expected);
+        }
+    }
+
+    #[test]
+    fn score_approx_forest_3d_f64() {
+        let forest = make_f64_forest();
+        for values in [[1.0, 3.0, 25.0], [-12.0, 6.0, 25.0], [-1.0, 2.0, 60.0]] {
+            let exact = forest.score(&values);
+            assert!((forest.score_approx(&values, 0.02) - exact).abs() < 0.05);
+            assert!((forest.score_approx(&values, 0.0) - exact).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn score_exceeds_forest_3d_f64() {
+        let mut forest = make_f64_forest();
+        let items = [[1.0, 3.0, 25.0], [-12.0, 6.0, 25.0], [-1.0, 2.0, 60.0]];
+        for values in items.iter() {
+            let score = forest.score(values);
+            for threshold in [0.0, 0.3, 0.45, 0.5, 0.6, 0.9, 1.0, score] {
+                assert_eq!(forest.score_exceeds(values, threshold), score > threshold);
+            }
+        }
+
+        forest.set_score_scale(ScoreScale::Percentile);
+        for values in items.iter() {
+            let score = forest.score(values);
+            for threshold in [0.0, 50.0, 95.0, 99.0, 100.0, score] {
+                assert_eq!(forest.score_exceeds(values, threshold), score > threshold);
+            }
+        }
+    }
+
+    #[test]
+    fn score_with_trees_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let items = [[1.0, 3.0, 25.0], [-12.0, 6.0, 25.0], [-1.0, 2.0, 60.0]];
+        for values in items.iter() {
+            assert_eq!(
+                forest.score_with_trees(values, 150, 1),
+                forest.score(values)
+            );
+            assert_eq!(
+                forest.score_with_trees(values, 500, 1),
+                forest.score(values)
+            );
+            let score = forest.score_with_trees(values, 30, 7);
+            assert_eq!(forest.score_with_trees(values, 30, 7), score);
+            assert_eq!(
+                forest.score_with_trees(values, 0, 7),
+                forest.score_with_trees(values, 1, 7)
+            );
+            assert!((score - forest.score(values)).abs() < 0.1);
+        }
+        assert!(
+            forest.score_with_trees(&items[1], 30, 7) > forest.score_with_trees(&items[0], 30, 7)
+        );
+
+        let batch = forest.score_batch_with_trees(&items, 30, 7);
+        let expected: Vec<_> = items
+            .iter()
+            .map(|values| forest.score_with_trees(values, 30, 7))
+            .collect();
+        assert_eq!(batch, expected);
+        let seeds: Vec<_> = (0..10)
+            .map(|seed| forest.score_with_trees(&items[0], 10, seed))
+            .collect();
+        assert!(seeds.iter().any(|score| *score != seeds[0]));
+    }
+
+    #[test]
+    fn score_batch_dedup_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let data: Vec<_> = (0..100)
+            .map(|i| match i % 4 {
+                0 => [1.0, 3.0, 25.0],
+                1 => [-12.0, 6.0, 25.0],
+                2 => [-0.0, 2.0, 60.0],
+                _ => [0.0, 2.0, 60.0],
+            })
+            .collect();
+        let expected: Vec<_> = data.iter().map(|values| forest.score(values)).collect();
+        assert_eq!(forest.score_batch_dedup(&data), expected);
+        assert!(forest.score_batch_dedup(&[]).is_empty());
+    }
+
+    #[test]
+    fn score_batch_threads_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let data = [
+            [1.0, 3.0, 25.0],
+            [-12.0, 6.0, 25.0],
+            [-1.0, 3.0, 25.0],
+            [-1.0, 2.0, 60.0],
+            [-1.0, 2.0, 0.0],
+        ];
+        let expected: Vec<_> = data.iter().map(|values| forest.score(values)).collect();
+        for n_threads in [0, 1, 2, 8] {
+            assert_eq!(forest.score_batch_threads(&data, n_threads), expected);
+        }
+        assert!(forest.score_batch_threads(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn precomputed_leaf_path_lengths() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 256,
+            max_tree_depth: Some(4),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        for values in values[..100].iter() {
+            let expected = forest.score_with(values, &AveragePathLength);
+            assert!((forest.score_raw(values) - expected).abs() < 1e-12);
+            for tree in forest.trees.iter() {
+                let expected = tree.path_length(values, &AveragePathLength);
+                assert!((tree.adjusted_path_length(values) - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_scores_forest_2d() {
+        let values: Vec<[f64; 2]> = uniform(500);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            max_threads: 3,
+            ..ForestOptions::default()
+        };
+        let (forest, scores) = Forest::fit_scores(values.as_slice(), &options).unwrap();
+        assert_eq!(scores.len(), values.len());
+        for (values, score) in values.iter().zip(scores) {
+            assert_eq!(forest.score(values), score);
+        }
+
+        assert!(matches!(
+            Forest::fit_scores(&values[..10], &options),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+
+    #[test]
+    fn score_columns_forest_2d() {
+        let values: Vec<[f64; 2]> = uniform(500);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        let x: Vec<_> = values.iter().map(|v| v[0]).collect();
+        let y: Vec<_> = values.iter().map(|v| v[1]).collect();
+        let scores = forest.score_columns(&[&x, &y]).unwrap();
+        for (values, score) in values.iter().zip(scores) {
+            assert_eq!(forest.score(values), score);
+        }
+
+        assert!(matches!(
+            forest.score_columns(&[&x]),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            forest.score_columns(&[&x, &y[1..]]),
+            Err(Error::DimensionMismatch {
+                expected: 500,
+                actual: 499
+            })
+        ));
+    }
+
+    #[test]
+    fn score_many_into_buffer() {
+        let forest = make_f64_forest();
+        let data = [[1.0, 3.0, 25.0], [-12.0, 6.0, 25.0], [-1.0, 2.0, 60.0]];
+        let mut scores = [0.0; 3];
+        forest.score_many_into(&data, &mut scores).unwrap();
+        for (values, score) in data.iter().zip(scores) {
+            assert_eq!(score, forest.score(values));
+        }
+        assert!(matches!(
+            forest.score_many_into(&data, &mut [0.0; 2]),
+            Err(Error::DimensionMismatch {
+                expected: 3,
+                actual: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn score_iter_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let data = vec![[1.0, 3.0, 25.0], [-12.0, 6.0, 25.0], [-1.0, 2.0, 60.0]];
+        let n_anomalies = forest
+            .score_iter(data.iter())
+            .filter(|score| *score > 0.52)
+            .count();
+        assert_eq!(n_anomalies, 2);
+
+        let scores: Vec<_> = forest.score_iter(&data).collect();
+        let expected: Vec<_> = data.iter().map(|values| forest.score(values)).collect();
+        assert_eq!(scores, expected);
+    }
+
+    #[test]
+    fn score_batch_columnar_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(-20., 70.);
+        let data: Vec<_> = (0..150)
+            .map(|_| {
+                [
+                    rng.sample(distribution),
+                    rng.sample(distribution),
+                    rng.sample(distribution),
+                ]
+            })
+            .collect();
+        let scores = forest.score_batch_columnar(&data);
+        assert_eq!(scores.len(), 150);
+        for (values, score) in data.iter().zip(scores) {
+            assert!((score - forest.score(values)).abs() < 1e-12);
+        }
+        assert!(forest.score_batch_columnar(&[]).is_empty());
+
+        // trees with their own transformation
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            rotation_subset_size: Some(3),
+            preprocessing: vec![Step::StandardScaler],
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(data.as_slice(), &options).unwrap();
+        for (values, score) in data.iter().zip(forest.score_batch_columnar(&data)) {
+            assert!((score - forest.score(values)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn build_forest_with_threads() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+
+        for max_threads in [0, 3, 200] {
+            let options = ForestOptions {
+                n_trees: 50,
+                sample_size: 100,
+                max_threads,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+            assert_eq!(forest.trees.len(), 50);
+            assert!(forest.score(&[20.0, 20.0]) > forest.score(&[5.0, 5.0]));
+        }
+    }
+
+    #[test]
+    fn are_equal_with_tolerance() {
+        assert!(are_equal(&0.0, &0.0, 1e-4));
+        assert!(are_equal(&-2.0, &-2.0001, 1e-4));
+        assert!(!are_equal(&-2.0, &-1.0, 1e-4));
+        assert!(!are_equal(&0.0, &1e-9, 1e-4));
+        assert!(are_equal(&1e-17, &-1e-17, 1e-4));
+        assert!(are_equal(&0.0, &1e-17, 0.0));
+        assert!(are_equal(&1.12800, &1.12801, 1e-4));
+        assert!(!are_equal(&1.12800, &1.12801, 1e-6));
+    }
+
+    #[test]
+    fn validate_split_tolerance() {
+        let values: Vec<_> = (0..100).map(|i| [1.128 + i as f64 * 1e-6, 0.0]).collect();
+        let options = ForestOptions {
+            n_trees: 10,
+            sample_size: 64,
+            split_tolerance: 0.0,
+            ..ForestOptions::default()
+        };
+        assert!(Forest::from_slice(values.as_slice(), &options).is_ok());
+        for split_tolerance in [-1e-4, f64::NAN] {
+            let options = ForestOptions {
+                split_tolerance,
+                ..options.clone()
+            };
+            assert!(matches!(
+                Forest::from_slice(values.as_slice(), &options),
+                Err(Error::InvalidOption {
+                    name: "split_tolerance"
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn score_forest_negative_values() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(-9., -1.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 100,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert!(forest.score(&[-20.0, -20.0]) > forest.score(&[-5.0, -5.0]));
+    }
+
+    #[test]
+    fn duplicates_collapse_into_leaves() {
+        let mut values = vec![[1.0, 2.0]; 500];
+        values.extend((0..500).map(|i| [f64::from(i), 5.0]));
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 200,
+            max_tree_depth: Some(100),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        for tree in forest.trees.iter() {
+            // without collapsing the duplicates, they would be split until the max. depth
+            // is reached.
+            assert!(tree.root.depth() < 100);
+        }
+    }
+
+    #[test]
+    fn random_tie_breaking() {
+        /// Always splits at the first item in the first dimension.
+        struct FirstItem;
+
+        impl SplitStrategy<f64, 2> for FirstItem {
+            fn split(
+                &self,
+                context: &SplitContext<'_, f64, 2>,
+                _rng: &mut dyn RngCore,
+            ) -> Hyperplane<f64, 2> {
+                Hyperplane {
+                    intercept: context.data[0],
+                    normal: [1.0, 0.0],
+                }
+            }
+        }
+
+        // all items lie on the hyperplane
+        let values: Vec<_> = (0..100).map(|i| [1.0, f64::from(i)]).collect();
+        let left_samples = |random_tie_breaking| {
+            let options = ForestOptions {
+                n_trees: 1,
+                sample_size: 100,
+                random_tie_breaking,
+                ..ForestOptions::default()
+            };
+            let forest =
+                Forest::from_slice_with_split(values.as_slice(), &options, &FirstItem).unwrap();
+            match &forest.trees[0].root {
+                Node::In(in_node) => match in_node.left.as_ref() {
+                    Node::In(InNode { num_samples, .. }) | Node::Ex(ExNode { num_samples, .. }) => {
+                        *num_samples
+                    }
+                },
+                Node::Ex(_) => panic!("expected the root to be split"),
+            }
+        };
+
+        assert_eq!(left_samples(false), 100);
+        assert!((10..90).contains(&left_samples(true)));
+    }
+
+    #[test]
+    fn oob_scores_forest_2d() {
+        let mut values: Vec<[f64; 2]> = uniform(1000);
+        values.push([100.0, 100.0]);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert!(forest.oob_scores(&values).is_none());
+
+        let options = ForestOptions {
+            record_samples: true,
+            ..options
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let oob = forest.oob_scores(&values).unwrap();
+        assert_eq!(oob.len(), values.len());
+
+        // only the trees which have not been built from the outlier score it
+        let (path_length, count) = forest
+            .trees
+            .iter()
+            .filter(|tree| !tree.samples.as_ref().unwrap().contains(&1000))
+            .fold((0.0, 0), |(sum, count), tree| {
+                let path_length = tree.path_length(&values[1000], &AveragePathLength);
+                (sum + path_length * tree.path_length_c_inv, count + 1)
+            });
+        assert!((oob[1000] - 2.0_f64.powf(-path_length / f64::from(count))).abs() < 1e-12);
+        // the trees built from the outlier isolate it immediately
+        assert!(oob[1000] < forest.score(&values[1000]));
+
+        // trees scoring a row in-bag isolate it more slowly, so in-bag scores are lower
+        let in_bag_mean = values.iter().map(|v| forest.score(v)).sum::<f64>() / 1001.0;
+        let oob_mean = oob.iter().sum::<f64>() / 1001.0;
+        assert!(oob_mean > in_bag_mean);
+    }
+
+    #[test]
+    fn stratified_samples() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        // a small but legitimate group far off the other rows
+        let mut values: Vec<[f64; 2]> = uniform(2000);
+        values.extend((0..10).map(|_| [rng.sample(distribution) + 50.0, 5.0]));
+        let mut groups = vec![0; 2000];
+        groups.resize(2010, 7);
+
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 64,
+            record_samples: true,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice_stratified(&values, &groups, &options).unwrap();
+        for tree in forest.trees.iter() {
+            let samples = tree.samples.as_ref().unwrap();
+            assert_eq!(samples.len(), 64);
+            assert_eq!(samples.iter().filter(|row| **row >= 2000).count(), 1);
+        }
+        // without stratification the group is absent from most samples
+        let plain = Forest::from_slice(&values, &options).unwrap();
+        let n_absent = plain
+            .trees
+            .iter()
+            .filter(|tree| tree.samples.as_ref().unwrap().iter().all(|row| *row < 2000))
+            .count();
+        assert!(n_absent > 25);
+
+        assert!(matches!(
+            Forest::from_slice_stratified(&values, &groups[1..], &options),
+            Err(Error::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn auto_options() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions::auto(&values);
+        assert_eq!(options.n_trees, 100);
+        assert_eq!(options.sample_size, 256);
+        assert_eq!(options.extension_level, ExtensionLevel::Full);
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        assert!(forest.score(&[30.0, 30.0]) > forest.score(&[5.0, 5.0]));
+
+        assert_eq!(ForestOptions::auto(&values[..50]).sample_size, 50);
+        assert_eq!(ForestOptions::auto(&[[0.0; 150]]).n_trees, 150);
+    }
+
+    #[test]
+    fn imputed_scores() {
+        let values: Vec<_> = (0..1000)
+            .map(|i| [(i % 100) as f64, if i % 10 == 0 { 50.0 } else { 1.0 }])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            imputation: Some(Imputation::Median),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        assert_eq!(forest.imputation_values(), Some([49.5, 1.0].as_slice()));
+        assert_eq!(
+            forest.score_imputed(&[f64::NAN, 1.0]),
+            forest.score(&[49.5, 1.0])
+        );
+        assert_eq!(forest.score_imputed(&[3.0, 1.0]), forest.score(&[3.0, 1.0]));
+
+        let options = ForestOptions {
+            imputation: Some(Imputation::Mean),
+            ..options
+        };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        assert_eq!(forest.imputation_values(), Some([49.5, 5.9].as_slice()));
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&forest).unwrap();
+            let forest2: Forest<f64, 2> = serde_json::from_str(&json).unwrap();
+            assert_eq!(forest2.imputation_values(), forest.imputation_values());
+
+            let mut json = serde_json::to_value(&forest).unwrap();
+            json["imputation_values"] = serde_json::json!([1.0]);
+            assert!(serde_json::from_value::<Forest<f64, 2>>(json).is_err());
+        }
+
+        let options = ForestOptions {
+            imputation: None,
+            ..options
+        };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        assert!(forest.imputation_values().is_none());
+        assert!(forest.score_imputed(&[f64::NAN, 1.0]).is_finite());
+    }
+
+    #[test]
+    fn from_iter_forest_2d() {
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let rows = (0..1000).map(|i| [(i % 10) as f64, (i / 100) as f64]);
+        let forest = Forest::from_iter(rows.clone(), &options).unwrap();
+        assert!(forest.score(&[30.0, 30.0]) > forest.score(&[5.0, 5.0]));
+        let range = forest.training_range().unwrap();
+        assert_eq!((range.min, range.max), ([0.0, 0.0], [9.0, 9.0]));
+
+        // the iterator does not know its length after filtering
+        let forest =
+            Forest::from_iter_sampled(rows.clone().filter(|values| values[0] < 5.0), 200, &options)
+                .unwrap();
+        assert!(forest.score(&[30.0, 30.0]) > forest.score(&[2.0, 5.0]));
+        assert!(forest.training_range().unwrap().max[0] <= 4.0);
+
+        assert!(matches!(
+            Forest::from_iter_sampled(rows, 10, &options),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+
+    #[test]
+    fn from_columns_forest_2d() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let x: Vec<f64> = (0..500).map(|_| rng.sample(distribution)).collect();
+        let y: Vec<f64> = (0..500).map(|_| rng.sample(distribution)).collect();
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::<f64, 2>::from_columns(&[&x, &y], &options).unwrap();
+        assert!(forest.score(&[30.0, 30.0]) > forest.score(&[5.0, 5.0]));
+        let range = forest.training_range().unwrap();
+        assert_eq!(
+            range.min[1],
+            y.iter().copied().fold(f64::INFINITY, f64::min)
+        );
+
+        // the samples are read from the columns and preprocessed row by row
+        let rows: Vec<_> = x.iter().zip(y.iter()).map(|(x, y)| [*x, *y]).collect();
+        let options = ForestOptions {
+            preprocessing: vec![Step::StandardScaler],
+            imputation: Some(Imputation::Mean),
+            record_samples: true,
+            record_score_statistics: true,
+            ..options
+        };
+        let forest = Forest::<f64, 2>::from_columns(&[&x, &y], &options).unwrap();
+        let from_rows = Forest::from_slice(&rows, &options).unwrap();
+        assert_eq!(forest.pipeline(), from_rows.pipeline());
+        assert_eq!(forest.imputation_values(), from_rows.imputation_values());
+        for tree in forest.trees.iter() {
+            let samples = tree.samples.as_ref().unwrap();
+            assert_eq!(samples.len(), 64);
+            assert!(samples.iter().any(|row| *row >= 64));
+            assert!(samples.iter().all(|row| *row < 500));
+        }
+        assert!(forest.score(&[30.0, 30.0]) > forest.score(&[5.0, 5.0]));
+
+        assert!(matches!(
+            Forest::<f64, 2>::from_columns(&[&x], &options),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            Forest::<f64, 2>::from_columns(&[&x, &y[1..]], &options),
+            Err(Error::DimensionMismatch {
+                expected: 500,
+                actual: 499
+            })
+        ));
+    }
+
+    #[test]
+    fn try_from_rows_forest_2d() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let mut rows: Vec<_> = (0..500)
+            .map(|_| vec![rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::<f64, 2>::try_from_rows(&rows, &options).unwrap();
+        assert!(forest.score(&[30.0, 30.0]) > forest.score(&[5.0, 5.0]));
+
+        rows[42].push(1.0);
+        assert!(matches!(
+            Forest::<f64, 2>::try_from_rows(&rows, &options),
+            Err(Error::RaggedRow {
+                row: 42,
+                expected: 2,
+                actual: 3
+            })
+        ));
+        assert!(matches!(
+            Forest::<f64, 2>::try_from_rows(&[], &options),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+
+    #[test]
+    fn reused_sample_buffers() {
+        let rng = &mut rand::thread_rng();
+        let mut scratch = TrainScratch::<f64, 2>::default();
+        // rejection sampling and the partial shuffle, each for several trees
+        for (n_rows, sample_size) in [(1000, 64), (1000, 900), (100, 64)] {
+            for _ in 0..3 {
+                scratch.sample_rows(n_rows, sample_size, false, rng);
+                let mut sample = scratch.sample.clone();
+                sample.sort_unstable();
+                sample.dedup();
+                assert_eq!(sample.len(), sample_size);
+                assert!(sample.iter().all(|row| *row < n_rows));
+            }
+        }
+        // all rows in order without drawing
+        scratch.sample_rows(1000, 1000, false, rng);
+        assert_eq!(scratch.sample, (0..1000).collect::<Vec<_>>());
+
+        scratch.sample_rows(10, 64, true, rng);
+        assert_eq!(scratch.sample.len(), 64);
+        assert!(scratch.sample.iter().all(|row| *row < 10));
+    }
+
+    #[test]
+    fn scaled_normals() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 1.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution) * 1e6])
+            .collect();
+        // anomalous in the dimension with the small range only
+        let (normal, anomaly) = ([0.5, 5e5], [3.0, 5e5]);
+
+        for normal_scaling in [NormalScaling::NodeRange, NormalScaling::SampleStdDev] {
+            let options = ForestOptions {
+                n_trees: 100,
+                sample_size: 256,
+                extension_level: ExtensionLevel::Full,
+                normal_scaling,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+            assert!(forest.score(&anomaly) > forest.score(&normal) + 0.1);
+        }
+    }
+
+    #[test]
+    fn sparse_normals() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 1.);
+        let values: Vec<[f64; 8]> = (0..1000)
+            .map(|_| std::array::from_fn(|_| rng.sample(distribution)))
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 256,
+            extension_level: ExtensionLevel::Full,
+            normal_distribution: NormalDistribution::achlioptas(),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        for node in forest.nodes() {
+            if let NodeKind::Internal { normal, .. } = node.kind {
+                assert!(normal.iter().all(|n| [-1.0, 0.0, 1.0].contains(n)));
+                assert!(normal.iter().any(|n| *n != 0.0));
+            }
+        }
+        assert!(forest.score(&[3.0; 8]) > forest.score(&[0.5; 8]) + 0.1);
+
+        for density in [0.0, -0.5, 1.5, f64::NAN] {
+            let options = ForestOptions {
+                normal_distribution: NormalDistribution::Sparse { density },
+                ..options.clone()
+            };
+            assert!(matches!(
+                Forest::from_slice(values.as_slice(), &options),
+                Err(Error::InvalidOption {
+                    name: "normal_distribution"
+                })
+            ));
+        }
+
+        // almost all draws are zero, so the normals fall back to random axes
+        let options = ForestOptions {
+            normal_distribution: NormalDistribution::Sparse { density: 1e-12 },
+            ..options
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        for node in forest.nodes() {
+            if let NodeKind::Internal { normal, .. } = node.kind {
+                assert_eq!(normal.iter().filter(|n| n.abs() == 1.0).count(), 1);
+                assert_eq!(normal.iter().filter(|n| **n == 0.0).count(), 7);
+            }
+        }
+    }
+
+    #[test]
+    fn node_feature_subsets() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 1.);
+        let values: Vec<[f64; 200]> = (0..500)
+            .map(|_| std::array::from_fn(|_| rng.sample(distribution)))
+            .collect();
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 128,
+            node_features: Some(3),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let mut covered = [false; 200];
+        for node in forest.nodes() {
+            if let NodeKind::Internal { normal, .. } = node.kind {
+                let features: Vec<_> = (0..200).filter(|i| normal[*i] != 0.0).collect();
+                assert!((1..=3).contains(&features.len()));
+                features.into_iter().for_each(|i| covered[i] = true);
+            }
+        }
+        assert!(covered.iter().all(|covered| *covered));
+
+        // with few features per node a single anomalous feature is often not split on
+        // before the item is isolated, so the anomaly spans several features
+        let mut anomaly = [0.5; 200];
+        for value in anomaly.iter_mut().step_by(10) {
+            *value = 5.0;
+        }
+        assert!(forest.score(&anomaly) > forest.score(&[0.5; 200]));
+    }
+
+    #[test]
+    fn feature_map_ring() {
+        let rng = &mut rand::thread_rng();
+        let angle = Uniform::new(0.0, 2.0 * std::f64::consts::PI);
+        let radius = Uniform::new(0.9, 1.1);
+        let values: Vec<_> = (0..2000)
+            .map(|_| {
+                let (a, r) = (rng.sample(angle), rng.sample(radius));
+                [r * a.cos(), r * a.sin()]
+            })
+            .collect();
+
+        for feature_map in [FeatureMap::Fourier { bandwidth: 1.0 }, FeatureMap::Relu] {
+            let options = ForestOptions {
+                n_trees: 100,
+                sample_size: 256,
+                extension_level: ExtensionLevel::Full,
+                feature_map: Some(feature_map),
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+            let mean = values.iter().map(|v| forest.score(v)).sum::<f64>() / 2000.0;
+            assert!(forest.score(&[3.0, 3.0]) > mean + 0.05);
+            if let FeatureMap::Fourier { .. } = feature_map {
+                // the center of the ring can not be isolated by a single hyperplane
+                assert!(forest.score(&[0.0, 0.0]) > mean + 0.02);
+            }
+            assert_eq!(
+                Forest::from_compact(forest.to_compact().unwrap()).unwrap(),
+                forest
+            );
+        }
+
+        for bandwidth in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let options = ForestOptions {
+                feature_map: Some(FeatureMap::Fourier { bandwidth }),
+                ..ForestOptions::default()
+            };
+            assert!(matches!(
+                Forest::from_slice(values.as_slice(), &options),
+                Err(Error::InvalidOption {
+                    name: "feature_map"
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn tree_stats_forest_2d() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 10,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let stats = forest.tree_stats();
+        assert_eq!(stats.len(), 10);
+        for (stats, n_leaves) in stats.iter().zip(forest.leaf_counts()) {
+            assert_eq!(stats.n_nodes, 2 * n_leaves as usize - 1);
+            assert!((1..=6).contains(&stats.max_depth));
+        }
+        let mut rebuilt = forest.clone();
+        rebuilt.trees[0].stats.build_time += std::time::Duration::from_secs(1);
+        assert!(rebuilt == forest);
+
+        // the same split is chosen over and over for samples on the hyperplane
+        struct Constant;
+        impl SplitStrategy<f64, 2> for Constant {
+            fn split(
+                &self,
+                _context: &SplitContext<'_, f64, 2>,
+                _rng: &mut dyn RngCore,
+            ) -> Hyperplane<f64, 2> {
+                Hyperplane {
+                    intercept: [100.0, 0.0],
+                    normal: [1.0, 0.0],
+                }
+            }
+        }
+        let forest = Forest::from_slice_with_split(values.as_slice(), &options, &Constant).unwrap();
+        assert!(forest
+            .tree_stats()
+            .iter()
+            .all(|stats| stats.n_empty_splits == 6 && stats.max_depth == 6));
+    }
+
+    #[test]
+    fn resolve_extension_level() {
+        assert_eq!(ExtensionLevel::Standard.resolve(3).unwrap(), 0);
+        assert_eq!(ExtensionLevel::Level(2).resolve(3).unwrap(), 2);
+        assert_eq!(ExtensionLevel::Full.resolve(3).unwrap(), 2);
+        assert!(matches!(
+            ExtensionLevel::Level(3).resolve(3),
+            Err(Error::ExtensionLevelExceedsDimensions)
+        ));
+
+        let values = vec![[1.0, 2.0, 3.0]; 100];
+        let options = ForestOptions {
+            extension_level: ExtensionLevel::Full,
+            ..ForestOptions::default()
+        };
+        assert!(Forest::from_slice(values.as_slice(), &options).is_ok());
+    }
+
+    #[test]
+    fn sample_size_policies() {
+        let values: Vec<_> = (0..50).map(|i| [f64::from(i), f64::from(i % 7)]).collect();
+        let options = ForestOptions {
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        assert!(matches!(
+            Forest::from_slice(values.as_slice(), &options),
+            Err(Error::InsufficientTrainingData)
+        ));
+
+        let forest = Forest::from_slice(
+            values.as_slice(),
+            &ForestOptions {
+                sample_size_policy: SampleSizePolicy::Clamp,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(forest.sample_size(), 50);
+        assert_eq!(forest.sample_size_policy(), SampleSizePolicy::Clamp);
+
+        let forest = Forest::from_slice(
+            values.as_slice(),
+            &ForestOptions {
+                sample_size_policy: SampleSizePolicy::Bootstrap,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(forest.sample_size(), 256);
+        assert!(forest.score(&[100.0, 100.0]) > forest.score(&[25.0, 3.0]));
+    }
+
+    #[test]
+    fn variable_sample_sizes() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 32,
+            max_sample_size: Some(512),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let sample_sizes: Vec<_> = forest.trees.iter().map(|tree| tree.sample_size()).collect();
+        assert!(sample_sizes.iter().all(|size| (32..=512).contains(size)));
+        assert!(sample_sizes.iter().any(|size| *size != sample_sizes[0]));
+        assert!(forest.score(&[20.0, 20.0]) > forest.score(&[5.0, 5.0]));
+
+        // each tree is normalized by the constant of its own sample size
+        let normalized: f64 = forest
+            .trees
+            .iter()
+            .map(|tree| {
+                tree.path_length(&[20.0, 20.0], &AveragePathLength) / c_factor(tree.sample_size())
+            })
+            .sum();
+        let expected = 2.0_f64.powf(-normalized / forest.trees.len() as f64);
+        assert!((forest.score(&[20.0, 20.0]) - expected).abs() < 1e-12);
+
+        // the largest sample size must be available
+        let options = ForestOptions {
+            max_sample_size: Some(2000),
+            ..options
+        };
+        assert!(matches!(
+            Forest::from_slice(values.as_slice(), &options),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+
+    #[test]
+    fn max_total_nodes() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 256,
+            max_total_nodes: Some(50 * 16),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        for tree in forest.trees.iter() {
+            // every internal node has two children
+            assert!(2 * tree.n_leaves - 1 <= 16);
+        }
+        // the left subtree of the root does not use up the budget of the right one
+        let split_roots = forest
+            .trees
+            .iter()
+            .filter(|tree| match &tree.root {
+                Node::In(in_node) => matches!(*in_node.right, Node::In(_)),
+                Node::Ex(_) => false,
+            })
+            .count();
+        assert!(split_roots > 25);
+        assert!(forest.score(&[20.0, 20.0]) > forest.score(&[5.0, 5.0]));
+
+        // each tree keeps at least its root
+        let options = ForestOptions {
+            max_total_nodes: Some(10),
+            ..options
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert!(forest.trees.iter().all(|tree| tree.n_leaves == 1));
+    }
+
+    #[test]
+    fn memory_usage_forest_2d() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 10,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let n_nodes: u32 = forest.leaf_counts().iter().map(|n| 2 * n - 1).sum();
+        let node_size = std::mem::size_of::<Node<f64, 2>>();
+        // all nodes but the roots are allocated separately
+        assert!(forest.memory_usage() > (n_nodes as usize - 10) * node_size);
+
+        let options = ForestOptions {
+            record_leaf_bounds: true,
+            preprocessing: vec![Step::StandardScaler],
+            ..options
+        };
+        let with_bounds = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let n_leaves: u32 = with_bounds.leaf_counts().iter().sum();
+        assert!(with_bounds.memory_usage() > n_leaves as usize * 4 * 8);
+    }
+
+    #[test]
+    fn score_scales() {
+        let values: Vec<[f64; 2]> = uniform(2000);
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            score_scale: ScoreScale::Percentile,
+            ..ForestOptions::default()
+        };
+        let mut forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let (normal, anomaly) = ([5.0, 5.0], [30.0, 30.0]);
+
+        assert_eq!(forest.score_scale(), ScoreScale::Percentile);
+        assert!(forest.score(&normal) < 50.0);
+        assert!(forest.score(&anomaly) > 99.0);
+
+        forest.set_score_scale(ScoreScale::ZScore);
+        assert!(forest.score(&normal) < 0.0);
+        assert!(forest.score(&anomaly) > 3.0);
+
+        forest.set_score_scale(ScoreScale::Linear0to100);
+        assert!((0.0..50.0).contains(&forest.score(&normal)));
+        assert!(forest.score(&anomaly) > forest.score(&normal) + 20.0);
+        assert!(forest.score(&anomaly) < 100.0);
+
+        forest.set_score_scale(ScoreScale::Raw);
+        assert_eq!(forest.score(&anomaly), forest.score_raw(&anomaly));
+    }
+
+    #[test]
+    fn similarity_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let a = [1.0, 1.0, 30.0];
+        assert_eq!(forest.similarity(&a, &a), 1.0);
+
+        let close = forest.similarity(&a, &[1.1, 1.0, 30.5]);
+        let far = forest.similarity(&a, &[-3.0, 3.0, 12.0]);
+        assert!(close > far);
+        assert!(far < 0.1);
+    }
+
+    #[test]
+    fn mass_dissimilarity_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let a = [1.0, 1.0, 30.0];
+        let close = forest.mass_dissimilarity(&a, &[1.1, 1.0, 30.5]);
+        let far = forest.mass_dissimilarity(&a, &[-3.0, 3.0, 12.0]);
+        assert!(forest.mass_dissimilarity(&a, &a) <= close);
+        assert!(close < far);
+        assert!(far <= 1.0);
+    }
+
+    #[test]
+    fn leaf_indices_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let a = [1.0, 1.0, 30.0];
+        let b = [-3.0, 3.0, 12.0];
+        let leaves_a = forest.leaf_indices(&a);
+        assert_eq!(leaves_a.len(), 150);
+        assert_eq!(leaves_a, forest.leaf_indices(&a));
+
+        let leaf_counts = forest.leaf_counts();
+        assert!(leaves_a
+            .iter()
+            .zip(leaf_counts.iter())
+            .all(|(leaf, count)| leaf < count));
+
+        let shared = leaves_a
+            .iter()
+            .zip(forest.leaf_indices(&b))
+            .filter(|(leaf_a, leaf_b)| **leaf_a == *leaf_b)
+            .count();
+        assert_eq!(shared as f64 / 150.0, forest.similarity(&a, &b));
+    }
+
+    #[test]
+    fn leaf_bounds_forest_2d() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 100,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert!(forest.leaf_bounds(&[5.0, 5.0]).iter().all(Option::is_none));
+
+        let forest = Forest::from_slice(
+            values.as_slice(),
+            &ForestOptions {
+                record_leaf_bounds: true,
+                ..options
+            },
+        )
+        .unwrap();
+        assert!(forest.leaf_bounds(&[5.0, 5.0]).iter().any(Option::is_some));
+
+        // the second dimension exceeds the range of all training samples
+        let item = [5.0, 30.0];
+        let n_bounded = forest.leaf_bounds(&item).iter().flatten().count();
+        assert!(n_bounded > 0);
+        let violations: Vec<_> = forest
+            .bounds_violations(&item)
+            .into_iter()
+            .filter(|violation| violation.dimension == 1)
+            .collect();
+        assert_eq!(violations.len(), n_bounded);
+        assert!(violations.iter().all(|violation| violation.max < 9.0));
+    }
+
+    #[test]
+    fn score_slice_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let values = vec![-12.0, 6.0, 25.0];
+        assert_eq!(
+            forest.score_slice(&values).unwrap(),
+            forest.score(&[-12.0, 6.0, 25.0])
+        );
+        assert!(matches!(
+            forest.score_slice(&values[..2]),
+            Err(Error::DimensionMismatch {
+                expected: 3,
+                actual: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn anomaly_scorer_trait_objects() {
+        let forest_3d = make_f64_forest();
+        let forest_1d = Forest::<f32, 1>::from_slice(
+            &[[1.0], [2.0], [3.0], [4.0]],
+            &ForestOptions {
+                sample_size: 4,
+                ..ForestOptions::default()
+            },
+        )
+        .unwrap();
+        let scorers: Vec<Box<dyn AnomalyScorer>> = vec![Box::new(forest_3d), Box::new(forest_1d)];
+
+        assert_eq!(scorers[0].dims(), 3);
+        assert!(scorers[0].score_slice(&[-12.0, 6.0, 25.0]).unwrap() > 0.5);
+        assert_eq!(scorers[1].dims(), 1);
+        assert!(scorers[1].score_slice(&[2.5]).is_ok());
+        assert!(scorers[1].score_slice(&[2.5, 1.0]).is_err());
+    }
+
+    #[test]
+    fn score_soft_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let normal = [1.0, 3.0, 25.0];
+        let anomaly = [-12.0, 6.0, 25.0];
+        assert!((forest.score_soft(&normal, 0.0) - forest.score(&normal)).abs() < 1e-12);
+        assert!(forest.score_soft(&anomaly, 0.5) > 0.5);
+
+        // moving an item by a tiny step changes the soft score only slightly
+        let step = 1e-3;
+        let moved = [1.0 + step, 3.0, 25.0];
+        let soft_difference =
+            (forest.score_soft(&normal, 1.0) - forest.score_soft(&moved, 1.0)).abs();
+        assert!(soft_difference < 0.01);
+    }
+
+    #[test]
+    fn scaled_score_variants_forest_3d_f64() {
+        let mut forest = make_f64_forest();
+        forest.set_score_scale(ScoreScale::Percentile);
+        for values in [[1.0, 3.0, 25.0], [-12.0, 6.0, 25.0], [-1.0, 2.0, 60.0]] {
+            let score = forest.score(&values);
+            assert!((forest.score_soft(&values, 0.0) - score).abs() < 1e-9);
+            assert!((forest.score_approx(&values, 0.0) - score).abs() < 1e-9);
+            assert!((forest.score_with(&values, &AveragePathLength) - score).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn score_checked_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let range = forest.training_range().unwrap();
+        assert!(range.min[0] >= -4.0 && range.max[0] < 4.0);
+
+        let normal = [1.0, 3.0, 25.0];
+        let checked = forest.score_checked(&normal, RangeHandling::Flag, 0.1);
+        assert!(checked.out_of_range.is_empty());
+        assert_eq!(checked.score, forest.score(&normal));
+
+        // slightly outside of the range, but within the margin
+        let checked = forest.score_checked(&[4.2, 3.0, 25.0], RangeHandling::Flag, 0.1);
+        assert!(checked.out_of_range.is_empty());
+
+        let far_off = [-100.0, 3.0, 500.0];
+        let checked = forest.score_checked(&far_off, RangeHandling::Flag, 0.1);
+        assert_eq!(checked.out_of_range, vec![0, 2]);
+        assert_eq!(checked.score, forest.score(&far_off));
+
+        let clamped = forest.score_checked(&far_off, RangeHandling::Clamp, 0.1);
+        assert!(clamped.score < checked.score);
+        let boosted = forest.score_checked(&far_off, RangeHandling::Boost, 0.1);
+        assert!(boosted.score > checked.score);
+        assert!(boosted.score <= 1.0);
+    }
+
+    #[test]
+    fn forest_from_trees() {
+        let forest = make_f64_forest();
+        let item = [-12.0, 6.0, 25.0];
+        let contributions: Vec<_> = forest
+            .trees()
+            .iter()
+            .map(|tree| tree.score_contribution(&item))
+            .collect();
+        let mean = contributions.iter().sum::<f64>() / contributions.len() as f64;
+        assert!((forest.score_raw(&item) - (-mean).exp2()).abs() < 1e-12);
+        let tree = &forest.trees()[0];
+        let path_length = tree.path_length(&item, &AveragePathLength);
+        assert!((path_length / c_factor(tree.sample_size()) - contributions[0]).abs() < 1e-12);
+
+        // a forest of the trees of two forests
+        let other = make_f64_forest();
+        let mut trees = forest.clone().into_trees();
+        trees.extend(other.trees()[..50].iter().cloned());
+        let combined = Forest::from_trees(trees, 200).unwrap();
+        assert_eq!(combined.trees().len(), 200);
+        assert_eq!(combined.sample_size(), 200);
+        let mean = (contributions.iter().sum::<f64>()
+            + other.trees()[..50]
+                .iter()
+                .map(|tree| tree.score_contribution(&item))
+                .sum::<f64>())
+            / 200.0;
+        assert!((combined.score(&item) - (-mean).exp2()).abs() < 1e-12);
+
+        let same = Forest::from_trees(forest.clone().into_trees(), forest.sample_size()).unwrap();
+        assert_eq!(same.score(&item), forest.score_raw(&item));
+
+        assert!(matches!(
+            Forest::<f64, 3>::from_trees(vec![], 200),
+            Err(Error::InsufficientTrainingData)
+        ));
+        assert!(matches!(
+            Forest::from_trees(forest.into_trees(), 0),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+
+    #[test]
+    fn score_extreme_values() {
+        // infinite values in a dimension the hyperplane does not depend on are ignored
+        let direction = determinate_direction(&[f64::INFINITY, 5.0], &[0.0, -1.0], &[0.0, 0.0]);
+        assert!(matches!(direction, Direction::Left));
+        // overflowing dot products
+        let direction = determinate_direction(&[1e308, -1e308], &[2.0, 1.0], &[-1e308, 1e308]);
+        assert!(matches!(direction, Direction::Right));
+        let direction = determinate_direction(
+            &[f64::INFINITY, f64::NEG_INFINITY],
+            &[1.0, 1.5],
+            &[0.0, 0.0],
+        );
+        assert!(matches!(direction, Direction::Left));
+        let direction = determinate_direction(&[f64::NAN, 5.0], &[1.0, 1.0], &[0.0, 0.0]);
+        assert!(matches!(direction, Direction::Right));
+
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(-1e300, 1e300);
+        let values: Vec<_> = (0..500)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let extreme = [
+            [f64::INFINITY, 0.0],
+            [f64::NEG_INFINITY, f64::INFINITY],
+            [f64::MAX, f64::MIN],
+            [1e308, 1e308],
+        ];
+        for extension_level in [ExtensionLevel::Standard, ExtensionLevel::Full] {
+            let options = ForestOptions {
+                n_trees: 50,
+                sample_size: 128,
+                extension_level,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(&values, &options).unwrap();
+            assert_eq!(forest.non_finite_handling(), NonFiniteHandling::Saturate);
+            for item in extreme.iter() {
+                let score = forest.score(item);
+                assert!(score.is_finite() && score > forest.score(&values[0]) / 2.0);
+                assert_eq!(forest.freeze().score(item), score);
+            }
+
+            let forest = Forest::from_slice(
+                &values,
+                &ForestOptions {
+                    non_finite_handling: NonFiniteHandling::MaxScore,
+                    ..options
+                },
+            )
+            .unwrap();
+            assert_eq!(forest.score(&[f64::NAN, 0.0]), 1.0);
+            assert_eq!(forest.score(&extreme[0]), 1.0);
+            assert_eq!(forest.freeze().score(&extreme[1]), 1.0);
+            assert!(forest.score(&extreme[2]) < 1.0);
+        }
+    }
+
+    #[test]
+    fn extreme_values_scoring_paths() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(-1e300, 1e300);
+        let values: Vec<_> = (0..500)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let extreme = [
+            [f64::NEG_INFINITY, f64::INFINITY],
+            [f64::INFINITY, 0.0],
+            [f64::MAX, f64::MIN],
+            [-f64::MAX, f64::MAX],
+        ];
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            extension_level: ExtensionLevel::Full,
+            ..ForestOptions::default()
         };
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        let scores: Vec<_> = extreme.iter().map(|item| forest.score(item)).collect();
+        assert_eq!(forest.score_batch_columnar(&extreme), scores);
+        for (item, score) in extreme.iter().zip(scores) {
+            assert!(score.is_finite());
+            assert_eq!(forest.score_soft(item, 0.0), score);
+            assert!((forest.score_with(item, &AveragePathLength) - score).abs() < 1e-12);
+            assert!((forest.score_approx(item, 0.0) - score).abs() < 1e-12);
+            assert_eq!(forest.score_with_trees(item, 50, 0), score);
+            assert_eq!(forest.freeze().score(item), score);
+        }
 
-        Forest::from_slice(values.as_slice(), &options).unwrap();
+        for (handling, expected) in [
+            (NonFiniteHandling::MaxScore, 1.0),
+            (NonFiniteHandling::Reject, f64::NAN),
+        ] {
+            let forest = Forest::from_slice(
+                &values,
+                &ForestOptions {
+                    non_finite_handling: handling,
+                    record_samples: true,
+                    ..options.clone()
+                },
+            )
+            .unwrap();
+            let same = |score: f64| score.to_bits() == expected.to_bits();
+            for item in extreme[..2].iter() {
+                assert!(same(forest.score(item)));
+                assert!(same(forest.score_soft(item, 0.0)));
+                assert!(same(forest.score_with(item, &AveragePathLength)));
+                assert!(same(forest.score_approx(item, 0.0)));
+                assert!(same(forest.score_with_trees(item, 10, 0)));
+            }
+            let batch = forest.score_batch_columnar(&extreme);
+            assert!(same(batch[0]) && same(batch[1]));
+            assert_eq!(batch[2], forest.score(&extreme[2]));
+            let oob = forest.oob_scores(&extreme).unwrap();
+            assert!(same(oob[0]) && same(oob[1]));
+        }
+    }
+
+    #[test]
+    fn try_score_forest_2d() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 10.);
+        let values: Vec<_> = (0..500)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            score_scale: ScoreScale::Percentile,
+            ..ForestOptions::default()
+        };
+
+        let forest = Forest::from_slice(&values, &options).unwrap();
+        let item = [5.0, f64::INFINITY];
+        assert_eq!(forest.try_score(&item).unwrap(), forest.score(&item));
+
+        let forest = Forest::from_slice(
+            &values,
+            &ForestOptions {
+                non_finite_handling: NonFiniteHandling::Reject,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            forest.try_score(&values[0]).unwrap(),
+            forest.score(&values[0])
+        );
+        match forest.try_score(&item) {
+            Err(error @ Error::NonFiniteValue { dimension: 1, .. }) => {
+                assert_eq!(error.to_string(), "value inf in dimension 1 is not finite")
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(forest.score(&item).is_nan());
+        assert!(forest.freeze().score(&[f64::NAN, 1.0]).is_nan());
+    }
+
+    #[test]
+    fn preprocessing_forest_2d() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 1000.);
+        let noise = Uniform::new(-10., 10.);
+        let values: Vec<_> = (0..2000)
+            .map(|_| {
+                let x = rng.sample(distribution);
+                [x, x + rng.sample(noise)]
+            })
+            .collect();
+
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            preprocessing: vec![Step::StandardScaler, Step::PcaWhitening],
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert!(!forest.pipeline().is_empty());
+
+        // within the range of both features, but far off their correlation
+        let normal = [500.0, 500.0];
+        let anomaly = [300.0, 700.0];
+        assert!(forest.score(&anomaly) > forest.score(&normal) + 0.05);
+
+        #[cfg(feature = "serde")]
+        {
+            let forest_json = serde_json::to_string(&forest).unwrap();
+            let forest2: Forest<f64, 2> = serde_json::from_str(forest_json.as_str()).unwrap();
+            assert!((forest2.score(&anomaly) - forest.score(&anomaly)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn clone_and_debug_forest_3d_f64() {
+        let forest = make_f64_forest();
+        let cloned = forest.clone();
+        assert_eq!(cloned, forest);
+        assert_anomalies_forest_3d_f64(&cloned);
+
+        let debug = format!("{:?}", forest);
+        assert!(debug.starts_with("Forest { n_trees: 150, sample_size: 200,"));
+        let debug = format!("{:?}", forest.trees[0]);
+        assert!(debug.starts_with("Tree { n_leaves: "));
+        assert!(debug.contains("root: InNode { num_samples: 200, .. }"));
+    }
+
+    #[test]
+    fn rotation_forest_2d() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 1000.);
+        let noise = Uniform::new(-10., 10.);
+        let values: Vec<_> = (0..2000)
+            .map(|_| {
+                let x = rng.sample(distribution);
+                [x, x + rng.sample(noise)]
+            })
+            .collect();
+
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            rotation_subset_size: Some(2),
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert!(forest.trees.iter().all(|tree| tree.transform.is_some()));
+
+        // within the range of both features, but far off their correlation
+        let normal = [500.0, 500.0];
+        let anomaly = [300.0, 700.0];
+        assert!(forest.score(&anomaly) > forest.score(&normal) + 0.05);
+
+        #[cfg(feature = "serde")]
+        {
+            // a rotation not fitting the number of dimensions
+            let mut json = serde_json::to_value(&forest).unwrap();
+            json["trees"][5]["transform"] = serde_json::json!({"Rotation": [1.0, 0.0, 0.0]});
+            assert!(serde_json::from_value::<Forest<f64, 2>>(json).is_err());
+        }
     }
 
     #[cfg(feature = "serde")]
@@ -658,5 +5110,99 @@ mod tests {
         let forest_json = serde_json::to_string(&forest).unwrap();
         let forest2 = serde_json::from_str(forest_json.as_str()).unwrap();
         assert_anomalies_forest_3d_f64(&forest2);
+        assert_eq!(forest2, forest);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_options() {
+        let options = ForestOptions {
+            n_trees: 50,
+            extension_level: ExtensionLevel::Level(1),
+            normal_distribution: NormalDistribution::Sparse { density: 0.5 },
+            preprocessing: vec![Step::RobustScaler],
+            imputation: Some(Imputation::Median),
+            ..ForestOptions::default()
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ForestOptions>(&json).unwrap(),
+            options
+        );
+        let options: ForestOptions = serde_json::from_str(r#"{"n_trees": 50}"#).unwrap();
+        assert_eq!(
+            options,
+            ForestOptions {
+                n_trees: 50,
+                ..ForestOptions::default()
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_without_node_samples() {
+        let forest = make_f64_forest();
+        let mut json = serde_json::to_value(&forest).unwrap();
+        fn remove_num_samples(node: &mut serde_json::Value) {
+            if let Some(in_node) = node.get_mut("In") {
+                in_node.as_object_mut().unwrap().remove("num_samples");
+                remove_num_samples(&mut in_node["left"]);
+                remove_num_samples(&mut in_node["right"]);
+            }
+        }
+        for tree in json["trees"].as_array_mut().unwrap() {
+            remove_num_samples(&mut tree["root"]);
+        }
+        let forest2: Forest<f64, 3> = serde_json::from_value(json).unwrap();
+        assert_eq!(forest2, forest);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_initial_format() {
+        // a forest serialized by the first version, without sample counts of internal
+        // nodes, leaf ids and numbers of leaves
+        let json = r#"{
+            "avg_path_length_c": 1.0,
+            "trees": [{"root": {"In": {
+                "left": {"Ex": {"num_samples": 3}},
+                "right": {"In": {
+                    "left": {"Ex": {"num_samples": 1}},
+                    "right": {"Ex": {"num_samples": 0}},
+                    "n": [0.0, 1.0],
+                    "p": [0.0, 2.0]
+                }},
+                "n": [1.0, 0.0],
+                "p": [0.5, 0.0]
+            }}}]
+        }"#;
+        let forest: Forest<f64, 2> = serde_json::from_str(json).unwrap();
+        assert_eq!(forest.leaf_counts(), vec![3]);
+        assert_eq!(forest.leaf_indices(&[0.0, 0.0]), vec![0]);
+        assert_eq!(forest.leaf_indices(&[1.0, 1.0]), vec![1]);
+        assert_eq!(forest.leaf_indices(&[1.0, 3.0]), vec![2]);
+        assert_eq!(forest.trees()[0].sample_size(), 4);
+        assert!(forest.score(&[1.0, 3.0]) > forest.score(&[0.0, 0.0]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_forest_binary_formats() {
+        let forest = make_f64_forest();
+
+        let bytes = postcard::to_allocvec(&forest).unwrap();
+        let forest2: Forest<f64, 3> = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(forest2, forest);
+
+        let mut bytes = vec![];
+        ciborium::ser::into_writer(&forest, &mut bytes).unwrap();
+        let forest2: Forest<f64, 3> = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(forest2, forest);
+
+        let compact = forest.to_compact().unwrap();
+        let bytes = postcard::to_allocvec(&compact).unwrap();
+        let compact2 = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(Forest::from_compact(compact2).unwrap(), forest);
     }
 }
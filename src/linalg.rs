@@ -0,0 +1,83 @@
+/// Eigen decomposition of the symmetric `n`x`n` matrix stored row-major in `matrix` using
+/// the cyclic Jacobi method.
+///
+/// Returns the eigenvalues in descending order together with the corresponding eigenvectors,
+/// stored as the rows of a row-major `n`x`n` matrix.
+pub(crate) fn symmetric_eigen(mut matrix: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    const MAX_SWEEPS: usize = 100;
+
+    let mut vectors = vec![0.0; n * n];
+    for i in 0..n {
+        vectors[i * n + i] = 1.0;
+    }
+
+    for _ in 0..MAX_SWEEPS {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |j| *j != i).map(move |j| (i, j)))
+            .map(|(i, j)| matrix[i * n + j].powi(2))
+            .sum();
+        if off_diagonal <= f64::EPSILON * f64::EPSILON {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = matrix[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                let theta = (matrix[q * n + q] - matrix[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                // A' = Jᵀ A J with the rotation J acting on rows/columns p and q
+                for k in 0..n {
+                    let (akp, akq) = (matrix[k * n + p], matrix[k * n + q]);
+                    matrix[k * n + p] = c * akp - s * akq;
+                    matrix[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (matrix[p * n + k], matrix[q * n + k]);
+                    matrix[p * n + k] = c * apk - s * aqk;
+                    matrix[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (vectors[k * n + p], vectors[k * n + q]);
+                    vectors[k * n + p] = c * vkp - s * vkq;
+                    vectors[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<_> = (0..n).collect();
+    order.sort_by(|a, b| {
+        matrix[b * n + b]
+            .partial_cmp(&matrix[a * n + a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let values = order.iter().map(|i| matrix[i * n + i]).collect();
+    // the eigenvectors are the columns of `vectors`
+    let rows = order
+        .iter()
+        .flat_map(|i| (0..n).map(|k| vectors[k * n + *i]).collect::<Vec<_>>())
+        .collect();
+    (values, rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::symmetric_eigen;
+
+    #[test]
+    fn eigen_decomposition() {
+        let (values, vectors) = symmetric_eigen(vec![2.0, 1.0, 1.0, 2.0], 2);
+        assert!((values[0] - 3.0).abs() < 1e-12);
+        assert!((values[1] - 1.0).abs() < 1e-12);
+        let half_sqrt2 = 0.5_f64.sqrt();
+        assert!((vectors[0].abs() - half_sqrt2).abs() < 1e-12);
+        assert!((vectors[0] - vectors[1]).abs() < 1e-12);
+        assert!((vectors[2] + vectors[3]).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,322 @@
+//! A relocatable binary representation of a [`FrozenForest`] which is scored directly from
+//! a byte buffer, e.g. a file mapped read-only into memory.
+//!
+//! In a pre-fork server every worker process can map the same file, so the operating system
+//! keeps a single copy of the nodes in its page cache instead of one copy per process. The
+//! crate does not map files itself, any `&[u8]` works, e.g. the `Mmap` of the `memmap2`
+//! crate.
+//!
+//! The representation starts with a header followed by the nodes of all trees:
+//!
+//! * the magic bytes `EIFM` and the version `1` as `u32`,
+//! * the number of dimensions `N`, the size of a value in bytes (`4` or `8`) and the
+//!   number of nodes, each as `u32`,
+//! * the length of the model as `u64`, followed by the model without its nodes as JSON,
+//! * the nodes in the order of [`FrozenForest::nodes`], each with the `N` coordinates of the
+//!   normal vector and the intercept point, the path length of leaves as `f64`, the index
+//!   of the right child and the number of samples as `u32`.
+//!
+//! All numbers are stored little endian without alignment requirements, so the buffer can
+//! be read on every platform.
+
+use std::fmt;
+use std::io::Write;
+use std::marker::PhantomData;
+
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::frozen::{FrozenForest, FrozenModel, FrozenNode};
+use crate::{Error, ForestFloat};
+
+const MAGIC: &[u8; 4] = b"EIFM";
+const VERSION: u32 = 1;
+
+/// Length of the header preceding the model.
+const HEADER_LEN: usize = 28;
+
+fn to_error(error: serde_json::Error) -> Error {
+    if error.is_io() {
+        Error::Io(error.into())
+    } else {
+        Error::InvalidModel
+    }
+}
+
+/// Number of bytes of a node with `N` values of `value_size` bytes each per vector.
+fn node_len<const N: usize>(value_size: usize) -> usize {
+    2 * N * value_size + 16
+}
+
+impl<'de, T, const N: usize> FrozenForest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Write the forest in the representation read by [`MappedForest::from_bytes`], see
+    /// the [module documentation](self).
+    pub fn write_mapped<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let value_size = std::mem::size_of::<T>();
+        let model = serde_json::to_vec(&self.model).map_err(to_error)?;
+        let mut header = Vec::with_capacity(HEADER_LEN + model.len());
+        header.extend_from_slice(MAGIC);
+        for value in [
+            VERSION,
+            N as u32,
+            value_size as u32,
+            self.nodes().len() as u32,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.extend_from_slice(&(model.len() as u64).to_le_bytes());
+        header.extend_from_slice(&model);
+        writer.write_all(&header).map_err(Error::Io)?;
+
+        let mut buffer = Vec::with_capacity(node_len::<N>(value_size));
+        for node in self.nodes() {
+            buffer.clear();
+            for value in node.normal.iter().chain(node.intercept.iter()) {
+                let value = value.to_f64().unwrap_or(f64::NAN);
+                if value_size == 4 {
+                    buffer.extend_from_slice(&(value as f32).to_le_bytes());
+                } else {
+                    buffer.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            buffer.extend_from_slice(&node.path_length.to_le_bytes());
+            buffer.extend_from_slice(&node.right.to_le_bytes());
+            buffer.extend_from_slice(&node.num_samples.to_le_bytes());
+            writer.write_all(&buffer).map_err(Error::Io)?;
+        }
+        writer.flush().map_err(Error::Io)
+    }
+}
+
+/// A frozen forest reading its nodes from a borrowed byte buffer, see the
+/// [module documentation](self).
+///
+/// Only the small remainder of the model is parsed into memory, the nodes are decoded from
+/// the buffer while scoring. Scores equal those of the [`FrozenForest`] the buffer was
+/// written from.
+#[derive(Clone)]
+pub struct MappedForest<'a, T, const N: usize> {
+    model: FrozenModel,
+    nodes: &'a [u8],
+    n_nodes: usize,
+    value_size: usize,
+    values: PhantomData<T>,
+}
+
+impl<'a, 'de, T, const N: usize> MappedForest<'a, T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Read a forest written by [`FrozenForest::write_mapped`] from `bytes`.
+    ///
+    /// All nodes are checked once, so scoring cannot fail later on. Fails with
+    /// [`Error::InvalidModel`] when the buffer is malformed or was written for a different
+    /// number of dimensions or value type.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        let u32_at = |offset: usize| -> Result<usize, Error> {
+            let field = bytes.get(offset..offset + 4).ok_or(Error::InvalidModel)?;
+            Ok(u32::from_le_bytes(field.try_into().unwrap()) as usize)
+        };
+        let value_size = std::mem::size_of::<T>();
+        if bytes.get(..4) != Some(MAGIC.as_slice())
+            || u32_at(4)? != VERSION as usize
+            || u32_at(8)? != N
+            || u32_at(12)? != value_size
+        {
+            return Err(Error::InvalidModel);
+        }
+        let n_nodes = u32_at(16)?;
+        let model_len = bytes
+            .get(20..HEADER_LEN)
+            .map(|field| u64::from_le_bytes(field.try_into().unwrap()))
+            .ok_or(Error::InvalidModel)?;
+        let model_end = usize::try_from(model_len)
+            .ok()
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .filter(|end| *end <= bytes.len())
+            .ok_or(Error::InvalidModel)?;
+        let model: FrozenModel =
+            serde_json::from_slice(&bytes[HEADER_LEN..model_end]).map_err(to_error)?;
+
+        let nodes = &bytes[model_end..];
+        if Some(nodes.len()) != n_nodes.checked_mul(node_len::<N>(value_size)) {
+            return Err(Error::InvalidModel);
+        }
+        let forest = Self {
+            model,
+            nodes,
+            n_nodes,
+            value_size,
+            values: PhantomData,
+        };
+        forest.validate()?;
+        Ok(forest)
+    }
+
+    /// Check that the model fits items with `N` features, that all roots exist and that
+    /// the children of each node follow it, which bounds every path by the number of nodes.
+    fn validate(&self) -> Result<(), Error> {
+        if !self.model.is_valid::<N>()
+            || self
+                .model
+                .trees
+                .iter()
+                .any(|tree| tree.root as usize >= self.n_nodes)
+        {
+            return Err(Error::InvalidModel);
+        }
+        for index in 0..self.n_nodes {
+            let right = self.right(index);
+            if right != 0 && (right <= index + 1 || right >= self.n_nodes) {
+                return Err(Error::InvalidModel);
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of trees of the forest.
+    pub fn n_trees(&self) -> usize {
+        self.model.trees.len()
+    }
+
+    /// Number of nodes of all trees.
+    pub fn n_nodes(&self) -> usize {
+        self.n_nodes
+    }
+
+    /// Compute the anomaly score for an item, see [`crate::Forest::score`].
+    pub fn score(&self, values: &[T; N]) -> f64 {
+        self.model.scale_score(self.score_raw(values))
+    }
+
+    /// Compute the anomaly score for an item without applying the score scale, see
+    /// [`crate::Forest::score_raw`].
+    pub fn score_raw(&self, values: &[T; N]) -> f64 {
+        self.model.score_raw(values, |index| self.node(index))
+    }
+
+    /// Index of the right child of the node at `index`.
+    fn right(&self, index: usize) -> usize {
+        let offset = (index + 1) * node_len::<N>(self.value_size) - 8;
+        u32::from_le_bytes(self.nodes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    fn node(&self, index: usize) -> FrozenNode<T, N> {
+        let node = &self.nodes[index * node_len::<N>(self.value_size)..];
+        let value = |i: usize| {
+            let bytes = &node[i * self.value_size..(i + 1) * self.value_size];
+            if self.value_size == 4 {
+                T::from(f32::from_le_bytes(bytes.try_into().unwrap()))
+            } else {
+                T::from(f64::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            .unwrap_or_else(T::nan)
+        };
+        let tail = &node[2 * N * self.value_size..];
+        FrozenNode {
+            normal: std::array::from_fn(value),
+            intercept: std::array::from_fn(|i| value(N + i)),
+            path_length: f64::from_le_bytes(tail[..8].try_into().unwrap()),
+            right: u32::from_le_bytes(tail[8..12].try_into().unwrap()),
+            num_samples: u32::from_le_bytes(tail[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for MappedForest<'_, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedForest")
+            .field("n_trees", &self.model.trees.len())
+            .field("n_nodes", &self.n_nodes)
+            .field("value_size", &self.value_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MappedForest;
+    use crate::datasets::uniform;
+    use crate::{Error, Forest, ForestOptions, ScoreScale};
+
+    #[test]
+    fn mapped_forest_3d() {
+        let values: Vec<[f64; 3]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            rotation_subset_size: Some(2),
+            score_scale: ScoreScale::Percentile,
+            ..ForestOptions::default()
+        };
+        let frozen = Forest::from_slice(&values, &options).unwrap().freeze();
+        let mut bytes = vec![];
+        frozen.write_mapped(&mut bytes).unwrap();
+
+        let mapped = MappedForest::from_bytes(&bytes).unwrap();
+        assert_eq!(mapped.n_trees(), 20);
+        assert_eq!(mapped.n_nodes(), frozen.nodes().len());
+        assert_eq!(
+            format!("{:?}", mapped),
+            format!(
+                "MappedForest {{ n_trees: 20, n_nodes: {}, value_size: 8, .. }}",
+                mapped.n_nodes()
+            )
+        );
+        for item in values[..100].iter().chain([[20.0, 20.0, 20.0]].iter()) {
+            assert_eq!(mapped.score(item), frozen.score(item));
+            assert_eq!(mapped.score_raw(item), frozen.score_raw(item));
+        }
+
+        let values_f32: Vec<_> = values.iter().map(|v| v.map(|x| x as f32)).collect();
+        let frozen_f32 = Forest::from_slice(&values_f32, &options).unwrap().freeze();
+        let mut bytes_f32 = vec![];
+        frozen_f32.write_mapped(&mut bytes_f32).unwrap();
+        let mapped_f32 = MappedForest::from_bytes(&bytes_f32).unwrap();
+        for item in values_f32[..100].iter() {
+            assert_eq!(mapped_f32.score(item), frozen_f32.score(item));
+        }
+
+        // wrong value type, number of dimensions or truncated nodes
+        assert!(matches!(
+            MappedForest::<f32, 3>::from_bytes(&bytes),
+            Err(Error::InvalidModel)
+        ));
+        assert!(matches!(
+            MappedForest::<f64, 2>::from_bytes(&bytes),
+            Err(Error::InvalidModel)
+        ));
+        assert!(matches!(
+            MappedForest::<f64, 3>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidModel)
+        ));
+        // a model not fitting the number of dimensions
+        let model_len = u64::from_le_bytes(bytes[20..28].try_into().unwrap()) as usize;
+        let mut model: serde_json::Value =
+            serde_json::from_slice(&bytes[28..28 + model_len]).unwrap();
+        model["pipeline"]["steps"] =
+            serde_json::json!([{"Scale": {"center": [0.0], "scale": [1.0]}}]);
+        let model = serde_json::to_vec(&model).unwrap();
+        let mut corrupt = bytes[..20].to_vec();
+        corrupt.extend_from_slice(&(model.len() as u64).to_le_bytes());
+        corrupt.extend_from_slice(&model);
+        corrupt.extend_from_slice(&bytes[28 + model_len..]);
+        assert!(matches!(
+            MappedForest::<f64, 3>::from_bytes(&corrupt),
+            Err(Error::InvalidModel)
+        ));
+        // a right child pointing backwards
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 8;
+        corrupt[last..last + 4].copy_from_slice(&1_u32.to_le_bytes());
+        assert!(matches!(
+            MappedForest::<f64, 3>::from_bytes(&corrupt),
+            Err(Error::InvalidModel)
+        ));
+    }
+}
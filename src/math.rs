@@ -0,0 +1,229 @@
+//! Transcendental functions used while scoring.
+//!
+//! The functions of the standard library call the math library of the platform, whose
+//! results may differ in the last bits between platforms. With the `deterministic` feature
+//! they are computed from the basic arithmetic operations only, which IEEE 754 defines
+//! exactly, so a serialized model yields bit-identical scores on all platforms.
+
+/// `2^x`.
+#[inline]
+pub(crate) fn exp2(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        soft::exp2(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.exp2()
+    }
+}
+
+/// The natural logarithm of `x`.
+#[inline]
+pub(crate) fn ln(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        soft::ln(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.ln()
+    }
+}
+
+/// `ln(1 + x)`, accurate also for `x` close to zero.
+#[inline]
+pub(crate) fn ln_1p(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        soft::ln_1p(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.ln_1p()
+    }
+}
+
+/// The cosine of `x`.
+#[inline]
+pub(crate) fn cos(x: f64) -> f64 {
+    #[cfg(feature = "deterministic")]
+    {
+        soft::cos(x)
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        x.cos()
+    }
+}
+
+/// Implementations using only additions, multiplications and divisions, accurate to a few
+/// units in the last place.
+#[cfg(any(feature = "deterministic", test))]
+mod soft {
+    use std::f64::consts::{FRAC_PI_2, LN_2};
+
+    /// `ln(2)` split into a part with trailing zero bits, so multiples of it are exact, and
+    /// the remainder.
+    const LN_2_HI: f64 = 6.931_471_803_691_238e-1;
+    const LN_2_LO: f64 = 1.908_214_929_270_587_7e-10;
+
+    /// `pi / 2` split like [`LN_2_HI`].
+    const FRAC_PI_2_HI: f64 = 1.570_796_326_734_125_6;
+    const FRAC_PI_2_LO: f64 = 6.077_100_506_506_192e-11;
+
+    /// `2^n` for `n` in the range of normal numbers.
+    fn pow2i(n: i64) -> f64 {
+        f64::from_bits(((n + 1023) as u64) << 52)
+    }
+
+    pub(super) fn exp2(x: f64) -> f64 {
+        if x.is_nan() {
+            return x;
+        }
+        if x >= 1024.0 {
+            return f64::INFINITY;
+        }
+        if x < -1075.0 {
+            return 0.0;
+        }
+        let n = x.round();
+        // `2^f = e^(f ln 2)` with `|f ln 2| <= 0.35`
+        let r = (x - n) * LN_2;
+        let mut term = 1.0;
+        let mut sum = 1.0;
+        for k in 1..=14 {
+            term *= r / f64::from(k);
+            sum += term;
+        }
+        let n = n as i64;
+        if n < -1022 {
+            // scale in two steps to reach subnormal results
+            sum * pow2i(n + 1022) * pow2i(-1022)
+        } else if n == 1024 {
+            sum * 2.0 * pow2i(1023)
+        } else {
+            sum * pow2i(n)
+        }
+    }
+
+    pub(super) fn ln(x: f64) -> f64 {
+        if x.is_nan() || x < 0.0 {
+            return f64::NAN;
+        }
+        if x == 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        if x.is_infinite() {
+            return x;
+        }
+        // `x = m * 2^e` with `m` in `[sqrt(1/2), sqrt(2))`
+        let (x, mut e) = if x < f64::MIN_POSITIVE {
+            (x * pow2i(54), -54)
+        } else {
+            (x, 0)
+        };
+        let bits = x.to_bits();
+        e += ((bits >> 52) & 0x7ff) as i64 - 1023;
+        let mut m = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | 0x3ff0_0000_0000_0000);
+        if m > std::f64::consts::SQRT_2 {
+            m /= 2.0;
+            e += 1;
+        }
+        // `ln(m) = 2 atanh(s)` with `|s| <= 0.172`
+        let s = (m - 1.0) / (m + 1.0);
+        let s2 = s * s;
+        let mut power = s;
+        let mut sum = 0.0;
+        for k in 0..12 {
+            sum += power / f64::from(2 * k + 1);
+            power *= s2;
+        }
+        let e = e as f64;
+        e * LN_2_HI + (e * LN_2_LO + 2.0 * sum)
+    }
+
+    pub(super) fn ln_1p(x: f64) -> f64 {
+        let u = 1.0 + x;
+        if u == 1.0 {
+            x
+        } else if u.is_infinite() {
+            u
+        } else {
+            // corrects for the rounding error of `1 + x`
+            ln(u) * (x / (u - 1.0))
+        }
+    }
+
+    pub(super) fn cos(x: f64) -> f64 {
+        if !x.is_finite() {
+            return f64::NAN;
+        }
+        // `x = k pi / 2 + r` with `|r| <= pi / 4`
+        let k = (x / FRAC_PI_2).round();
+        let r = (x - k * FRAC_PI_2_HI) - k * FRAC_PI_2_LO;
+        match (k.rem_euclid(4.0)) as u8 {
+            0 => cos_kernel(r),
+            1 => -sin_kernel(r),
+            2 => -cos_kernel(r),
+            _ => sin_kernel(r),
+        }
+    }
+
+    fn cos_kernel(r: f64) -> f64 {
+        let r2 = r * r;
+        let mut term = 1.0;
+        let mut sum = 1.0;
+        for k in 1..=9 {
+            term *= -r2 / f64::from((2 * k - 1) * (2 * k));
+            sum += term;
+        }
+        sum
+    }
+
+    fn sin_kernel(r: f64) -> f64 {
+        let r2 = r * r;
+        let mut term = r;
+        let mut sum = r;
+        for k in 1..=9 {
+            term *= -r2 / f64::from((2 * k) * (2 * k + 1));
+            sum += term;
+        }
+        sum
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{cos, exp2, ln, ln_1p};
+
+        fn assert_close(a: f64, b: f64) {
+            assert!(
+                (a - b).abs() <= 1e-15 * a.abs().max(b.abs()) || a == b,
+                "{} != {}",
+                a,
+                b
+            );
+        }
+
+        #[test]
+        fn portable_functions() {
+            for i in -2000_i32..=2000 {
+                let x = f64::from(i) * 0.537;
+                assert_close(exp2(x), x.exp2());
+                assert!((cos(x / 10.0) - (x / 10.0).cos()).abs() <= 1e-15);
+                let y = f64::from(i.abs() + 1) * 0.731;
+                assert_close(ln(y), y.ln());
+                assert_close(ln(1.0 / y), (1.0 / y).ln());
+                assert_close(ln_1p(x / 2000.0), (x / 2000.0).ln_1p());
+            }
+            assert_eq!(exp2(0.0), 1.0);
+            assert_eq!(exp2(-1074.0), f64::from_bits(1));
+            assert_eq!(exp2(2000.0), f64::INFINITY);
+            assert_eq!(ln(1.0), 0.0);
+            assert_close(ln(f64::from_bits(1)), f64::from_bits(1).ln());
+            assert_eq!(ln(0.0), f64::NEG_INFINITY);
+            assert!(ln(-1.0).is_nan());
+            assert_eq!(ln_1p(1e-300), 1e-300);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Helpers for monitoring a stream of anomaly scores.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::ranked::Ranked;
+use crate::stats::{quantile_sorted, sort_scores, P2Quantile};
+
+/// Maintains the `k` most anomalous items of a stream of scored points together
+/// with the score quantiles over a rolling window of the most recent scores.
+pub struct TopK<I> {
+    k: usize,
+    heap: BinaryHeap<Reverse<Ranked<I>>>,
+    window_size: usize,
+    window: VecDeque<f64>,
+    count: u64,
+}
+
+/// Point-in-time view of a [`TopK`] monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopKSnapshot<I> {
+    /// The most anomalous items seen so far, ordered by descending score.
+    pub top: Vec<(I, f64)>,
+
+    /// Total number of scores observed.
+    pub count: u64,
+
+    /// Median of the scores in the rolling window.
+    pub p50: Option<f64>,
+
+    /// 90th percentile of the scores in the rolling window.
+    pub p90: Option<f64>,
+
+    /// 99th percentile of the scores in the rolling window.
+    pub p99: Option<f64>,
+}
+
+impl<I> TopK<I> {
+    /// Create a monitor keeping the `k` highest scored items and computing quantiles over
+    /// the last `window_size` scores.
+    pub fn new(k: usize, window_size: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+            window_size,
+            window: VecDeque::with_capacity(window_size),
+            count: 0,
+        }
+    }
+
+    /// Consume a scored point. Scores are ordered by [`f64::total_cmp`], so NaN scores,
+    /// e.g. of items rejected by [`crate::NonFiniteHandling::Reject`], rank above all others.
+    pub fn push(&mut self, item: I, score: f64) {
+        self.count += 1;
+
+        if self.window_size > 0 {
+            if self.window.len() == self.window_size {
+                self.window.pop_front();
+            }
+            self.window.push_back(score);
+        }
+
+        if self.k > 0 {
+            self.heap.push(Reverse(Ranked { score, item }));
+            if self.heap.len() > self.k {
+                self.heap.pop();
+            }
+        }
+    }
+
+    /// Total number of scores observed.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The lowest score currently contained in the top-k, if the top-k is already
+    /// filled. Scores below this value will not enter the top-k.
+    pub fn min_top_score(&self) -> Option<f64> {
+        if self.heap.len() < self.k {
+            None
+        } else {
+            self.heap.peek().map(|Reverse(ranked)| ranked.score)
+        }
+    }
+
+    /// Quantiles `qs` (each in `[0, 1]`) of the scores in the rolling window.
+    ///
+    /// Returns an empty vec when no scores have been observed yet.
+    pub fn quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        let sorted = self.sorted_window();
+        qs.iter()
+            .filter_map(|q| quantile_sorted(&sorted, *q))
+            .collect()
+    }
+
+    /// Remove all observed items and scores.
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.window.clear();
+        self.count = 0;
+    }
+
+    fn sorted_window(&self) -> Vec<f64> {
+        let mut sorted: Vec<_> = self.window.iter().copied().collect();
+        sort_scores(&mut sorted);
+        sorted
+    }
+}
+
+impl<I: Clone> TopK<I> {
+    /// Take a snapshot of the current state.
+    pub fn snapshot(&self) -> TopKSnapshot<I> {
+        let mut top: Vec<_> = self
+            .heap
+            .iter()
+            .map(|Reverse(ranked)| (ranked.item.clone(), ranked.score))
+            .collect();
+        top.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let sorted = self.sorted_window();
+        TopKSnapshot {
+            top,
+            count: self.count,
+            p50: quantile_sorted(&sorted, 0.5),
+            p90: quantile_sorted(&sorted, 0.9),
+            p99: quantile_sorted(&sorted, 0.99),
+        }
+    }
+}
+
+/// Running statistics of emitted anomaly scores.
+///
+/// Uses constant memory: mean and variance are computed using Welford's algorithm and the
+/// quantiles are approximated using the P² algorithm.
+#[derive(Clone, Debug)]
+pub struct ScoreTracker {
+    threshold: f64,
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+    above_threshold: u64,
+    quantiles: Vec<P2Quantile>,
+}
+
+/// Summary of the scores observed by a [`ScoreTracker`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScoreSummary {
+    pub count: u64,
+    pub mean: Option<f64>,
+    pub std_dev: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+
+    /// Approximated quantiles as `(q, value)` pairs.
+    pub quantiles: Vec<(f64, f64)>,
+
+    /// Fraction of the scores above the threshold.
+    pub rate_above_threshold: Option<f64>,
+}
+
+impl ScoreTracker {
+    /// Create a tracker counting the scores above `threshold` and approximating the
+    /// quantiles `qs` (each in `[0, 1]`).
+    pub fn new(threshold: f64, qs: &[f64]) -> Self {
+        Self {
+            threshold,
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            above_threshold: 0,
+            quantiles: qs.iter().map(|q| P2Quantile::new(*q)).collect(),
+        }
+    }
+
+    /// Feed an emitted score.
+    pub fn observe(&mut self, score: f64) {
+        self.count += 1;
+        let delta = score - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (score - self.mean);
+        self.min = self.min.min(score);
+        self.max = self.max.max(score);
+        if score > self.threshold {
+            self.above_threshold += 1;
+        }
+        self.quantiles
+            .iter_mut()
+            .for_each(|quantile| quantile.observe(score));
+    }
+
+    /// Number of observed scores.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Mean of the observed scores.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Sample variance of the observed scores.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 1).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    /// Sample standard deviation of the observed scores.
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// Smallest observed score.
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Largest observed score.
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Approximation of quantile `q`. Only the quantiles the tracker has been created with
+    /// are available.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        self.quantiles
+            .iter()
+            .find(|quantile| quantile.p() == q)
+            .and_then(P2Quantile::estimate)
+    }
+
+    /// Fraction of the observed scores above the threshold.
+    pub fn rate_above_threshold(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.above_threshold as f64 / self.count as f64)
+    }
+
+    /// Summarize all statistics, for example for exporting them to a dashboard.
+    pub fn summary(&self) -> ScoreSummary {
+        ScoreSummary {
+            count: self.count,
+            mean: self.mean(),
+            std_dev: self.std_dev(),
+            min: self.min(),
+            max: self.max(),
+            quantiles: self
+                .quantiles
+                .iter()
+                .filter_map(|quantile| quantile.estimate().map(|v| (quantile.p(), v)))
+                .collect(),
+            rate_above_threshold: self.rate_above_threshold(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScoreTracker, TopK};
+
+    #[test]
+    fn top_k_monitor() {
+        let mut monitor = TopK::new(3, 10);
+        for i in 0..100u32 {
+            monitor.push(i, f64::from(i % 50) / 50.0);
+        }
+
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.count, 100);
+        assert_eq!(
+            snapshot.top.iter().map(|(_, s)| *s).collect::<Vec<_>>(),
+            vec![0.98, 0.98, 0.96]
+        );
+        assert_eq!(monitor.min_top_score(), Some(0.96));
+
+        // the window only covers the last 10 scores: 0.8 ..= 0.98
+        let p50 = snapshot.p50.unwrap();
+        assert!((p50 - 0.89).abs() < 1e-9);
+        assert_eq!(monitor.quantiles(&[0.0, 1.0]), vec![0.8, 0.98]);
+
+        monitor.clear();
+        assert!(monitor.snapshot().p50.is_none());
+
+        for (i, score) in [0.5, f64::NAN, 0.7, 0.6].into_iter().enumerate() {
+            monitor.push(i as u32, score);
+        }
+        let snapshot = monitor.snapshot();
+        assert_eq!(
+            snapshot.top.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(monitor.min_top_score(), Some(0.6));
+        assert_eq!(monitor.quantiles(&[0.0, 1.0 / 3.0]), vec![0.5, 0.6]);
+        assert!(snapshot.p99.unwrap().is_nan());
+    }
+
+    #[test]
+    fn score_tracker() {
+        let mut tracker = ScoreTracker::new(0.7, &[0.5, 0.9]);
+        assert!(tracker.mean().is_none());
+        assert!(tracker.quantile(0.5).is_none());
+
+        // 0.0, 0.001, ..., 0.999 in a scrambled order
+        for i in 0..1000u32 {
+            tracker.observe(f64::from((i * 7919) % 1000) / 1000.0);
+        }
+        let summary = tracker.summary();
+        assert_eq!(summary.count, 1000);
+        assert!((summary.mean.unwrap() - 0.4995).abs() < 1e-9);
+        assert!((summary.std_dev.unwrap() - 0.2888).abs() < 1e-3);
+        assert_eq!(summary.min, Some(0.0));
+        assert_eq!(summary.max, Some(0.999));
+        assert!((tracker.quantile(0.5).unwrap() - 0.5).abs() < 0.02);
+        assert!((tracker.quantile(0.9).unwrap() - 0.9).abs() < 0.02);
+        assert!(tracker.quantile(0.1).is_none());
+        assert!((summary.rate_above_threshold.unwrap() - 0.299).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,369 @@
+//! Partial identification forest (PIDForest) of Gopalan, Sharan and Wieder, which scores
+//! items by the sparsity of the regions they fall into instead of by their path lengths.
+//!
+//! Each node splits the range of a single dimension at the point which maximizes the
+//! variance of the density of the samples on both sides. Leaves store their sparsity: the
+//! volume of their box relative to the box of the training data, divided by the share of
+//! the samples they contain. As dense regions are split apart from sparse ones early on,
+//! features without structure rarely get picked, which makes the forest robust against
+//! many irrelevant features.
+
+use num_traits::Float;
+use rand::seq::index;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PidForestOptions {
+    /// `n_trees` is the number of trees to be created.
+    pub n_trees: usize,
+
+    /// `sample_size` is the number of samples of the training data to be used in creation
+    /// of each tree. Must not be larger than `training_data.len()`.
+    pub sample_size: usize,
+
+    /// `max_tree_depth` is the max. allowed tree depth.
+    pub max_tree_depth: usize,
+
+    /// `n_candidates` is the max. number of split points evaluated per dimension and node.
+    pub n_candidates: usize,
+}
+
+impl Default for PidForestOptions {
+    fn default() -> Self {
+        Self {
+            n_trees: 50,
+            sample_size: 100,
+            max_tree_depth: 10,
+            n_candidates: 16,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SerializedPidForest"))]
+pub struct PidForest<const N: usize> {
+    /// At least one tree, split only in dimensions below `N`.
+    trees: Vec<PidNode>,
+}
+
+/// The serialized fields of a [`PidForest`].
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SerializedPidForest {
+    trees: Vec<PidNode>,
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> TryFrom<SerializedPidForest> for PidForest<N> {
+    type Error = &'static str;
+
+    fn try_from(serialized: SerializedPidForest) -> Result<Self, Self::Error> {
+        if serialized.trees.is_empty() {
+            return Err("forest without trees");
+        }
+        if !serialized.trees.iter().all(|tree| tree.is_valid(N)) {
+            return Err("split dimension does not fit the number of dimensions");
+        }
+        Ok(Self {
+            trees: serialized.trees,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum PidNode {
+    Leaf {
+        /// Logarithm of the sparsity of the leaf.
+        log_sparsity: f64,
+    },
+    Split {
+        dimension: usize,
+        /// Items with a value up to the threshold go to the left child.
+        threshold: f64,
+        left: Box<PidNode>,
+        right: Box<PidNode>,
+    },
+}
+
+impl PidNode {
+    /// Whether all splits are in one of the `n_dimensions` dimensions, which is not the
+    /// case for malformed deserialized trees.
+    #[cfg(feature = "serde")]
+    fn is_valid(&self, n_dimensions: usize) -> bool {
+        match self {
+            Self::Leaf { .. } => true,
+            Self::Split {
+                dimension,
+                left,
+                right,
+                ..
+            } => {
+                *dimension < n_dimensions
+                    && left.is_valid(n_dimensions)
+                    && right.is_valid(n_dimensions)
+            }
+        }
+    }
+
+    fn log_sparsity(&self, values: &[f64]) -> f64 {
+        let mut node = self;
+        loop {
+            match node {
+                Self::Leaf { log_sparsity } => return *log_sparsity,
+                Self::Split {
+                    dimension,
+                    threshold,
+                    left,
+                    right,
+                } => {
+                    node = if values[*dimension] <= *threshold {
+                        left
+                    } else {
+                        right
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The samples of a tree and their box, shared by all nodes while building the tree.
+struct TreeBuilder<'a> {
+    /// The sampled rows, converted to `f64`.
+    samples: Vec<Vec<f64>>,
+
+    /// Width of the box of the training data in each dimension.
+    root_widths: &'a [f64],
+
+    options: &'a PidForestOptions,
+}
+
+impl<const N: usize> PidForest<N> {
+    /// Build a new forest from the given training data.
+    pub fn from_slice<T: Float>(
+        training_data: &[[T; N]],
+        options: &PidForestOptions,
+    ) -> Result<Self, Error> {
+        if training_data.is_empty()
+            || N == 0
+            || options.n_trees == 0
+            || options.sample_size == 0
+            || training_data.len() < options.sample_size
+        {
+            return Err(Error::InsufficientTrainingData);
+        }
+        let to_f64 = |values: &[T; N]| -> Vec<f64> {
+            values
+                .iter()
+                .map(|v| v.to_f64().unwrap_or(f64::NAN))
+                .collect()
+        };
+
+        let mut lower = [f64::INFINITY; N];
+        let mut upper = [f64::NEG_INFINITY; N];
+        for values in training_data {
+            for (i, v) in to_f64(values).into_iter().enumerate() {
+                lower[i] = lower[i].min(v);
+                upper[i] = upper[i].max(v);
+            }
+        }
+        let root_widths: Vec<_> = lower.iter().zip(upper.iter()).map(|(l, u)| u - l).collect();
+
+        let rng = &mut rand::thread_rng();
+        let trees = (0..options.n_trees)
+            .map(|_| {
+                let builder = TreeBuilder {
+                    samples: index::sample(rng, training_data.len(), options.sample_size)
+                        .iter()
+                        .map(|row| to_f64(&training_data[row]))
+                        .collect(),
+                    root_widths: &root_widths,
+                    options,
+                };
+                let mut rows: Vec<_> = (0..builder.samples.len()).collect();
+                builder.make_node(&mut rows, lower.to_vec(), upper.to_vec(), 0.0, 0)
+            })
+            .collect();
+        Ok(Self { trees })
+    }
+
+    /// Compute the anomaly score for an item: the average logarithm of the sparsity of the
+    /// leaves it ends up in. Higher scores indicate anomalies. Unlike the scores of
+    /// [`crate::Forest`], the scores are not bounded.
+    pub fn score<T: Float>(&self, values: &[T; N]) -> f64 {
+        let values: Vec<_> = values
+            .iter()
+            .map(|v| v.to_f64().unwrap_or(f64::NAN))
+            .collect();
+        self.trees
+            .iter()
+            .map(|tree| tree.log_sparsity(&values))
+            .sum::<f64>()
+            / self.trees.len() as f64
+    }
+}
+
+impl<'a> TreeBuilder<'a> {
+    /// Build the node for the samples `rows` within the box from `lower` to `upper`, whose
+    /// logarithmic volume relative to the box of the training data is `log_volume`.
+    fn make_node(
+        &self,
+        rows: &mut [usize],
+        mut lower: Vec<f64>,
+        upper: Vec<f64>,
+        log_volume: f64,
+        depth: usize,
+    ) -> PidNode {
+        let split = if depth < self.options.max_tree_depth && rows.len() > 1 {
+            self.best_split(rows, &lower, &upper)
+        } else {
+            None
+        };
+        let (dimension, threshold) = match split {
+            Some(split) => split,
+            None => {
+                let share = rows.len().max(1) as f64 / self.samples.len() as f64;
+                return PidNode::Leaf {
+                    log_sparsity: log_volume - share.ln(),
+                };
+            }
+        };
+
+        // partition the rows in place, rows going to the left are moved to the front
+        let mut split_idx = 0;
+        for i in 0..rows.len() {
+            if self.samples[rows[i]][dimension] <= threshold {
+                rows.swap(i, split_idx);
+                split_idx += 1;
+            }
+        }
+        let (rows_left, rows_right) = rows.split_at_mut(split_idx);
+
+        // the volume only changes in the dimension of the split
+        let relative_width = |low: f64, high: f64| {
+            if self.root_widths[dimension] > 0.0 {
+                ((high - low) / self.root_widths[dimension]).ln()
+            } else {
+                0.0
+            }
+        };
+        let base_volume = log_volume - relative_width(lower[dimension], upper[dimension]);
+        let left_volume = base_volume + relative_width(lower[dimension], threshold);
+        let right_volume = base_volume + relative_width(threshold, upper[dimension]);
+
+        let mut upper_left = upper.clone();
+        upper_left[dimension] = threshold;
+        let left = self.make_node(rows_left, lower.clone(), upper_left, left_volume, depth + 1);
+        lower[dimension] = threshold;
+        let right = self.make_node(rows_right, lower, upper, right_volume, depth + 1);
+        PidNode::Split {
+            dimension,
+            threshold,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// The dimension and threshold maximizing the variance of the density of the samples
+    /// on both sides of the split, weighted by the width of the sides.
+    ///
+    /// Only thresholds halfway between consecutive distinct values are considered, so both
+    /// sides contain samples. `None` when all samples are equal.
+    fn best_split(&self, rows: &[usize], lower: &[f64], upper: &[f64]) -> Option<(usize, f64)> {
+        let mut best: Option<(f64, usize, f64)> = None;
+        for dimension in 0..lower.len() {
+            let width = upper[dimension] - lower[dimension];
+            if width <= 0.0 {
+                continue;
+            }
+            let mut values: Vec<_> = rows
+                .iter()
+                .map(|row| self.samples[*row][dimension])
+                .collect();
+            values.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+            let candidates: Vec<_> = (1..values.len())
+                .filter(|i| values[*i - 1] < values[*i])
+                .collect();
+            let stride = (candidates.len() / self.options.n_candidates.max(1)).max(1);
+            for i in candidates.into_iter().step_by(stride) {
+                let threshold = (values[i - 1] + values[i]) / 2.0;
+                let width_left = ((threshold - lower[dimension]) / width).clamp(0.0, 1.0);
+                let width_right = 1.0 - width_left;
+                if width_left <= 0.0 || width_right <= 0.0 {
+                    continue;
+                }
+                let share_left = i as f64 / values.len() as f64;
+                // the average density over the complete width is 1
+                let objective = width_left * (share_left / width_left - 1.0).powi(2)
+                    + width_right * ((1.0 - share_left) / width_right - 1.0).powi(2);
+                if best.map_or(true, |(best_objective, _, _)| objective > best_objective) {
+                    best = Some((objective, dimension, threshold));
+                }
+            }
+        }
+        best.map(|(_, dimension, threshold)| (dimension, threshold))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::{PidForest, PidForestOptions};
+    use crate::Error;
+
+    #[test]
+    fn score_with_irrelevant_features() {
+        let rng = &mut rand::thread_rng();
+        let cluster = Uniform::new(4., 6.);
+        let noise = Uniform::new(0., 10.);
+        // the first dimension is clustered apart from a few rows, all others are noise
+        let values: Vec<[f64; 8]> = (0..2000)
+            .map(|row| {
+                let mut values = [0.0; 8];
+                values[0] = rng.sample(if row % 50 == 0 { noise } else { cluster });
+                values[1..].iter_mut().for_each(|v| *v = rng.sample(noise));
+                values
+            })
+            .collect();
+
+        let options = PidForestOptions {
+            n_trees: 50,
+            sample_size: 256,
+            ..PidForestOptions::default()
+        };
+        let forest = PidForest::from_slice(&values, &options).unwrap();
+        let normal = forest.score(&[5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0]);
+        let anomaly = forest.score(&[9.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0]);
+        assert!(anomaly > normal + 2.0);
+
+        assert!(matches!(
+            PidForest::from_slice(&values[..10], &options),
+            Err(Error::InsufficientTrainingData)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_invalid_forest() {
+        let split = |dimension: usize| {
+            serde_json::json!({"trees": [{"Split": {
+                "dimension": dimension,
+                "threshold": 0.5,
+                "left": {"Leaf": {"log_sparsity": 0.0}},
+                "right": {"Leaf": {"log_sparsity": 1.0}},
+            }}]})
+        };
+        let forest: PidForest<2> = serde_json::from_value(split(1)).unwrap();
+        assert_eq!(forest.score(&[0.0, 1.0]), 1.0);
+        assert!(serde_json::from_value::<PidForest<2>>(split(2)).is_err());
+        assert!(serde_json::from_value::<PidForest<2>>(serde_json::json!({"trees": []})).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+//! Preprocessing steps which are fit together with a forest and applied to every item
+//! before it is passed to the trees.
+
+use num_traits::Float;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::bundle::Fnv1a;
+use crate::linalg::symmetric_eigen;
+use crate::math;
+use crate::stats::{quantile_sorted, sort_scores, P2Quantile};
+
+/// A preprocessing step, see [`crate::ForestOptions::preprocessing`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Step {
+    /// Scale each feature to zero mean and unit standard deviation.
+    StandardScaler,
+
+    /// Scale each feature to zero median and unit interquartile range, which is less
+    /// sensitive to outliers in the training data than [`Step::StandardScaler`].
+    RobustScaler,
+
+    /// Compress each feature with `sign(x) * ln(1 + |x|)`, useful for heavy-tailed
+    /// features.
+    Log,
+
+    /// Rotate the features onto their principal components and scale these to unit
+    /// variance, removing linear correlations between the features.
+    PcaWhitening,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum FittedStep {
+    Scale {
+        center: Vec<f64>,
+        scale: Vec<f64>,
+    },
+    Log,
+    /// `components` holds one row per output feature, already divided by the standard
+    /// deviation of the component.
+    Project {
+        center: Vec<f64>,
+        components: Vec<f64>,
+    },
+}
+
+impl FittedStep {
+    fn fit<const N: usize>(step: Step, data: &[[f64; N]]) -> Self {
+        let count = data.len().max(1) as f64;
+        let mean: Vec<_> = (0..N)
+            .map(|i| data.iter().map(|values| values[i]).sum::<f64>() / count)
+            .collect();
+
+        match step {
+            Step::StandardScaler => {
+                let scale = (0..N)
+                    .map(|i| {
+                        let variance = data
+                            .iter()
+                            .map(|values| (values[i] - mean[i]).powi(2))
+                            .sum::<f64>()
+                            / count;
+                        non_zero(variance.sqrt())
+                    })
+                    .collect();
+                Self::Scale {
+                    center: mean,
+                    scale,
+                }
+            }
+            Step::RobustScaler => {
+                let (center, scale) = (0..N)
+                    .map(|i| {
+                        let mut column: Vec<_> = data.iter().map(|values| values[i]).collect();
+                        sort_scores(&mut column);
+                        let quantile = |q| quantile_sorted(&column, q).unwrap_or(0.0);
+                        (quantile(0.5), non_zero(quantile(0.75) - quantile(0.25)))
+                    })
+                    .unzip();
+                Self::Scale { center, scale }
+            }
+            Step::Log => Self::Log,
+            Step::PcaWhitening => {
+                let mut covariance = vec![0.0; N * N];
+                for values in data {
+                    for i in 0..N {
+                        for j in 0..N {
+                            covariance[i * N + j] +=
+                                (values[i] - mean[i]) * (values[j] - mean[j]) / count;
+                        }
+                    }
+                }
+
+                let (variances, mut components) = symmetric_eigen(covariance, N);
+                // components without variance in the training data are not scaled up
+                // arbitrarily
+                let tolerance = 1e-12 * variances.first().copied().unwrap_or(0.0);
+                for (i, variance) in variances.into_iter().enumerate() {
+                    let std_dev = non_zero(variance.max(tolerance).sqrt());
+                    components[i * N..(i + 1) * N]
+                        .iter_mut()
+                        .for_each(|c| *c /= std_dev);
+                }
+                Self::Project {
+                    center: mean,
+                    components,
+                }
+            }
+        }
+    }
+
+    fn apply<const N: usize>(&self, values: &mut [f64; N]) {
+        match self {
+            Self::Scale { center, scale } => {
+                for (i, v) in values.iter_mut().enumerate() {
+                    *v = (*v - center[i]) / scale[i];
+                }
+            }
+            Self::Log => {
+                for v in values.iter_mut() {
+                    *v = v.signum() * math::ln_1p(v.abs());
+                }
+            }
+            Self::Project { center, components } => {
+                let centered = *values;
+                for (i, v) in values.iter_mut().enumerate() {
+                    *v = (0..N)
+                        .map(|j| components[i * N + j] * (centered[j] - center[j]))
+                        .sum();
+                }
+            }
+        }
+    }
+}
+
+fn non_zero(scale: f64) -> f64 {
+    if scale > 0.0 && scale.is_finite() {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// Fitted preprocessing steps, applied in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pipeline {
+    steps: Vec<FittedStep>,
+}
+
+impl Pipeline {
+    /// Fit the steps one after another, each on the output of the previous steps.
+    pub fn fit<T: Float, const N: usize>(steps: &[Step], data: &[[T; N]]) -> Self {
+        Self::fit_rows(steps, data.iter().copied())
+    }
+
+    /// [`Pipeline::fit`] on rows which are not stored as slice. The rows are only read
+    /// when there are steps to fit.
+    pub(crate) fn fit_rows<T: Float, const N: usize>(
+        steps: &[Step],
+        rows: impl Iterator<Item = [T; N]>,
+    ) -> Self {
+        if steps.is_empty() {
+            return Self::default();
+        }
+        let mut transformed: Vec<_> = rows.map(|values| to_f64(&values)).collect();
+        let mut fitted = Vec::with_capacity(steps.len());
+        for step in steps {
+            let step = FittedStep::fit(*step, &transformed);
+            transformed.iter_mut().for_each(|values| step.apply(values));
+            fitted.push(step);
+        }
+        Self { steps: fitted }
+    }
+
+    /// Whether the pipeline leaves items unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Whether the fitted steps have the lengths of items with `N` features, which is not
+    /// the case for malformed deserialized pipelines.
+    pub(crate) fn is_valid<const N: usize>(&self) -> bool {
+        self.steps.iter().all(|step| match step {
+            FittedStep::Scale { center, scale } => center.len() == N && scale.len() == N,
+            FittedStep::Log => true,
+            FittedStep::Project { center, components } => {
+                center.len() == N && components.len() == N * N
+            }
+        })
+    }
+
+    /// Feed the fitted steps into the hash of [`crate::Forest::fingerprint`].
+    pub(crate) fn write_fingerprint(&self, hash: &mut Fnv1a) {
+        hash.write_u64(self.steps.len() as u64);
+        for step in &self.steps {
+            match step {
+                FittedStep::Scale { center, scale } => {
+                    hash.write_u64(0);
+                    hash.write_f64s(center);
+                    hash.write_f64s(scale);
+                }
+                FittedStep::Log => hash.write_u64(1),
+                FittedStep::Project { center, components } => {
+                    hash.write_u64(2);
+                    hash.write_f64s(center);
+                    hash.write_f64s(components);
+                }
+            }
+        }
+    }
+
+    /// Number of bytes allocated on the heap by the fitted steps.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.steps.capacity() * std::mem::size_of::<FittedStep>()
+            + self
+                .steps
+                .iter()
+                .map(|step| match step {
+                    FittedStep::Scale { center, scale } => center.capacity() + scale.capacity(),
+                    FittedStep::Log => 0,
+                    FittedStep::Project { center, components } => {
+                        center.capacity() + components.capacity()
+                    }
+                })
+                .sum::<usize>()
+                * std::mem::size_of::<f64>()
+    }
+
+    /// Apply all steps to an item.
+    pub fn transform<T: Float, const N: usize>(&self, values: &[T; N]) -> [T; N] {
+        if self.steps.is_empty() {
+            return *values;
+        }
+        let mut transformed = to_f64(values);
+        self.steps
+            .iter()
+            .for_each(|step| step.apply(&mut transformed));
+        transformed.map(|v| T::from(v).unwrap_or_else(T::nan))
+    }
+}
+
+/// Scales features using running statistics of a stream of items, so changes in the scale of
+/// the features are tracked without refitting.
+///
+/// Uses constant memory: the mean and the standard deviation are computed using Welford's
+/// algorithm and, for robust scaling, the median and the quartiles are approximated using
+/// the P² algorithm. The current statistics can be frozen into a [`Pipeline`], e.g. to
+/// transform the training data when retraining a forest. A forest trained on transformed
+/// data does not store the pipeline, so the items it scores have to be transformed with the
+/// same pipeline.
+#[derive(Clone, Debug)]
+pub struct RunningScaler<const N: usize> {
+    count: u64,
+    mean: [f64; N],
+    m2: [f64; N],
+    /// Lower quartile, median and upper quartile of each feature for robust scaling.
+    quartiles: Option<Vec<[P2Quantile; 3]>>,
+    /// Center and scale of each feature, updated with each observed item.
+    center: [f64; N],
+    scale: [f64; N],
+}
+
+impl<const N: usize> RunningScaler<N> {
+    /// A scaler like [`Step::StandardScaler`].
+    pub fn standard() -> Self {
+        Self {
+            count: 0,
+            mean: [0.0; N],
+            m2: [0.0; N],
+            quartiles: None,
+            center: [0.0; N],
+            scale: [1.0; N],
+        }
+    }
+
+    /// A scaler like [`Step::RobustScaler`].
+    pub fn robust() -> Self {
+        Self {
+            quartiles: Some(
+                (0..N)
+                    .map(|_| [0.25, 0.5, 0.75].map(P2Quantile::new))
+                    .collect(),
+            ),
+            ..Self::standard()
+        }
+    }
+
+    /// Update the statistics with an item.
+    pub fn observe<T: Float>(&mut self, values: &[T; N]) {
+        let values = to_f64(values);
+        self.count += 1;
+        for (i, v) in values.iter().enumerate() {
+            let delta = v - self.mean[i];
+            self.mean[i] += delta / self.count as f64;
+            self.m2[i] += delta * (v - self.mean[i]);
+        }
+        match &mut self.quartiles {
+            Some(quartiles) => {
+                for (i, (quartiles, v)) in quartiles.iter_mut().zip(values).enumerate() {
+                    quartiles.iter_mut().for_each(|q| q.observe(v));
+                    let estimate = |q: &P2Quantile| q.estimate().unwrap_or(0.0);
+                    let [lower, median, upper] = &*quartiles;
+                    self.center[i] = estimate(median);
+                    self.scale[i] = non_zero(estimate(upper) - estimate(lower));
+                }
+            }
+            None => {
+                self.center = self.mean;
+                self.scale = self.std_dev().map(non_zero);
+            }
+        }
+    }
+
+    /// Number of observed items.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Mean of each feature.
+    pub fn mean(&self) -> [f64; N] {
+        self.mean
+    }
+
+    /// Standard deviation of each feature.
+    pub fn std_dev(&self) -> [f64; N] {
+        let count = self.count.max(1) as f64;
+        self.m2.map(|m2| (m2 / count).sqrt())
+    }
+
+    /// Scale an item using the current statistics.
+    pub fn transform<T: Float>(&self, values: &[T; N]) -> [T; N] {
+        let values = to_f64(values);
+        std::array::from_fn(|i| {
+            T::from((values[i] - self.center[i]) / self.scale[i]).unwrap_or_else(T::nan)
+        })
+    }
+
+    /// A pipeline scaling items using the current statistics.
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline {
+            steps: vec![FittedStep::Scale {
+                center: self.center.to_vec(),
+                scale: self.scale.to_vec(),
+            }],
+        }
+    }
+}
+
+fn to_f64<T: Float, const N: usize>(values: &[T; N]) -> [f64; N] {
+    values.map(|v| v.to_f64().unwrap_or(f64::NAN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pipeline, RunningScaler, Step};
+
+    #[test]
+    fn fit_pipeline() {
+        let data: Vec<_> = (0..100)
+            .map(|i| {
+                let x = f64::from(i);
+                [x, 2.0 * x + 5.0]
+            })
+            .collect();
+
+        let pipeline = Pipeline::fit(&[Step::StandardScaler], &data);
+        let scaled: Vec<_> = data
+            .iter()
+            .map(|values| pipeline.transform(values))
+            .collect();
+        let mean = scaled.iter().map(|values| values[1]).sum::<f64>() / 100.0;
+        let variance = scaled.iter().map(|values| values[1].powi(2)).sum::<f64>() / 100.0;
+        assert!(mean.abs() < 1e-9);
+        assert!((variance - 1.0).abs() < 1e-9);
+        assert!((scaled[10][0] - scaled[10][1]).abs() < 1e-9);
+
+        let pipeline = Pipeline::fit(&[Step::RobustScaler], &data);
+        assert_eq!(pipeline.transform(&[49.5, 104.0]), [0.0, 0.0]);
+        assert_eq!(pipeline.transform(&[99.0, 5.0]), [1.0, -1.0]);
+
+        let pipeline = Pipeline::fit(&[Step::Log], &data);
+        let compressed = pipeline.transform(&[0.0, -(1.0_f64.exp() - 1.0)]);
+        assert_eq!(compressed[0], 0.0);
+        assert!((compressed[1] + 1.0).abs() < 1e-12);
+
+        // the second feature is perfectly correlated to the first one, so the whitened data
+        // has a single component with unit variance
+        let pipeline = Pipeline::fit(&[Step::PcaWhitening], &data);
+        let whitened: Vec<_> = data
+            .iter()
+            .map(|values| pipeline.transform(values))
+            .collect();
+        let variance = whitened.iter().map(|values| values[0].powi(2)).sum::<f64>() / 100.0;
+        assert!((variance - 1.0).abs() < 1e-6);
+        assert!(whitened.iter().all(|values| values[1].abs() < 1e-3));
+    }
+
+    #[test]
+    fn running_scaler() {
+        let data: Vec<_> = (0..1000)
+            .map(|i| {
+                let x = f64::from(i % 100);
+                [x, 2.0 * x + 5.0]
+            })
+            .collect();
+
+        let mut scaler = RunningScaler::standard();
+        data.iter().for_each(|values| scaler.observe(values));
+        assert_eq!(scaler.count(), 1000);
+        let pipeline = Pipeline::fit(&[Step::StandardScaler], &data);
+        for values in data.iter().take(100) {
+            let (a, b) = (scaler.transform(values), pipeline.transform(values));
+            assert!((a[0] - b[0]).abs() < 1e-9);
+            assert!((a[1] - b[1]).abs() < 1e-9);
+        }
+
+        let mut scaler = RunningScaler::robust();
+        data.iter().for_each(|values| scaler.observe(values));
+        let scaled = scaler.transform(&[49.5_f64, 104.0]);
+        assert_eq!(scaler.pipeline().transform(&[49.5_f64, 104.0]), scaled);
+        assert!(scaled[0].abs() < 0.1);
+        assert!(scaled[1].abs() < 0.1);
+        // the interquartile range of the first feature is about 50
+        assert!((scaler.transform(&[99.5_f64, 0.0])[0] - 1.0).abs() < 0.1);
+    }
+}
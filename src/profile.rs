@@ -0,0 +1,231 @@
+//! Counters of the nodes visited while scoring, see [`Forest::set_profiling`].
+//!
+//! The counts show which parts of the trees are actually used by the scored data, which
+//! guides pruning and the layout of the nodes, and the distribution of the depths of the
+//! paths explains outliers of the scoring latency.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{Forest, ForestFloat};
+
+/// Snapshot of the counters of a forest, see [`Forest::profile`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// Number of items scored.
+    pub n_items: u64,
+
+    /// Number of nodes visited in each tree, including the leaves.
+    pub node_visits: Vec<u64>,
+
+    /// Number of items reaching each leaf, indexed by tree and leaf id, see
+    /// [`Forest::leaf_indices`].
+    pub leaf_visits: Vec<Vec<u64>>,
+
+    /// Number of paths ending at each depth, summed over all trees.
+    pub depths: Vec<u64>,
+}
+
+impl Profile {
+    /// Mean depth of the paths of all trees, NaN if no item has been scored.
+    pub fn mean_depth(&self) -> f64 {
+        let total: u64 = self.depths.iter().sum();
+        let weighted: f64 = self
+            .depths
+            .iter()
+            .enumerate()
+            .map(|(depth, count)| depth as f64 * *count as f64)
+            .sum();
+        weighted / total as f64
+    }
+}
+
+/// Counters updated while scoring. Scoring only takes the forest by reference, so the
+/// counters are atomic.
+#[derive(Debug)]
+pub(crate) struct Profiler {
+    n_items: AtomicU64,
+    node_visits: Box<[AtomicU64]>,
+    leaf_visits: Box<[Box<[AtomicU64]>]>,
+    depths: Box<[AtomicU64]>,
+}
+
+impl Profiler {
+    /// Counters for trees with the given numbers of leaves and the longest path
+    /// `max_depth`.
+    fn new(leaf_counts: &[u32], max_depth: usize) -> Self {
+        let zeros = |n: usize| (0..n).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            n_items: AtomicU64::new(0),
+            node_visits: zeros(leaf_counts.len()),
+            leaf_visits: leaf_counts
+                .iter()
+                .map(|n_leaves| zeros(*n_leaves as usize))
+                .collect(),
+            depths: zeros(max_depth + 1),
+        }
+    }
+
+    pub(crate) fn record_item(&self) {
+        self.n_items.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the path of an item through `tree` ending at the leaf `leaf` at `depth`.
+    pub(crate) fn record_path(&self, tree: usize, leaf: u32, depth: usize) {
+        self.node_visits[tree].fetch_add(depth as u64 + 1, Ordering::Relaxed);
+        self.leaf_visits[tree][leaf as usize].fetch_add(1, Ordering::Relaxed);
+        self.depths[depth].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Profile {
+        let load = |counters: &[AtomicU64]| {
+            counters
+                .iter()
+                .map(|counter| counter.load(Ordering::Relaxed))
+                .collect()
+        };
+        Profile {
+            n_items: self.n_items.load(Ordering::Relaxed),
+            node_visits: load(&self.node_visits),
+            leaf_visits: self.leaf_visits.iter().map(|leaves| load(leaves)).collect(),
+            depths: load(&self.depths),
+        }
+    }
+}
+
+impl Clone for Profiler {
+    fn clone(&self) -> Self {
+        let copy = |counters: &[AtomicU64]| {
+            counters
+                .iter()
+                .map(|counter| AtomicU64::new(counter.load(Ordering::Relaxed)))
+                .collect()
+        };
+        Self {
+            n_items: AtomicU64::new(self.n_items.load(Ordering::Relaxed)),
+            node_visits: copy(&self.node_visits),
+            leaf_visits: self.leaf_visits.iter().map(|leaves| copy(leaves)).collect(),
+            depths: copy(&self.depths),
+        }
+    }
+}
+
+/// The counters are no part of the model, so forests which only differ in their counters
+/// are equal.
+impl PartialEq for Profiler {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Enable or disable counting the nodes visited by [`Forest::score`] and
+    /// [`Forest::score_raw`]. Enabling resets the counters, as does replacing trees by
+    /// [`crate::replacement::TreeReplacement`].
+    ///
+    /// Profiling adds atomic updates of shared counters to every path through a tree, so it
+    /// slows down scoring, especially from several threads.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = enabled.then(|| {
+            let max_depth = self
+                .trees
+                .iter()
+                .map(|tree| tree.root.depth())
+                .max()
+                .unwrap_or(0);
+            Profiler::new(&self.leaf_counts(), max_depth)
+        });
+    }
+
+    /// The counters collected since profiling was enabled, `None` if it is disabled.
+    pub fn profile(&self) -> Option<Profile> {
+        self.profiler.as_ref().map(Profiler::snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use crate::datasets::uniform;
+    use crate::replacement::{ReplacementSchedule, TreeReplacement};
+    use crate::{Forest, ForestOptions};
+
+    #[test]
+    fn profile_node_visits() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let mut forest = Forest::from_slice(&values, &options).unwrap();
+        assert!(forest.profile().is_none());
+
+        forest.set_profiling(true);
+        let scores: Vec<_> = values[..100].iter().map(|v| forest.score(v)).collect();
+        let profile = forest.profile().unwrap();
+        assert_eq!(profile.n_items, 100);
+        assert_eq!(profile.node_visits.len(), 20);
+        assert_eq!(profile.depths.iter().sum::<u64>(), 100 * 20);
+        assert!(profile.mean_depth() > 1.0);
+        for (tree, leaves) in profile.leaf_visits.iter().enumerate() {
+            assert_eq!(leaves.len() as u32, forest.leaf_counts()[tree]);
+            assert_eq!(leaves.iter().sum::<u64>(), 100);
+        }
+        for values in values[..100].iter() {
+            for (tree, leaf) in forest.leaf_indices(values).into_iter().enumerate() {
+                assert!(profile.leaf_visits[tree][leaf as usize] > 0);
+            }
+        }
+        let visits: u64 = profile.node_visits.iter().sum();
+        let depths: u64 = profile
+            .depths
+            .iter()
+            .enumerate()
+            .map(|(depth, count)| (depth as u64 + 1) * count)
+            .sum();
+        assert_eq!(visits, depths);
+
+        // profiling does not change the scores
+        forest.set_profiling(false);
+        assert!(forest.profile().is_none());
+        for (values, score) in values[..100].iter().zip(scores) {
+            assert_eq!(forest.score(values), score);
+        }
+    }
+
+    #[test]
+    fn profile_replaced_trees() {
+        let options = ForestOptions {
+            n_trees: 4,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        // trees of constant data consist of their root only
+        let mut forest = Forest::from_slice(&[[1.0, 1.0]; 100], &options).unwrap();
+        forest.set_profiling(true);
+        forest.score(&[1.0, 1.0]);
+
+        let mut policy = TreeReplacement::new(options, ReplacementSchedule::Samples(1000), 4, 100);
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        for _ in 0..100 {
+            let values = [rng.sample(distribution), rng.sample(distribution)];
+            policy.observe(&mut forest, values).unwrap();
+        }
+        assert_eq!(policy.replace_oldest(&mut forest).unwrap(), 4);
+        assert_eq!(forest.profile().unwrap().n_items, 0);
+        forest.score(&[9.0, 9.0]);
+        let profile = forest.profile().unwrap();
+        assert_eq!(profile.n_items, 1);
+        assert_eq!(profile.leaf_visits[0].len() as u32, forest.leaf_counts()[0]);
+    }
+}
@@ -0,0 +1,29 @@
+use std::cmp::Ordering;
+
+/// An item ordered solely by its anomaly score, using the total order of `f64`.
+///
+/// Used to keep bounded heaps of the most anomalous items.
+pub(crate) struct Ranked<I> {
+    pub score: f64,
+    pub item: I,
+}
+
+impl<I> PartialEq for Ranked<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<I> Eq for Ranked<I> {}
+
+impl<I> PartialOrd for Ranked<I> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I> Ord for Ranked<I> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score)
+    }
+}
@@ -0,0 +1,181 @@
+//! Gradual replacement of the trees of a forest to follow slow concept drift without
+//! retraining the complete forest.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::split::RandomHyperplane;
+use crate::{build_trees, Error, Forest, ForestFloat, ForestOptions, SampleSizePolicy};
+
+/// When trees get replaced.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReplacementSchedule {
+    /// Replace trees after the given number of observed samples.
+    Samples(usize),
+
+    /// Replace trees when the given time has passed since the last replacement.
+    Interval(Duration),
+}
+
+/// Policy replacing the oldest trees of a forest with trees built from recently observed
+/// samples.
+///
+/// The trees are replaced in a round-robin fashion, so each replacement hits the trees
+/// which have been part of the forest for the longest time. The new trees are built with
+/// the sample size of the forest, so no replacement happens before enough samples
+/// have been buffered, regardless of `ForestOptions::max_sample_size`. The preprocessing of
+/// the forest is kept and applied to the buffered samples.
+pub struct TreeReplacement<T, const N: usize> {
+    options: ForestOptions,
+    schedule: ReplacementSchedule,
+    trees_per_replacement: usize,
+    buffer_capacity: usize,
+    buffer: VecDeque<[T; N]>,
+    next_tree: usize,
+    samples_since_replacement: usize,
+    last_replacement: Instant,
+}
+
+impl<'de, T, const N: usize> TreeReplacement<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default + Send + Sync,
+    StandardNormal: Distribution<T>,
+{
+    /// Create a policy replacing `trees_per_replacement` trees according to `schedule`.
+    ///
+    /// New trees are built with `options` from the last `buffer_capacity` observed samples.
+    pub fn new(
+        options: ForestOptions,
+        schedule: ReplacementSchedule,
+        trees_per_replacement: usize,
+        buffer_capacity: usize,
+    ) -> Self {
+        Self {
+            options,
+            schedule,
+            trees_per_replacement,
+            buffer_capacity,
+            buffer: VecDeque::with_capacity(buffer_capacity),
+            next_tree: 0,
+            samples_since_replacement: 0,
+            last_replacement: Instant::now(),
+        }
+    }
+
+    /// Buffer a recently seen sample and replace trees of `forest` when the schedule is due.
+    ///
+    /// Returns the number of replaced trees.
+    pub fn observe(&mut self, forest: &mut Forest<T, N>, values: [T; N]) -> Result<usize, Error> {
+        if self.buffer_capacity > 0 {
+            if self.buffer.len() == self.buffer_capacity {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back(values);
+        }
+        self.samples_since_replacement += 1;
+
+        let due = match self.schedule {
+            ReplacementSchedule::Samples(n_samples) => self.samples_since_replacement >= n_samples,
+            ReplacementSchedule::Interval(interval) => self.last_replacement.elapsed() >= interval,
+        };
+        if due {
+            self.replace_oldest(forest)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Replace the oldest trees of `forest` right away, regardless of the schedule.
+    ///
+    /// Returns the number of replaced trees, which is `0` as long as fewer samples than the
+    /// sample size of the forest have been buffered. Replacing trees resets the counters of
+    /// a forest with profiling enabled, see [`Forest::set_profiling`].
+    pub fn replace_oldest(&mut self, forest: &mut Forest<T, N>) -> Result<usize, Error> {
+        let n_trees = self.trees_per_replacement.min(forest.trees.len());
+        if n_trees == 0 || self.buffer.len() < forest.sample_size {
+            return Ok(0);
+        }
+
+        let buffered: Vec<_> = self
+            .buffer
+            .iter()
+            .map(|values| forest.pipeline.transform(values))
+            .collect();
+        let options = ForestOptions {
+            sample_size: forest.sample_size,
+            max_sample_size: None,
+            sample_size_policy: SampleSizePolicy::Strict,
+            preprocessing: vec![],
+            // the indices would refer to the buffer instead of the training data
+            record_samples: false,
+            ..self.options.clone()
+        };
+        let (_, trees) = build_trees(
+            buffered.as_slice(),
+            None,
+            &options,
+            n_trees,
+            &RandomHyperplane,
+        )?;
+        for tree in trees {
+            forest.trees[self.next_tree] = tree;
+            self.next_tree = (self.next_tree + 1) % forest.trees.len();
+        }
+        // the counters are sized for the leaves and depths of the replaced trees
+        if forest.profiler.is_some() {
+            forest.set_profiling(true);
+        }
+
+        self.samples_since_replacement = 0;
+        self.last_replacement = Instant::now();
+        Ok(n_trees)
+    }
+
+    /// Number of currently buffered samples.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::{ReplacementSchedule, TreeReplacement};
+    use crate::{Forest, ForestOptions};
+
+    #[test]
+    fn replace_trees_on_drift() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 2.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 40,
+            sample_size: 100,
+            ..ForestOptions::default()
+        };
+        let mut forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let score_before = forest.score(&[11.5, 11.5]);
+
+        let mut policy = TreeReplacement::new(options, ReplacementSchedule::Samples(50), 10, 200);
+        let mut replaced = 0;
+        for _ in 0..1000 {
+            let values = [
+                rng.sample(distribution) + 10.0,
+                rng.sample(distribution) + 10.0,
+            ];
+            replaced += policy.observe(&mut forest, values).unwrap();
+        }
+        assert_eq!(policy.buffered(), 200);
+        // the first replacement is delayed until the 100th sample as it requires a full
+        // sample to be buffered. It is followed by one replacement every 50 samples.
+        assert_eq!(replaced, 190);
+        assert!(forest.score(&[11.5, 11.5]) < score_before);
+    }
+}
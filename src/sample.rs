@@ -0,0 +1,122 @@
+//! Items of other types than arrays, accepted by [`Forest::from_samples`] and
+//! [`Forest::score_sample`].
+//!
+//! ```rust
+//! use extended_isolation_forest::sample::Sample;
+//! use extended_isolation_forest::{Forest, ForestOptions};
+//!
+//! struct Reading {
+//!     temperature: f64,
+//!     pressure: f64,
+//! }
+//!
+//! impl Sample<f64, 2> for Reading {
+//!     fn to_array(&self) -> [f64; 2] {
+//!         [self.temperature, self.pressure]
+//!     }
+//! }
+//!
+//! let readings: Vec<_> = (0..100)
+//!     .map(|i| Reading {
+//!         temperature: 20.0 + (i % 10) as f64,
+//!         pressure: 1000.0 + (i % 7) as f64,
+//!     })
+//!     .collect();
+//! let forest = Forest::from_samples(&readings, &ForestOptions::default()).unwrap();
+//! assert!(forest.score_sample(&(40.0, 1050.0)) > forest.score_sample(&(25.0, 1003.0)));
+//! ```
+//!
+//! [`Forest::from_samples`]: crate::Forest::from_samples
+//! [`Forest::score_sample`]: crate::Forest::score_sample
+
+use std::borrow::Cow;
+
+/// An item with `N` features of type `T`.
+pub trait Sample<T, const N: usize> {
+    /// The features of the item.
+    fn to_array(&self) -> [T; N];
+
+    /// The features of all `samples`, borrowed if they are stored as arrays already.
+    fn to_rows(samples: &[Self]) -> Cow<'_, [[T; N]]>
+    where
+        Self: Sized,
+        T: Clone,
+    {
+        Cow::Owned(samples.iter().map(Self::to_array).collect())
+    }
+}
+
+impl<T: Copy, const N: usize> Sample<T, N> for [T; N] {
+    fn to_array(&self) -> [T; N] {
+        *self
+    }
+
+    fn to_rows(samples: &[Self]) -> Cow<'_, [[T; N]]> {
+        Cow::Borrowed(samples)
+    }
+}
+
+macro_rules! impl_sample_tuple {
+    ($n:literal; $($field:tt: $name:ident),+) => {
+        impl<T: Copy> Sample<T, $n> for ($($name,)+) {
+            fn to_array(&self) -> [T; $n] {
+                [$(self.$field),+]
+            }
+        }
+    };
+}
+
+impl_sample_tuple!(1; 0: T);
+impl_sample_tuple!(2; 0: T, 1: T);
+impl_sample_tuple!(3; 0: T, 1: T, 2: T);
+impl_sample_tuple!(4; 0: T, 1: T, 2: T, 3: T);
+impl_sample_tuple!(5; 0: T, 1: T, 2: T, 3: T, 4: T);
+impl_sample_tuple!(6; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T);
+impl_sample_tuple!(7; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T, 6: T);
+impl_sample_tuple!(8; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T, 6: T, 7: T);
+impl_sample_tuple!(9; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T, 6: T, 7: T, 8: T);
+impl_sample_tuple!(10; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T, 6: T, 7: T, 8: T, 9: T);
+impl_sample_tuple!(11; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T, 6: T, 7: T, 8: T, 9: T, 10: T);
+impl_sample_tuple!(12; 0: T, 1: T, 2: T, 3: T, 4: T, 5: T, 6: T, 7: T, 8: T, 9: T, 10: T, 11: T);
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::Sample;
+    use crate::{Forest, ForestOptions};
+
+    #[test]
+    fn tuple_samples() {
+        assert_eq!((1.0, 2.0, 3.0).to_array(), [1.0, 2.0, 3.0]);
+        let rows = [[1.0, 2.0], [3.0, 4.0]];
+        assert!(matches!(Sample::to_rows(&rows), Cow::Borrowed(_)));
+        assert_eq!(Sample::to_rows(&[(1.0, 2.0), (3.0, 4.0)]).as_ref(), &rows);
+
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 9.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| {
+                (
+                    rng.sample(distribution),
+                    rng.sample(distribution),
+                    rng.sample(distribution),
+                )
+            })
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 128,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_samples(&values, &options).unwrap();
+        assert_eq!(
+            forest.score_sample(&(5.0, 5.0, 5.0)),
+            forest.score(&[5.0, 5.0, 5.0])
+        );
+        assert!(forest.score_sample(&(20.0, 5.0, 5.0)) > forest.score_sample(&(5.0, 5.0, 5.0)));
+    }
+}
@@ -0,0 +1,156 @@
+//! Smoothing of consecutive anomaly scores of the same entity.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{Forest, ForestFloat};
+
+/// Alerting thresholds with hysteresis: an entity enters the alerting state when its
+/// smoothed score exceeds `enter` and only leaves it once the smoothed score drops
+/// below `exit`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hysteresis {
+    pub enter: f64,
+    pub exit: f64,
+}
+
+/// Score of an item together with the smoothed score of its entity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmoothedScore {
+    /// The anomaly score of the item itself.
+    pub raw: f64,
+
+    /// Exponentially weighted moving average of the scores of the entity.
+    pub smoothed: f64,
+
+    /// Whether the entity is in the alerting state. Always `false` when no
+    /// [`Hysteresis`] is configured.
+    pub alerting: bool,
+}
+
+struct EntityState {
+    smoothed: f64,
+    alerting: bool,
+}
+
+/// Exponentially smooths consecutive scores of the same entity to reduce flapping alerts
+/// on noisy data.
+pub struct Ewma<'a, K, T, const N: usize> {
+    forest: &'a Forest<T, N>,
+    alpha: f64,
+    hysteresis: Option<Hysteresis>,
+    entities: HashMap<K, EntityState>,
+}
+
+impl<'a, 'de, K, T, const N: usize> Ewma<'a, K, T, N>
+where
+    K: Eq + Hash,
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Create a smoother using the smoothing factor `alpha` in `(0, 1]`. Larger values
+    /// give more weight to the most recent score.
+    pub fn new(forest: &'a Forest<T, N>, alpha: f64) -> Self {
+        Self {
+            forest,
+            alpha: alpha.clamp(f64::MIN_POSITIVE, 1.0),
+            hysteresis: None,
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Track the alerting state of the entities using the given thresholds.
+    pub fn with_hysteresis(mut self, enter: f64, exit: f64) -> Self {
+        self.hysteresis = Some(Hysteresis { enter, exit });
+        self
+    }
+
+    /// Score an item of entity `key` and update the smoothed score of the entity.
+    pub fn score(&mut self, key: K, values: &[T; N]) -> SmoothedScore {
+        let raw = self.forest.score(values);
+        let alpha = self.alpha;
+        let state = self
+            .entities
+            .entry(key)
+            .and_modify(|state| state.smoothed = alpha * raw + (1.0 - alpha) * state.smoothed)
+            .or_insert(EntityState {
+                smoothed: raw,
+                alerting: false,
+            });
+
+        if let Some(hysteresis) = self.hysteresis {
+            if state.alerting {
+                state.alerting = state.smoothed >= hysteresis.exit;
+            } else {
+                state.alerting = state.smoothed > hysteresis.enter;
+            }
+        }
+
+        SmoothedScore {
+            raw,
+            smoothed: state.smoothed,
+            alerting: state.alerting,
+        }
+    }
+
+    /// The current smoothed score of entity `key`.
+    pub fn smoothed(&self, key: &K) -> Option<f64> {
+        self.entities.get(key).map(|state| state.smoothed)
+    }
+
+    /// Forget the state of entity `key`.
+    pub fn remove(&mut self, key: &K) {
+        self.entities.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use super::Ewma;
+    use crate::{Forest, ForestOptions};
+
+    #[test]
+    fn ewma_with_hysteresis() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 2.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 100,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        let normal = [1.5, 1.5];
+        let anomaly = [10.0, 10.0];
+        let (normal_score, anomaly_score) = (forest.score(&normal), forest.score(&anomaly));
+        let enter = anomaly_score - 0.3 * (anomaly_score - normal_score);
+        let exit = normal_score + 0.3 * (anomaly_score - normal_score);
+
+        let mut ewma = Ewma::new(&forest, 0.5).with_hysteresis(enter, exit);
+        assert!(!ewma.score("a", &normal).alerting);
+
+        // a single anomalous point does not lead to an alert
+        let smoothed = ewma.score("a", &anomaly);
+        assert_eq!(smoothed.raw, anomaly_score);
+        assert!(smoothed.smoothed < anomaly_score);
+        assert!(!smoothed.alerting);
+
+        assert!(ewma.score("a", &anomaly).alerting);
+        // the alert stays active until the smoothed score drops below the exit threshold
+        assert!(ewma.score("a", &normal).alerting);
+        assert!(!ewma.score("a", &normal).alerting);
+
+        // entities are tracked independently
+        assert_eq!(ewma.score("b", &anomaly).smoothed, anomaly_score);
+        ewma.remove(&"b");
+        assert!(ewma.smoothed(&"b").is_none());
+    }
+}
@@ -0,0 +1,276 @@
+//! Generation of the hyperplanes splitting the samples of the internal nodes of a tree.
+
+use num_traits::Float;
+use rand::distributions::{uniform::SampleUniform, Uniform};
+use rand::seq::{index, IteratorRandom};
+use rand::{Rng, RngCore};
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{are_equal, ForestFloat, NormalDistribution};
+
+/// Hyperplane passing through `intercept` and perpendicular to `normal`. Samples on the
+/// side `normal` points to go to the right child node, all other samples to the left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hyperplane<T, const N: usize> {
+    pub intercept: [T; N],
+    pub normal: [T; N],
+}
+
+/// The samples of a node which is about to be split.
+pub struct SplitContext<'a, T, const N: usize> {
+    /// The training data. Only the samples of the tree for trees with a transform and for
+    /// forests built from columns, see [`crate::Forest::from_columns`].
+    pub data: &'a [[T; N]],
+
+    /// The rows of `data` present at the node.
+    pub sample_indices: &'a [usize],
+
+    /// Smallest value of the samples in each dimension.
+    pub min: &'a [T; N],
+
+    /// Largest value of the samples in each dimension.
+    pub max: &'a [T; N],
+
+    /// Depth of the node, `0` for the root.
+    pub depth: usize,
+
+    /// The extension level resolved from [`crate::ForestOptions::extension_level`].
+    pub extension_level: usize,
+
+    /// See [`crate::ForestOptions::split_tolerance`].
+    pub split_tolerance: f64,
+
+    /// The values the coordinates of random normal vectors are divided by, see
+    /// [`crate::ForestOptions::normal_scaling`]. `None` when the normals are not scaled.
+    pub normal_scales: Option<&'a [f64; N]>,
+
+    /// See [`crate::ForestOptions::normal_distribution`].
+    pub normal_distribution: NormalDistribution,
+
+    /// See [`crate::ForestOptions::node_features`].
+    pub node_features: Option<usize>,
+}
+
+/// Chooses the hyperplanes splitting the samples of the internal nodes while building
+/// the trees.
+///
+/// Nodes whose samples are all equal are turned into leaves before a split is requested.
+pub trait SplitStrategy<T, const N: usize>: Send + Sync {
+    fn split(&self, context: &SplitContext<'_, T, N>, rng: &mut dyn RngCore) -> Hyperplane<T, N>;
+}
+
+/// The split rule of the extended isolation forest: the intercept is drawn uniformly from
+/// the range of the samples, the normal from a standard Gaussian distribution with all but
+/// `extension_level + 1` randomly selected coordinates set to zero.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RandomHyperplane;
+
+impl<'de, T, const N: usize> SplitStrategy<T, N> for RandomHyperplane
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    fn split(&self, context: &SplitContext<'_, T, N>, rng: &mut dyn RngCore) -> Hyperplane<T, N> {
+        // randomly select an intercept point p ~ ∈ IR |samples| in
+        // the range of the samples using a uniform distribution
+        let mut p = [T::zero(); N];
+        context
+            .min
+            .iter()
+            .zip(context.max.iter())
+            .zip(p.iter_mut())
+            .for_each(|((min_val, max_val), p_i)| {
+                *p_i = if are_equal(min_val, max_val, context.split_tolerance) {
+                    // sampling with lower and upper bound being equal panics
+                    *min_val
+                } else {
+                    rng.sample(Uniform::new(*min_val, *max_val))
+                }
+            });
+
+        Hyperplane {
+            intercept: p,
+            normal: random_normal(context, rng),
+        }
+    }
+}
+
+/// Like [`RandomHyperplane`], but the intercept is a randomly chosen sample of the node
+/// instead of a point drawn uniformly from the range of the samples.
+///
+/// On heavily skewed features most of the range of the samples is empty, so uniformly
+/// drawn intercepts waste many splits on separating only a few samples. Intercepts chosen
+/// from the samples follow their distribution instead.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SampledIntercept;
+
+impl<'de, T, const N: usize> SplitStrategy<T, N> for SampledIntercept
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    fn split(&self, context: &SplitContext<'_, T, N>, rng: &mut dyn RngCore) -> Hyperplane<T, N> {
+        let sample = context.sample_indices[rng.gen_range(0..context.sample_indices.len())];
+        Hyperplane {
+            intercept: context.data[sample],
+            normal: random_normal(context, rng),
+        }
+    }
+}
+
+/// Number of normal vectors drawn before [`random_normal`] falls back to a random axis when
+/// all coordinates keep being zero.
+const MAX_NORMAL_ATTEMPTS: usize = 100;
+
+/// Randomly select a normal vector ~n ∈ IR |samples| by drawing each coordinate of ~n from
+/// [`SplitContext::normal_distribution`], with all but `extension_level + 1` coordinates set
+/// to zero. With [`SplitContext::node_features`] only that many randomly selected
+/// coordinates are drawn, all others are zero. The coordinates are divided by
+/// [`SplitContext::normal_scales`].
+fn random_normal<T, const N: usize>(
+    context: &SplitContext<'_, T, N>,
+    rng: &mut dyn RngCore,
+) -> [T; N]
+where
+    T: Float,
+    StandardNormal: Distribution<T>,
+{
+    let draw = |rng: &mut dyn RngCore| -> T {
+        match context.normal_distribution {
+            NormalDistribution::Gaussian => rng.sample(StandardNormal),
+            NormalDistribution::Sparse { density } => {
+                if !rng.gen_bool(density) {
+                    T::zero()
+                } else if rng.gen_bool(0.5) {
+                    T::one()
+                } else {
+                    -T::one()
+                }
+            }
+        }
+    };
+
+    let mut n = [T::zero(); N];
+    let mut attempts = 0;
+    loop {
+        match context.node_features {
+            Some(n_features) => {
+                n = [T::zero(); N];
+                for idx in index::sample(rng, N, n_features.clamp(1, N)) {
+                    n[idx] = draw(rng);
+                }
+            }
+            None => {
+                n.iter_mut().for_each(|n_i| *n_i = draw(rng));
+
+                // set coordinates of ~n to zero according to extension level
+                for idx in (0..N).choose_multiple(rng, N - context.extension_level - 1) {
+                    n[idx] = T::zero();
+                }
+            }
+        }
+        if n.iter().any(|n_i| !n_i.is_zero()) || N == 0 {
+            break;
+        }
+        attempts += 1;
+        if attempts == MAX_NORMAL_ATTEMPTS {
+            // e.g. sparse normals with a tiny density, fall back to a random axis
+            n = [T::zero(); N];
+            n[rng.gen_range(0..N)] = if rng.gen_bool(0.5) {
+                T::one()
+            } else {
+                -T::one()
+            };
+            break;
+        }
+    }
+
+    if let Some(scales) = context.normal_scales {
+        // dimensions without any spread are left unscaled
+        for (n_i, scale) in n.iter_mut().zip(scales.iter()) {
+            if *scale > 0.0 {
+                *n_i = *n_i / T::from(*scale).unwrap_or_else(T::one);
+            }
+        }
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rand::distributions::Uniform;
+    use rand::{Rng, RngCore};
+
+    use super::{Hyperplane, SampledIntercept, SplitContext, SplitStrategy};
+    use crate::{Forest, ForestOptions};
+
+    /// Splits the dimension with the largest range at a random point.
+    #[derive(Default)]
+    struct WidestDimension {
+        n_splits: AtomicUsize,
+    }
+
+    impl SplitStrategy<f64, 2> for WidestDimension {
+        fn split(
+            &self,
+            context: &SplitContext<'_, f64, 2>,
+            rng: &mut dyn RngCore,
+        ) -> Hyperplane<f64, 2> {
+            self.n_splits.fetch_add(1, Ordering::Relaxed);
+            let widest = if context.max[0] - context.min[0] > context.max[1] - context.min[1] {
+                0
+            } else {
+                1
+            };
+            let mut normal = [0.0; 2];
+            normal[widest] = 1.0;
+            let mut intercept = [0.0; 2];
+            intercept[widest] = rng.gen_range(context.min[widest]..context.max[widest]);
+            Hyperplane { intercept, normal }
+        }
+    }
+
+    #[test]
+    fn custom_split_strategy() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 2.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| [rng.sample(distribution), rng.sample(distribution)])
+            .collect();
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 100,
+            max_threads: 2,
+            ..ForestOptions::default()
+        };
+        let strategy = WidestDimension::default();
+        let forest = Forest::from_slice_with_split(values.as_slice(), &options, &strategy).unwrap();
+
+        assert!(strategy.n_splits.load(Ordering::Relaxed) >= 20);
+        assert!(forest.score(&[10.0, 10.0]) > forest.score(&[1.5, 1.5]));
+    }
+
+    #[test]
+    fn sampled_intercept_on_skewed_data() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(0., 1.);
+        // most values are close to zero, a few are up to 1000 times larger
+        let values: Vec<_> = (0..1000)
+            .map(|_| {
+                let x: f64 = rng.sample(distribution);
+                [x.powi(10) * 1000.0, rng.sample(distribution)]
+            })
+            .collect();
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        let forest =
+            Forest::from_slice_with_split(values.as_slice(), &options, &SampledIntercept).unwrap();
+        assert!(forest.score(&[5000.0, 0.5]) > forest.score(&[0.01, 0.5]));
+        assert!(forest.score(&[0.01, 5.0]) > forest.score(&[0.01, 0.5]));
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::bundle::Fnv1a;
+
+/// Sort scores in ascending order. NaN values are sorted according to their sign bit, so
+/// NaN scores of this crate come last.
+pub(crate) fn sort_scores(scores: &mut [f64]) {
+    scores.sort_unstable_by(f64::total_cmp);
+}
+
+/// Quantile `q` (in `[0, 1]`) of already sorted values using linear interpolation
+/// between the closest ranks.
+///
+/// Returns `None` for empty input.
+pub(crate) fn quantile_sorted(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * fraction)
+}
+
+/// Ranks of the values starting at `1`, tied values get the average of their ranks.
+pub(crate) fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<_> = (0..values.len()).collect();
+    order.sort_unstable_by(|a, b| values[*a].total_cmp(&values[*b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end + 1) as f64 / 2.0;
+        order[start..end].iter().for_each(|idx| ranks[*idx] = rank);
+        start = end;
+    }
+    ranks
+}
+
+/// Pearson correlation of `a` and `b`, `None` if either of them is constant.
+pub(crate) fn pearson_correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let count = a.len() as f64;
+    let (mean_a, mean_b) = (a.iter().sum::<f64>() / count, b.iter().sum::<f64>() / count);
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+    if variance_a > 0.0 && variance_b > 0.0 {
+        Some(covariance / (variance_a * variance_b).sqrt())
+    } else {
+        None
+    }
+}
+
+/// Distribution of the scores of the training data, used to rescale scores.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct ScoreStatistics {
+    /// The percentiles `0..=100` of the scores.
+    percentiles: Vec<f64>,
+    mean: f64,
+    std_dev: f64,
+}
+
+impl ScoreStatistics {
+    /// `None` for an empty set of scores.
+    pub fn new(mut scores: Vec<f64>) -> Option<Self> {
+        sort_scores(&mut scores);
+        let percentiles = (0..=100)
+            .map(|p| quantile_sorted(&scores, f64::from(p) / 100.0))
+            .collect::<Option<Vec<_>>>()?;
+        let count = scores.len() as f64;
+        let mean = scores.iter().sum::<f64>() / count;
+        let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count;
+        Some(Self {
+            percentiles,
+            mean,
+            std_dev: variance.sqrt(),
+        })
+    }
+
+    /// Percentile (in `[0, 100]`) of `score` within the scores, interpolating linearly
+    /// between the stored percentiles.
+    pub fn percentile(&self, score: f64) -> f64 {
+        let upper = self.percentiles.partition_point(|p| *p <= score);
+        if upper == 0 {
+            0.0
+        } else if upper == self.percentiles.len() {
+            100.0
+        } else {
+            let (low, high) = (self.percentiles[upper - 1], self.percentiles[upper]);
+            (upper - 1) as f64 + (score - low) / (high - low)
+        }
+    }
+
+    pub fn z_score(&self, score: f64) -> f64 {
+        if self.std_dev > 0.0 {
+            (score - self.mean) / self.std_dev
+        } else {
+            0.0
+        }
+    }
+
+    /// Feed the statistics into the hash of [`crate::Forest::fingerprint`].
+    pub fn write_fingerprint(&self, hash: &mut Fnv1a) {
+        hash.write_f64s(&self.percentiles);
+        hash.write_f64(self.mean);
+        hash.write_f64(self.std_dev);
+    }
+
+    pub fn min(&self) -> f64 {
+        self.percentiles[0]
+    }
+
+    pub fn max(&self) -> f64 {
+        self.percentiles[100]
+    }
+}
+
+/// Streaming estimator of a single quantile using the P² algorithm of Jain and Chlamtac,
+/// which keeps five markers instead of the observed values.
+#[derive(Clone, Debug)]
+pub(crate) struct P2Quantile {
+    p: f64,
+    count: usize,
+    /// Marker heights.
+    heights: [f64; 5],
+    /// Actual marker positions.
+    positions: [f64; 5],
+    /// Desired marker positions.
+    desired: [f64; 5],
+    /// Increments of the desired marker positions.
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(p: f64) -> Self {
+        let p = p.clamp(0.0, 1.0);
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn p(&self) -> f64 {
+        self.p
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                sort_scores(&mut self.heights);
+            }
+            return;
+        }
+        self.count += 1;
+
+        // find the cell containing the value and extend the extreme markers if necessary
+        let k = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (0..4).find(|i| value < self.heights[i + 1]).unwrap_or(3)
+        };
+
+        self.positions
+            .iter_mut()
+            .skip(k + 1)
+            .for_each(|position| *position += 1.0);
+        self.desired
+            .iter_mut()
+            .zip(self.increments.iter())
+            .for_each(|(desired, increment)| *desired += increment);
+
+        // adjust the heights of the inner markers
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            if (d >= 1.0 && self.positions[i + 1] - self.positions[i] > 1.0)
+                || (d <= -1.0 && self.positions[i - 1] - self.positions[i] < -1.0)
+            {
+                let d = d.signum();
+                let parabolic = self.parabolic(i, d);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, d)
+                    };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    /// The current estimate, `None` before the first observation.
+    pub fn estimate(&self) -> Option<f64> {
+        if self.count >= 5 {
+            Some(self.heights[2])
+        } else {
+            let mut observed = self.heights[..self.count].to_vec();
+            sort_scores(&mut observed);
+            quantile_sorted(&observed, self.p)
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        let j = if d < 0.0 { i - 1 } else { i + 1 };
+        q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+    }
+}
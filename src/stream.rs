@@ -0,0 +1,108 @@
+//! Serialization of forests as a stream of JSON documents, one per tree.
+
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Deserializer;
+
+use crate::{Error, Forest};
+
+fn to_error(error: serde_json::Error) -> Error {
+    if error.is_io() {
+        Error::Io(error.into())
+    } else {
+        Error::InvalidModel
+    }
+}
+
+impl<T, const N: usize> Forest<T, N>
+where
+    T: Clone + Serialize,
+{
+    /// Write the forest to `writer` as a sequence of JSON documents: the number of trees,
+    /// the forest without its trees and then each tree on its own line.
+    ///
+    /// Unlike serializing the complete forest, this never holds more than a single tree in
+    /// the serialized form, which matters for very large forests.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let header = Self {
+            avg_path_length_c: self.avg_path_length_c,
+            sample_size: self.sample_size,
+            sample_size_policy: self.sample_size_policy,
+            pipeline: self.pipeline.clone(),
+            training_range: self.training_range.clone(),
+            score_scale: self.score_scale,
+            score_statistics: self.score_statistics.clone(),
+            imputation_values: self.imputation_values.clone(),
+            non_finite_handling: self.non_finite_handling,
+            profiler: None,
+            trees: Box::new([]),
+        };
+        serde_json::to_writer(&mut writer, &self.trees.len()).map_err(to_error)?;
+        writer.write_all(b"\n").map_err(Error::Io)?;
+        serde_json::to_writer(&mut writer, &header).map_err(to_error)?;
+        for tree in self.trees.iter() {
+            writer.write_all(b"\n").map_err(Error::Io)?;
+            serde_json::to_writer(&mut writer, tree).map_err(to_error)?;
+        }
+        writer.flush().map_err(Error::Io)
+    }
+}
+
+impl<T, const N: usize> Forest<T, N>
+where
+    T: DeserializeOwned + Default,
+{
+    /// Read a forest written by [`Forest::write_to`] from `reader`, parsing the trees one
+    /// after another.
+    ///
+    /// Fails with [`Error::InvalidModel`] when the stream is malformed or ends early.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut documents = Deserializer::from_reader(reader).into_iter::<serde_json::Value>();
+        let mut next = || {
+            documents
+                .next()
+                .ok_or(Error::InvalidModel)?
+                .map_err(to_error)
+        };
+
+        let n_trees: usize = serde_json::from_value(next()?).map_err(to_error)?;
+        let mut forest: Self = serde_json::from_value(next()?).map_err(to_error)?;
+        let trees = (0..n_trees)
+            .map(|_| serde_json::from_value(next()?).map_err(to_error))
+            .collect::<Result<Vec<_>, _>>()?;
+        forest.trees = trees.into_boxed_slice();
+        Ok(forest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datasets::uniform;
+    use crate::{Error, Forest, ForestOptions};
+
+    #[test]
+    fn stream_forest_3d_f64() {
+        let values: Vec<[f64; 3]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 20,
+            sample_size: 64,
+            record_leaf_bounds: true,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        let mut buffer = vec![];
+        forest.write_to(&mut buffer).unwrap();
+        assert_eq!(buffer.iter().filter(|b| **b == b'\n').count(), 21);
+        let forest2: Forest<f64, 3> = Forest::read_from(buffer.as_slice()).unwrap();
+        assert!(forest == forest2);
+
+        let truncated = &buffer[..buffer.len() / 2];
+        assert!(matches!(
+            Forest::<f64, 3>::read_from(truncated),
+            Err(Error::InvalidModel)
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+//! Export of a forest as flat arrays, to evaluate it with tensor libraries like `candle` or
+//! `burn` on the same device as other models.
+//!
+//! The nodes of all trees are stored in a single array. Every array can be loaded as a
+//! tensor directly, e.g. with `Tensor::from_vec` in candle or `TensorData::new` in burn.
+//! A batch of items is evaluated by starting each item at the root of every tree and then
+//! applying [`ForestTensors::max_depth`] times the same gather and select operations:
+//!
+//! ```text
+//! projection = sum(x * normals[node], features) - offsets[node]
+//! node = where(projection <= 0, left[node], right[node])
+//! ```
+//!
+//! Leaves are their own children, so items which have reached a leaf stay there. Finally
+//! the leaf values are gathered and summed per item, the raw score is
+//! `2^(-sum / n_trees)`. [`ForestTensors::score_batch`] implements these steps on the CPU.
+//!
+//! With the `candle` feature, [`ForestTensors::to_candle`] loads the arrays as `candle`
+//! tensors and [`CandleForest::score_batch`] runs these operations on their device. For
+//! other libraries loading the arrays and running the operations is up to the application.
+//! Items with infinite or NaN values need the special treatment of
+//! [`ForestTensors::non_finite_handling`], which is not part of the operations above.
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+#[cfg(feature = "candle")]
+mod candle;
+
+#[cfg(feature = "candle")]
+pub use self::candle::CandleForest;
+use crate::math;
+use crate::{
+    saturated_direction, Direction, Forest, ForestFloat, Node, NonFiniteHandling, TreeTransform,
+};
+
+/// The nodes of a forest as flat arrays, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForestTensors {
+    /// Number of features of the items.
+    pub n_features: usize,
+
+    /// Number of levels to evaluate until all items have reached a leaf.
+    pub max_depth: usize,
+
+    /// Index of the root node of each tree.
+    pub roots: Vec<u32>,
+
+    /// Normals of the hyperplanes, `n_features` values per node. Zero for leaves.
+    pub normals: Vec<f64>,
+
+    /// Projection of the intercept onto the normal of each node. Zero for leaves.
+    pub offsets: Vec<f64>,
+
+    /// Intercepts of the hyperplanes, `n_features` values per node. Zero for leaves. Only
+    /// needed to route items whose projection is NaN, see [`NonFiniteHandling::Saturate`].
+    pub intercepts: Vec<f64>,
+
+    /// Index of the left child of each node, items with a projection `<= 0` go left.
+    pub left: Vec<u32>,
+
+    /// Index of the right child of each node.
+    pub right: Vec<u32>,
+
+    /// Path length of each leaf, normalized by the average path length of its tree. Zero
+    /// for internal nodes.
+    pub leaf_values: Vec<f64>,
+
+    /// Scoring of items with infinite or NaN values, see
+    /// [`crate::ForestOptions::non_finite_handling`].
+    pub non_finite_handling: NonFiniteHandling,
+}
+
+impl ForestTensors {
+    /// Number of nodes of all trees.
+    pub fn n_nodes(&self) -> usize {
+        self.left.len()
+    }
+
+    /// Compute the raw anomaly scores of a batch of items stored row by row in `data`,
+    /// with `n_features` values per item.
+    ///
+    /// This is a reference implementation of the tensor operations described in the
+    /// [module documentation](self). The items have to be preprocessed already, see
+    /// [`Forest::pipeline`]. Items with infinite or NaN values are scored according to
+    /// [`ForestTensors::non_finite_handling`]. Saturated items are routed like by the
+    /// forest, except in trees with rotations, which are saturated in the space of the
+    /// items instead of the rotated space.
+    pub fn score_batch(&self, data: &[f64]) -> Vec<f64> {
+        let n_rows = data.len() / self.n_features.max(1);
+        let row = |row: usize| &data[row * self.n_features..(row + 1) * self.n_features];
+        let mut sums = vec![0.0; n_rows];
+        for root in self.roots.iter() {
+            let mut nodes = vec![*root as usize; n_rows];
+            for _ in 0..self.max_depth {
+                for (i, node) in nodes.iter_mut().enumerate() {
+                    let values = row(i);
+                    let range = *node * self.n_features..(*node + 1) * self.n_features;
+                    let normal = &self.normals[range.clone()];
+                    let projection = values.iter().zip(normal).map(|(v, n)| v * n).sum::<f64>()
+                        - self.offsets[*node];
+                    let direction = if projection <= 0.0 {
+                        Direction::Left
+                    } else if projection > 0.0 {
+                        Direction::Right
+                    } else {
+                        saturated_direction(values, normal, &self.intercepts[range])
+                    };
+                    *node = match direction {
+                        Direction::Left => self.left[*node],
+                        Direction::Right => self.right[*node],
+                    } as usize;
+                }
+            }
+            for (sum, node) in sums.iter_mut().zip(nodes) {
+                *sum += self.leaf_values[node];
+            }
+        }
+        let n_trees = self.roots.len().max(1) as f64;
+        let non_finite_score = self.non_finite_handling.score();
+        sums.into_iter()
+            .enumerate()
+            .map(|(i, sum)| match non_finite_score {
+                Some(score) if row(i).iter().any(|value| !value.is_finite()) => score,
+                _ => math::exp2(-sum / n_trees),
+            })
+            .collect()
+    }
+}
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+{
+    /// Export the trees as flat arrays for evaluation with tensor libraries.
+    ///
+    /// Rotations of the trees are folded into the normals. `None` when trees use a random
+    /// feature map, see [`crate::ForestOptions::feature_map`], as these can not be expressed
+    /// as hyperplanes of the items.
+    pub fn to_tensors(&self) -> Option<ForestTensors> {
+        let mut tensors = ForestTensors {
+            n_features: N,
+            max_depth: 0,
+            roots: Vec::with_capacity(self.trees.len()),
+            normals: vec![],
+            offsets: vec![],
+            intercepts: vec![],
+            left: vec![],
+            right: vec![],
+            leaf_values: vec![],
+            non_finite_handling: self.non_finite_handling,
+        };
+        for tree in self.trees.iter() {
+            let rotation = match &tree.transform {
+                Some(TreeTransform::FeatureMap { .. }) => return None,
+                Some(TreeTransform::Rotation(matrix)) => Some(matrix.as_slice()),
+                None => None,
+            };
+            tensors.roots.push(tensors.n_nodes() as u32);
+            let context = TreeContext {
+                rotation,
+                path_length_c: tree.path_length_c(),
+            };
+            push_node(&mut tensors, &tree.root, 0, &context);
+        }
+        Some(tensors)
+    }
+}
+
+struct TreeContext<'a> {
+    /// Row-major rotation matrix of the tree.
+    rotation: Option<&'a [f64]>,
+    path_length_c: f64,
+}
+
+/// Append `node` and its subtree in preorder, returning the index of `node`.
+fn push_node<T: Float, const N: usize>(
+    tensors: &mut ForestTensors,
+    node: &Node<T, N>,
+    depth: usize,
+    context: &TreeContext<'_>,
+) -> u32 {
+    let index = tensors.n_nodes() as u32;
+    tensors.left.push(index);
+    tensors.right.push(index);
+    tensors.max_depth = tensors.max_depth.max(depth);
+    match node {
+        Node::Ex(ex_node) => {
+            tensors.normals.extend(std::iter::repeat(0.0).take(N));
+            tensors.offsets.push(0.0);
+            tensors.intercepts.extend(std::iter::repeat(0.0).take(N));
+            tensors
+                .leaf_values
+                .push((depth as f64 + ex_node.path_length) / context.path_length_c);
+        }
+        Node::In(in_node) => {
+            let to_f64 = |v: T| v.to_f64().unwrap_or(f64::NAN);
+            // the projection of a rotated item `R x` is `(R^T n) . x - n . p`, which equals
+            // `(R^T n) . (x - R^T p)` for the orthogonal rotations
+            let rotate_back = |v: &[T; N]| -> Vec<f64> {
+                match context.rotation {
+                    Some(matrix) => (0..N)
+                        .map(|i| (0..N).map(|j| matrix[j * N + i] * to_f64(v[j])).sum())
+                        .collect(),
+                    None => v.iter().map(|v| to_f64(*v)).collect(),
+                }
+            };
+            tensors.normals.extend(rotate_back(&in_node.n));
+            tensors.intercepts.extend(rotate_back(&in_node.p));
+            tensors.offsets.push(
+                in_node
+                    .n
+                    .iter()
+                    .zip(in_node.p.iter())
+                    .map(|(n, p)| to_f64(*n) * to_f64(*p))
+                    .sum(),
+            );
+            tensors.leaf_values.push(0.0);
+
+            let left = push_node(tensors, &in_node.left, depth + 1, context);
+            let right = push_node(tensors, &in_node.right, depth + 1, context);
+            tensors.left[index as usize] = left;
+            tensors.right[index as usize] = right;
+        }
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datasets::uniform;
+    use crate::{Forest, ForestOptions, NonFiniteHandling};
+
+    #[test]
+    fn tensors_forest_3d_f64() {
+        let values: Vec<[f64; 3]> = uniform(1000);
+        for rotation_subset_size in [None, Some(2)] {
+            let options = ForestOptions {
+                n_trees: 20,
+                sample_size: 64,
+                rotation_subset_size,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+            let tensors = forest.to_tensors().unwrap();
+            assert_eq!(tensors.roots.len(), 20);
+            assert_eq!(tensors.normals.len(), 3 * tensors.n_nodes());
+
+            let data: Vec<_> = values[..100].iter().flatten().copied().collect();
+            let scores = tensors.score_batch(&data);
+            for (values, score) in values[..100].iter().zip(scores) {
+                assert!((forest.score_raw(values) - score).abs() < 1e-9);
+            }
+        }
+
+        let extreme = [
+            [f64::INFINITY, 5.0, 5.0],
+            [f64::NEG_INFINITY, f64::INFINITY, 5.0],
+            [f64::NAN, 5.0, 5.0],
+            [f64::MAX, -f64::MAX, f64::MAX],
+        ];
+        let data: Vec<_> = extreme.iter().flatten().copied().collect();
+        for non_finite_handling in [
+            NonFiniteHandling::Saturate,
+            NonFiniteHandling::MaxScore,
+            NonFiniteHandling::Reject,
+        ] {
+            let options = ForestOptions {
+                n_trees: 20,
+                sample_size: 64,
+                non_finite_handling,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+            let scores = forest.to_tensors().unwrap().score_batch(&data);
+            for (values, score) in extreme.iter().zip(scores) {
+                let expected = forest.score_raw(values);
+                assert!(
+                    (expected - score).abs() < 1e-9 || (expected.is_nan() && score.is_nan()),
+                    "{:?}: {} != {}",
+                    values,
+                    expected,
+                    score
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Evaluation of [`ForestTensors`] with `candle`, on the device of the other models.
+
+use candle_core::{DType, Device, Result, Tensor};
+
+use super::ForestTensors;
+
+/// The arrays of a [`ForestTensors`] loaded as `candle` tensors, see
+/// [`ForestTensors::to_candle`].
+#[derive(Clone, Debug)]
+pub struct CandleForest {
+    n_trees: usize,
+    max_depth: usize,
+
+    /// Largest finite value of the dtype, infinite values are clamped to it.
+    max_value: f64,
+
+    /// Raw score of items with infinite or NaN values, if they are not passed through the
+    /// trees.
+    non_finite_score: Option<f64>,
+
+    /// `(n_trees)`
+    roots: Tensor,
+
+    /// `(n_nodes, n_features)`
+    normals: Tensor,
+
+    /// `(n_nodes)`
+    offsets: Tensor,
+
+    /// `(n_nodes, n_features)`
+    intercepts: Tensor,
+
+    /// `(n_nodes)`
+    left: Tensor,
+
+    /// `(n_nodes)`
+    right: Tensor,
+
+    /// `(n_nodes)`
+    leaf_values: Tensor,
+}
+
+impl ForestTensors {
+    /// Load the arrays as tensors of `dtype` on `device`, which has to be [`DType::F32`] or
+    /// [`DType::F64`].
+    pub fn to_candle(&self, device: &Device, dtype: DType) -> Result<CandleForest> {
+        let max_value = match dtype {
+            DType::F32 => f32::MAX as f64,
+            DType::F64 => f64::MAX,
+            _ => {
+                return Err(candle_core::Error::UnsupportedDTypeForOp(
+                    dtype,
+                    "to_candle",
+                ))
+            }
+        };
+        let n_nodes = self.n_nodes();
+        let floats = |values: &[f64], shape: &[usize]| {
+            Tensor::from_slice(values, shape, device)?.to_dtype(dtype)
+        };
+        Ok(CandleForest {
+            n_trees: self.roots.len(),
+            max_depth: self.max_depth,
+            max_value,
+            non_finite_score: self.non_finite_handling.score(),
+            roots: Tensor::from_slice(&self.roots, self.roots.len(), device)?,
+            normals: floats(&self.normals, &[n_nodes, self.n_features])?,
+            offsets: floats(&self.offsets, &[n_nodes])?,
+            intercepts: floats(&self.intercepts, &[n_nodes, self.n_features])?,
+            left: Tensor::from_slice(&self.left, n_nodes, device)?,
+            right: Tensor::from_slice(&self.right, n_nodes, device)?,
+            leaf_values: floats(&self.leaf_values, &[n_nodes])?,
+        })
+    }
+}
+
+impl CandleForest {
+    /// Compute the raw anomaly scores of a batch of items of shape `(n_items, n_features)`,
+    /// with the dtype and on the device of the forest.
+    ///
+    /// All items are passed through all trees at once using the operations described in the
+    /// [module documentation](super), so the batch takes `n_items * n_trees * n_features`
+    /// values of memory. Items whose projection onto a hyperplane is NaN, because of
+    /// infinite or NaN values or an overflowing dot product, are routed like by
+    /// [`ForestTensors::score_batch`]. The items have to be preprocessed already, see
+    /// [`crate::Forest::pipeline`].
+    pub fn score_batch(&self, items: &Tensor) -> Result<Tensor> {
+        let (n_items, _) = items.dims2()?;
+        let device = items.device();
+        // each item is passed through each tree, the paths are ordered by item
+        let item_ids = Tensor::arange(0u32, n_items as u32, device)?
+            .reshape((n_items, 1))?
+            .broadcast_as((n_items, self.n_trees))?
+            .flatten_all()?
+            .contiguous()?;
+        let values = items.index_select(&item_ids, 0)?;
+        let mut nodes = self
+            .roots
+            .reshape((1, self.n_trees))?
+            .broadcast_as((n_items, self.n_trees))?
+            .flatten_all()?
+            .contiguous()?;
+
+        let zeros = values.zeros_like()?;
+        let is_nan = values.ne(&values)?;
+        // halved, so the differences to the intercepts do not overflow
+        let halved = (values.clamp(-self.max_value, self.max_value)? * 0.5)?;
+        for _ in 0..self.max_depth {
+            let normals = self.normals.index_select(&nodes, 0)?;
+            let projections = (&values * &normals)?
+                .sum(1)?
+                .sub(&self.offsets.index_select(&nodes, 0)?)?;
+            let go_left = projections.le(0.0)?;
+
+            // the rule of `saturated_direction` for NaN projections
+            let in_dimensions = normals.ne(0.0)?;
+            let nan_value = in_dimensions
+                .where_cond(&is_nan, &in_dimensions.zeros_like()?)?
+                .max(1)?;
+            let differences = (&halved - (self.intercepts.index_select(&nodes, 0)? * 0.5)?)?;
+            let differences = in_dimensions.where_cond(&differences, &zeros)?;
+            let scale = differences.abs()?.max_keepdim(1)?;
+            let saturated_left = scale.eq(0.0)?.squeeze(1)?.maximum(
+                &(differences.broadcast_div(&scale)? * &normals)?
+                    .sum(1)?
+                    .le(0.0)?,
+            )?;
+            let saturated_left = nan_value.where_cond(&nan_value.zeros_like()?, &saturated_left)?;
+            let go_left = projections
+                .ne(&projections)?
+                .where_cond(&saturated_left, &go_left)?;
+
+            nodes = go_left.where_cond(
+                &self.left.index_select(&nodes, 0)?,
+                &self.right.index_select(&nodes, 0)?,
+            )?;
+        }
+
+        let path_lengths = self
+            .leaf_values
+            .index_select(&nodes, 0)?
+            .reshape((n_items, self.n_trees))?
+            .sum(1)?;
+        // 2^(-path_length / n_trees)
+        let scores = path_lengths
+            .affine(-std::f64::consts::LN_2 / self.n_trees.max(1) as f64, 0.0)?
+            .exp()?;
+        match self.non_finite_score {
+            Some(score) => {
+                let non_finite = items.abs()?.le(self.max_value)?.min(1)?.eq(0u8)?;
+                non_finite.where_cond(&scores.affine(0.0, score)?, &scores)
+            }
+            None => Ok(scores),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use candle_core::{DType, Device, Tensor};
+
+    use crate::datasets::uniform;
+    use crate::{Forest, ForestOptions, NonFiniteHandling};
+
+    #[test]
+    fn candle_forest_3d_f64() {
+        let mut values: Vec<[f64; 3]> = uniform(200);
+        values.extend([
+            [f64::INFINITY, 5.0, 5.0],
+            [f64::NEG_INFINITY, f64::INFINITY, 5.0],
+            [f64::NAN, 5.0, 5.0],
+            [f64::MAX, -f64::MAX, f64::MAX],
+        ]);
+        let data: Vec<_> = values.iter().flatten().copied().collect();
+        let items = Tensor::from_slice(&data, (values.len(), 3), &Device::Cpu).unwrap();
+
+        for (rotation_subset_size, non_finite_handling) in [
+            (None, NonFiniteHandling::Saturate),
+            (Some(2), NonFiniteHandling::Saturate),
+            (None, NonFiniteHandling::MaxScore),
+            (None, NonFiniteHandling::Reject),
+        ] {
+            let options = ForestOptions {
+                n_trees: 20,
+                sample_size: 64,
+                rotation_subset_size,
+                non_finite_handling,
+                ..ForestOptions::default()
+            };
+            let forest = Forest::from_slice(&values[..200], &options).unwrap();
+            let tensors = forest.to_tensors().unwrap();
+            let expected = tensors.score_batch(&data);
+            let scores = tensors
+                .to_candle(&Device::Cpu, DType::F64)
+                .unwrap()
+                .score_batch(&items)
+                .unwrap()
+                .to_vec1::<f64>()
+                .unwrap();
+            for (expected, score) in expected.into_iter().zip(scores) {
+                assert!(
+                    (expected - score).abs() < 1e-9 || (expected.is_nan() && score.is_nan()),
+                    "{} != {}",
+                    expected,
+                    score
+                );
+            }
+        }
+        assert!(
+            Forest::from_slice(&values[..200], &ForestOptions::default())
+                .unwrap()
+                .to_tensors()
+                .unwrap()
+                .to_candle(&Device::Cpu, DType::U8)
+                .is_err()
+        );
+    }
+}
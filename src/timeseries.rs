@@ -0,0 +1,179 @@
+//! Embedding of univariate time series into windows which can be used with a
+//! `Forest<T, W>`.
+
+use num_traits::Float;
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{Forest, ForestFloat};
+
+/// Turns a univariate series into overlapping windows of `W` consecutive values.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WindowEmbedder<const W: usize> {
+    step: usize,
+    differencing: bool,
+    normalize: bool,
+}
+
+impl<const W: usize> Default for WindowEmbedder<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: usize> WindowEmbedder<W> {
+    /// Create an embedder producing a window for every position of the series.
+    pub fn new() -> Self {
+        Self {
+            step: 1,
+            differencing: false,
+            normalize: false,
+        }
+    }
+
+    /// Only produce a window every `step` positions.
+    pub fn with_step(mut self, step: usize) -> Self {
+        self.step = step.max(1);
+        self
+    }
+
+    /// Embed the differences between consecutive values instead of the values themselves.
+    pub fn with_differencing(mut self) -> Self {
+        self.differencing = true;
+        self
+    }
+
+    /// Normalize each window to zero mean and unit standard deviation, so windows are
+    /// compared by their shape instead of their level.
+    pub fn with_normalization(mut self) -> Self {
+        self.normalize = true;
+        self
+    }
+
+    /// Embed the series into windows.
+    pub fn embed<T: Float>(&self, series: &[T]) -> Vec<[T; W]> {
+        let differences: Vec<_>;
+        let values = if self.differencing {
+            differences = series.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            differences.as_slice()
+        } else {
+            series
+        };
+        if W == 0 {
+            return vec![];
+        }
+
+        values
+            .windows(W)
+            .step_by(self.step)
+            .map(|window| {
+                let mut embedded = [T::zero(); W];
+                embedded.copy_from_slice(window);
+                if self.normalize {
+                    normalize(&mut embedded);
+                }
+                embedded
+            })
+            .collect()
+    }
+
+    /// Index of the last value of the series covered by the window at `window_idx`.
+    pub fn end_index(&self, window_idx: usize) -> usize {
+        window_idx * self.step + W.saturating_sub(1) + usize::from(self.differencing)
+    }
+}
+
+fn normalize<T: Float, const W: usize>(window: &mut [T; W]) {
+    let len = T::from(W).unwrap_or_else(T::one);
+    let mean = window.iter().fold(T::zero(), |sum, v| sum + *v) / len;
+    let variance = window
+        .iter()
+        .fold(T::zero(), |sum, v| sum + (*v - mean) * (*v - mean))
+        / len;
+    let std_dev = variance.sqrt();
+    for v in window.iter_mut() {
+        *v = if std_dev > T::zero() {
+            (*v - mean) / std_dev
+        } else {
+            *v - mean
+        };
+    }
+}
+
+/// Score a time series using windows embedded by `embedder`.
+///
+/// Each score is assigned to the timestamp of the last value of its window.
+pub fn score_series<'de, T, S, const W: usize>(
+    forest: &Forest<T, W>,
+    embedder: &WindowEmbedder<W>,
+    timestamps: &[S],
+    values: &[T],
+) -> Vec<(S, f64)>
+where
+    T: ForestFloat<'de> + SampleUniform + Default,
+    StandardNormal: Distribution<T>,
+    S: Clone,
+{
+    embedder
+        .embed(values)
+        .iter()
+        .enumerate()
+        .filter_map(|(window_idx, window)| {
+            timestamps
+                .get(embedder.end_index(window_idx))
+                .map(|timestamp| (timestamp.clone(), forest.score(window)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{score_series, WindowEmbedder};
+    use crate::{Forest, ForestOptions};
+
+    #[test]
+    fn embed_windows() {
+        let series = [1.0, 2.0, 4.0, 7.0, 11.0];
+        let embedder = WindowEmbedder::<3>::new();
+        assert_eq!(
+            embedder.embed(&series),
+            vec![[1.0, 2.0, 4.0], [2.0, 4.0, 7.0], [4.0, 7.0, 11.0]]
+        );
+        assert_eq!(embedder.end_index(0), 2);
+
+        let embedder = WindowEmbedder::<3>::new().with_differencing().with_step(2);
+        assert_eq!(embedder.embed(&series), vec![[1.0, 2.0, 3.0]]);
+        assert_eq!(embedder.end_index(0), 3);
+
+        let embedder = WindowEmbedder::<2>::new().with_normalization();
+        assert_eq!(
+            embedder.embed(&[3.0, 5.0, 5.0]),
+            vec![[-1.0, 1.0], [0.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn score_sine_series() {
+        let mut series: Vec<_> = (0..2000).map(|i| (f64::from(i) / 10.0).sin()).collect();
+        series[1500] = 3.0;
+        let timestamps: Vec<_> = (0..series.len()).collect();
+
+        let embedder = WindowEmbedder::<4>::new();
+        let windows = embedder.embed(&series[..1000]);
+        let options = ForestOptions {
+            n_trees: 100,
+            sample_size: 256,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(windows.as_slice(), &options).unwrap();
+
+        let scores = score_series(&forest, &embedder, &timestamps, &series);
+        assert_eq!(scores.len(), series.len() - 3);
+        assert_eq!(scores[0].0, 3);
+        let (max_timestamp, _) = scores
+            .iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+        assert!((1500..1504).contains(max_timestamp));
+    }
+}
@@ -0,0 +1,58 @@
+//! Support for training and scoring with `nalgebra` vectors.
+
+use nalgebra::{SVector, Scalar};
+use rand::distributions::uniform::SampleUniform;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{Error, Forest, ForestFloat, ForestOptions};
+
+impl<'de, T, const N: usize> Forest<T, N>
+where
+    T: ForestFloat<'de> + SampleUniform + Default + Scalar,
+    StandardNormal: Distribution<T>,
+{
+    /// Build a new forest from training data given as `nalgebra` vectors.
+    pub fn from_vectors(
+        training_data: &[SVector<T, N>],
+        options: &ForestOptions,
+    ) -> Result<Self, Error>
+    where
+        T: Send + Sync,
+    {
+        let training_data: Vec<[T; N]> = training_data.iter().map(|v| (*v).into()).collect();
+        Self::from_slice(training_data.as_slice(), options)
+    }
+
+    /// compute anomaly score for an item given as `nalgebra` vector
+    pub fn score_vector(&self, values: &SVector<T, N>) -> f64 {
+        self.score(&(*values).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    use crate::{Forest, ForestOptions};
+
+    #[test]
+    fn score_nalgebra_vectors() {
+        let rng = &mut rand::thread_rng();
+        let distribution = Uniform::new(1., 2.);
+        let values: Vec<_> = (0..1000)
+            .map(|_| Vector2::new(rng.sample(distribution), rng.sample(distribution)))
+            .collect();
+        let options = ForestOptions {
+            n_trees: 50,
+            sample_size: 100,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_vectors(values.as_slice(), &options).unwrap();
+
+        let anomaly = Vector2::new(10.0, 10.0);
+        assert_eq!(forest.score_vector(&anomaly), forest.score(&[10.0, 10.0]));
+        assert!(forest.score_vector(&anomaly) > forest.score_vector(&Vector2::new(1.5, 1.5)));
+    }
+}
@@ -0,0 +1,274 @@
+//! Read-only access to the structure of the trees of a forest, e.g. to compute custom
+//! statistics or to export the trees to other formats.
+
+use std::fmt::Write as _;
+use std::io::Write;
+
+use num_traits::Float;
+
+use crate::{Error, Forest, LeafBounds, Node};
+
+/// A node of a tree, see [`Forest::nodes`].
+///
+/// The normals, intercepts and bounds refer to the items after the preprocessing of the
+/// forest and the transformation of the tree, if any, have been applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeInfo<'a, T, const N: usize> {
+    /// Index of the tree.
+    pub tree: usize,
+
+    /// Depth of the node, `0` for the root.
+    pub depth: usize,
+
+    /// Number of training samples present at the node.
+    pub num_samples: usize,
+
+    pub kind: NodeKind<'a, T, N>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeKind<'a, T, const N: usize> {
+    /// An internal node splitting its samples by a hyperplane. Its left subtree directly
+    /// follows it, the right subtree follows after the left one.
+    Internal {
+        /// Normal vector of the hyperplane.
+        normal: &'a [T; N],
+
+        /// Point through which the hyperplane passes.
+        intercept: &'a [T; N],
+    },
+
+    /// An external node.
+    Leaf {
+        /// Index of the leaf within its tree, see [`Forest::leaf_indices`].
+        id: u32,
+
+        /// Bounding box of the samples of the leaf, see
+        /// [`crate::ForestOptions::record_leaf_bounds`].
+        bounds: Option<&'a LeafBounds<T, N>>,
+    },
+}
+
+/// Iterator over the nodes of the trees of a forest in preorder, see [`Forest::nodes`].
+pub struct Nodes<'a, T, const N: usize> {
+    forest: &'a Forest<T, N>,
+    next_tree: usize,
+    /// Nodes still to be visited together with their depth, the next one at the end.
+    stack: Vec<(&'a Node<T, N>, usize)>,
+}
+
+impl<'a, T, const N: usize> Iterator for Nodes<'a, T, N> {
+    type Item = NodeInfo<'a, T, N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stack.is_empty() {
+            let tree = self.forest.trees.get(self.next_tree)?;
+            self.stack.push((&tree.root, 0));
+            self.next_tree += 1;
+        }
+        let (node, depth) = self.stack.pop()?;
+        let tree = self.next_tree - 1;
+        Some(match node {
+            Node::Ex(ex_node) => NodeInfo {
+                tree,
+                depth,
+                num_samples: ex_node.num_samples,
+                kind: NodeKind::Leaf {
+                    id: ex_node.id,
+                    bounds: ex_node.bounds.as_deref(),
+                },
+            },
+            Node::In(in_node) => {
+                self.stack.push((&in_node.right, depth + 1));
+                self.stack.push((&in_node.left, depth + 1));
+                NodeInfo {
+                    tree,
+                    depth,
+                    num_samples: in_node.num_samples,
+                    kind: NodeKind::Internal {
+                        normal: &in_node.n,
+                        intercept: &in_node.p,
+                    },
+                }
+            }
+        })
+    }
+}
+
+impl<T, const N: usize> Forest<T, N> {
+    /// Number of trees of the forest.
+    pub fn n_trees(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// Iterate over the nodes of all trees, tree by tree and each tree in preorder.
+    pub fn nodes(&self) -> Nodes<'_, T, N> {
+        Nodes {
+            forest: self,
+            next_tree: 0,
+            stack: vec![],
+        }
+    }
+}
+
+impl<T: Float, const N: usize> Forest<T, N> {
+    /// Write the tree with index `tree` as nested JSON for display, e.g. by the
+    /// hierarchy layouts of d3.
+    ///
+    /// Internal nodes are written as `{"children": [left, right], "n": [...], "p": [...],
+    /// "num_samples": ...}` with the normal vector `n` and intercept point `p` of the
+    /// hyperplane, leaves as `{"num_samples": ...}`. Like the hyperplanes of
+    /// [`Forest::nodes`], `n` and `p` live in the space the tree splits, i.e. after
+    /// [`crate::ForestOptions::preprocessing`] and the rotation or feature map of the tree,
+    /// not in the space of the items. Non-finite values are written as `null`. Unlike the
+    /// serde representation of the forest, the output can not be read back into a forest.
+    ///
+    /// Fails with [`Error::TreeIndexOutOfRange`] if `tree` is not less than
+    /// [`Forest::n_trees`].
+    pub fn write_tree_json<W: Write>(&self, tree: usize, mut writer: W) -> Result<(), Error> {
+        let tree = self.trees.get(tree).ok_or(Error::TreeIndexOutOfRange {
+            index: tree,
+            n_trees: self.trees.len(),
+        })?;
+        let mut json = String::new();
+        write_node_json(&tree.root, &mut json);
+        writer.write_all(json.as_bytes()).map_err(Error::Io)
+    }
+
+    /// Write all trees as a JSON array of the nested trees of
+    /// [`Forest::write_tree_json`].
+    pub fn write_trees_json<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut json = String::from("[");
+        for (i, tree) in self.trees.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_node_json(&tree.root, &mut json);
+        }
+        json.push(']');
+        writer.write_all(json.as_bytes()).map_err(Error::Io)
+    }
+}
+
+fn write_node_json<T: Float, const N: usize>(node: &Node<T, N>, json: &mut String) {
+    match node {
+        Node::Ex(ex_node) => {
+            write!(json, r#"{{"num_samples":{}}}"#, ex_node.num_samples).unwrap();
+        }
+        Node::In(in_node) => {
+            json.push_str(r#"{"children":["#);
+            write_node_json(&in_node.left, json);
+            json.push(',');
+            write_node_json(&in_node.right, json);
+            json.push_str(r#"],"n":"#);
+            write_values_json(&in_node.n, json);
+            json.push_str(r#","p":"#);
+            write_values_json(&in_node.p, json);
+            write!(json, r#","num_samples":{}}}"#, in_node.num_samples).unwrap();
+        }
+    }
+}
+
+fn write_values_json<T: Float, const N: usize>(values: &[T; N], json: &mut String) {
+    json.push('[');
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        match value.to_f64().filter(|value| value.is_finite()) {
+            // `Debug` keeps the exponent of very large and small values
+            Some(value) => write!(json, "{:?}", value).unwrap(),
+            None => json.push_str("null"),
+        }
+    }
+    json.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodeKind;
+    use crate::datasets::uniform;
+    use crate::{Error, Forest, ForestOptions};
+
+    #[test]
+    fn visit_nodes() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 10,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+        assert_eq!(forest.n_trees(), 10);
+
+        let nodes: Vec<_> = forest.nodes().collect();
+        let n_leaves = nodes
+            .iter()
+            .filter(|node| matches!(node.kind, NodeKind::Leaf { .. }))
+            .count();
+        assert_eq!(n_leaves as u32, forest.leaf_counts().iter().sum::<u32>());
+        assert_eq!(nodes.len(), 2 * n_leaves - 10);
+
+        for tree in 0..10 {
+            let mut tree_nodes = nodes.iter().filter(|node| node.tree == tree);
+            let root = tree_nodes.next().unwrap();
+            assert_eq!((root.depth, root.num_samples), (0, 64));
+            // the leaves are numbered in preorder
+            let ids: Vec<_> = tree_nodes
+                .filter_map(|node| match node.kind {
+                    NodeKind::Leaf { id, .. } => Some(id),
+                    NodeKind::Internal { .. } => None,
+                })
+                .collect();
+            assert!(ids.iter().enumerate().all(|(i, id)| i as u32 == *id));
+        }
+    }
+
+    #[test]
+    fn nested_json() {
+        let values: Vec<[f64; 2]> = uniform(1000);
+        let options = ForestOptions {
+            n_trees: 3,
+            sample_size: 64,
+            ..ForestOptions::default()
+        };
+        let forest = Forest::from_slice(values.as_slice(), &options).unwrap();
+
+        let mut bytes = vec![];
+        forest.write_tree_json(1, &mut bytes).unwrap();
+        assert!(matches!(
+            forest.write_tree_json(3, vec![]),
+            Err(Error::TreeIndexOutOfRange {
+                index: 3,
+                n_trees: 3
+            })
+        ));
+        let root: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(root["num_samples"], 64);
+        assert_eq!(root["children"].as_array().unwrap().len(), 2);
+        assert_eq!(root["n"].as_array().unwrap().len(), 2);
+        assert_eq!(root["p"].as_array().unwrap().len(), 2);
+
+        // preorder of the nested nodes equals that of the visitor
+        fn preorder(node: &serde_json::Value, samples: &mut Vec<u64>) {
+            samples.push(node["num_samples"].as_u64().unwrap());
+            for child in node["children"].as_array().into_iter().flatten() {
+                preorder(child, samples);
+            }
+        }
+        let mut samples = vec![];
+        preorder(&root, &mut samples);
+        let expected: Vec<_> = forest
+            .nodes()
+            .filter(|node| node.tree == 1)
+            .map(|node| node.num_samples as u64)
+            .collect();
+        assert_eq!(samples, expected);
+
+        let mut bytes = vec![];
+        forest.write_trees_json(&mut bytes).unwrap();
+        let trees: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(trees.as_array().unwrap().len(), 3);
+        assert_eq!(trees[1], root);
+    }
+}